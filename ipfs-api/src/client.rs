@@ -16,6 +16,17 @@ pub trait Client {
         req: req::add::AddRequest,
     ) -> impl Future<Output = Result<AddResponse, Error>> + Send;
 
+    /**
+     * Add a file, calling `on_progress` with the bytes the node has processed so far.
+     */
+    fn add_with_progress<P>(
+        &self,
+        req: req::add::AddRequest,
+        on_progress: P,
+    ) -> impl Future<Output = Result<AddResponse, Error>> + Send
+    where
+        P: FnMut(i64) + Send;
+
     /**
      * Make directories.
      */
@@ -45,6 +56,94 @@ pub trait Client {
         req: req::files::StatRequest,
     ) -> impl Future<Output = Result<resp::files::StatResponse, Error>> + Send;
 
+    /**
+     * List directories in the local mutable namespace.
+     */
+    fn files_ls(
+        &self,
+        req: req::files::LsRequest,
+    ) -> impl Future<Output = Result<resp::files::LsResponse, Error>> + Send;
+
+    /**
+     * Read a file from MFS.
+     */
+    fn files_read(
+        &self,
+        req: req::files::ReadRequest,
+    ) -> impl Future<Output = Result<resp::files::ReadResponse, Error>> + Send;
+
+    /**
+     * Remove a file or directory from MFS.
+     */
+    fn files_rm(
+        &self,
+        req: req::files::RmRequest,
+    ) -> impl Future<Output = Result<EmptyResponse, Error>> + Send;
+
+    /**
+     * Move files or directories within MFS.
+     */
+    fn files_mv(
+        &self,
+        req: req::files::MvRequest,
+    ) -> impl Future<Output = Result<EmptyResponse, Error>> + Send;
+
+    /**
+     * Add references to IPFS files and directories in MFS (or copy within MFS).
+     */
+    fn files_cp(
+        &self,
+        req: req::files::CpRequest,
+    ) -> impl Future<Output = Result<EmptyResponse, Error>> + Send;
+
+    /**
+     * Change the CID version or hash function of the root node of a given path.
+     */
+    fn files_chcid(
+        &self,
+        req: req::files::ChcidRequest,
+    ) -> impl Future<Output = Result<EmptyResponse, Error>> + Send;
+
+    /**
+     * Pin objects to local storage.
+     */
+    fn pin_add(
+        &self,
+        req: req::pin::PinAddRequest,
+    ) -> impl Future<Output = Result<resp::pin::PinsResponse, Error>> + Send;
+
+    /**
+     * Remove object from pin-list.
+     */
+    fn pin_rm(
+        &self,
+        req: req::pin::PinRmRequest,
+    ) -> impl Future<Output = Result<resp::pin::PinsResponse, Error>> + Send;
+
+    /**
+     * List objects pinned to local storage.
+     */
+    fn pin_ls(
+        &self,
+        req: req::pin::PinLsRequest,
+    ) -> impl Future<Output = Result<resp::pin::PinLsResponse, Error>> + Send;
+
+    /**
+     * Verify that recursive pins are complete.
+     */
+    fn pin_verify(
+        &self,
+        req: req::pin::PinVerifyRequest,
+    ) -> impl Future<Output = Result<resp::pin::PinVerifyResponse, Error>> + Send;
+
+    /**
+     * Update a recursive pin.
+     */
+    fn pin_update(
+        &self,
+        req: req::pin::PinUpdateRequest,
+    ) -> impl Future<Output = Result<resp::pin::PinsResponse, Error>> + Send;
+
     /**
      * Add a DAG node to IPFS.
      */
@@ -59,8 +158,7 @@ pub struct LocalIPFSClient {
 }
 
 impl LocalIPFSClient {
-    #[allow(dead_code)]
-    fn new(endpoint: String) -> Self {
+    pub fn new(endpoint: String) -> Self {
         LocalIPFSClient {
             endpoint: Url::parse(&endpoint).unwrap(),
         }
@@ -83,6 +181,21 @@ impl Client for LocalIPFSClient {
         AddResponse::parse(response).await
     }
 
+    async fn add_with_progress<P>(
+        &self,
+        mut req: req::add::AddRequest,
+        on_progress: P,
+    ) -> Result<AddResponse, Error>
+    where
+        P: FnMut(i64) + Send,
+    {
+        req.query.progress = Some(true);
+        let url = RequestUrl::new(&self.endpoint, "add", &req.query).url()?;
+        let form = req.form()?;
+        let response = post_with_form(url, form).await?;
+        AddResponse::parse_with_progress(response, on_progress).await
+    }
+
     async fn files_mkdir(&self, req: req::files::MkdirRequest) -> Result<EmptyResponse, Error> {
         let url = RequestUrl::new(&self.endpoint, "files/mkdir", &req.query).url()?;
         let response = post(url).await?;
@@ -93,7 +206,7 @@ impl Client for LocalIPFSClient {
         &self,
         req: req::files::FlushRequest,
     ) -> Result<resp::files::FlushResponse, Error> {
-        let url: Url = RequestUrl::new(&self.endpoint, "files/flush", req.query).url()?;
+        let url: Url = RequestUrl::new(&self.endpoint, "files/flush", &req.query).url()?;
         let response = post(url).await?;
         resp::files::FlushResponse::parse(response).await
     }
@@ -124,4 +237,436 @@ impl Client for LocalIPFSClient {
         log::info!("files_stat response: {:?}", response);
         resp::files::StatResponse::parse(response).await
     }
+
+    async fn files_ls(&self, req: req::files::LsRequest) -> Result<resp::files::LsResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/ls", &req.query).url()?;
+        let response = post(url).await?;
+        resp::files::LsResponse::parse(response).await
+    }
+
+    async fn files_read(
+        &self,
+        req: req::files::ReadRequest,
+    ) -> Result<resp::files::ReadResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/read", &req.query).url()?;
+        let response = post(url).await?;
+        resp::files::ReadResponse::parse(response).await
+    }
+
+    async fn files_rm(&self, req: req::files::RmRequest) -> Result<EmptyResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/rm", &req.query).url()?;
+        let response = post(url).await?;
+        EmptyResponse::parse(response).await
+    }
+
+    async fn files_mv(&self, req: req::files::MvRequest) -> Result<EmptyResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/mv", &req.query).url()?;
+        let response = post(url).await?;
+        EmptyResponse::parse(response).await
+    }
+
+    async fn files_cp(&self, req: req::files::CpRequest) -> Result<EmptyResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/cp", &req.query).url()?;
+        let response = post(url).await?;
+        EmptyResponse::parse(response).await
+    }
+
+    async fn files_chcid(&self, req: req::files::ChcidRequest) -> Result<EmptyResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "files/chcid", &req.query).url()?;
+        let response = post(url).await?;
+        EmptyResponse::parse(response).await
+    }
+
+    async fn pin_add(
+        &self,
+        req: req::pin::PinAddRequest,
+    ) -> Result<resp::pin::PinsResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "pin/add", &req.query).url()?;
+        let response = post(url).await?;
+        resp::pin::PinsResponse::parse(response).await
+    }
+
+    async fn pin_rm(&self, req: req::pin::PinRmRequest) -> Result<resp::pin::PinsResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "pin/rm", &req.query).url()?;
+        let response = post(url).await?;
+        resp::pin::PinsResponse::parse(response).await
+    }
+
+    async fn pin_ls(&self, req: req::pin::PinLsRequest) -> Result<resp::pin::PinLsResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "pin/ls", &req.query).url()?;
+        let response = post(url).await?;
+        resp::pin::PinLsResponse::parse(response).await
+    }
+
+    async fn pin_verify(
+        &self,
+        req: req::pin::PinVerifyRequest,
+    ) -> Result<resp::pin::PinVerifyResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "pin/verify", &req.query).url()?;
+        let response = post(url).await?;
+        resp::pin::PinVerifyResponse::parse(response).await
+    }
+
+    async fn pin_update(
+        &self,
+        req: req::pin::PinUpdateRequest,
+    ) -> Result<resp::pin::PinsResponse, Error> {
+        let url: Url = RequestUrl::new(&self.endpoint, "pin/update", &req.query).url()?;
+        let response = post(url).await?;
+        resp::pin::PinsResponse::parse(response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Server};
+
+    use super::*;
+    use crate::req::add::{AddQuery, AddRequest};
+    use crate::req::files::{
+        ChcidQuery, ChcidRequest, CpQuery, CpRequest, LsQuery, LsRequest, MvQuery, MvRequest,
+        ReadQuery, ReadRequest, RmQuery, RmRequest,
+    };
+    use crate::req::pin::{
+        PinAddQuery, PinAddRequest, PinLsQuery, PinLsRequest, PinRmQuery, PinRmRequest, PinType,
+        PinUpdateQuery, PinUpdateRequest, PinVerifyQuery, PinVerifyRequest,
+    };
+
+    fn client(server: &Server) -> LocalIPFSClient {
+        LocalIPFSClient::new(format!("{}/api/v0", server.url()))
+    }
+
+    #[tokio::test]
+    async fn files_ls_lists_entries() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/files/ls")
+            .match_query(Matcher::Exact("arg=%2Fdemo&long=true".to_string()))
+            .with_body(r#"{"Entries":[{"Name":"1.json","Type":0,"Size":42,"Hash":"QmFile"}]}"#)
+            .create_async()
+            .await;
+        let response = client(&server)
+            .files_ls(LsRequest {
+                query: LsQuery {
+                    arg: Some("/demo".to_string()),
+                    long: Some(true),
+                    u: None,
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.entries.len(), 1);
+        assert_eq!(response.entries[0].name, "1.json");
+        assert_eq!(response.entries[0].hash, "QmFile");
+        assert_eq!(response.entries[0].size, 42);
+    }
+
+    #[tokio::test]
+    async fn files_ls_accepts_an_empty_directory() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/files/ls")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"Entries":null}"#)
+            .create_async()
+            .await;
+        let response = client(&server)
+            .files_ls(LsRequest {
+                query: LsQuery::default(),
+            })
+            .await
+            .unwrap();
+        assert!(response.entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn files_read_returns_the_raw_content() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/files/read")
+            .match_query(Matcher::Exact("arg=%2Fdemo%2F1.json&count=2".to_string()))
+            .with_body("{}")
+            .create_async()
+            .await;
+        let response = client(&server)
+            .files_read(ReadRequest {
+                query: ReadQuery {
+                    arg: "/demo/1.json".to_string(),
+                    offset: None,
+                    count: Some(2),
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.bytes, b"{}");
+    }
+
+    #[tokio::test]
+    async fn files_rm_sends_its_flags() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/files/rm")
+            .match_query(Matcher::Exact("arg=%2Fdemo&recursive=true".to_string()))
+            .create_async()
+            .await;
+        client(&server)
+            .files_rm(RmRequest {
+                query: RmQuery {
+                    arg: "/demo".to_string(),
+                    recursive: Some(true),
+                    force: None,
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn files_mv_and_cp_repeat_arg() {
+        let mut server = Server::new_async().await;
+        let mv = server
+            .mock("POST", "/api/v0/files/mv")
+            .match_query(Matcher::Exact("arg=%2Fa&arg=%2Fb".to_string()))
+            .create_async()
+            .await;
+        let cp = server
+            .mock("POST", "/api/v0/files/cp")
+            .match_query(Matcher::Exact(
+                "arg=%2Fipfs%2FQmFile&arg=%2Fdemo%2F1.json&parents=true".to_string(),
+            ))
+            .create_async()
+            .await;
+        let client = client(&server);
+        client
+            .files_mv(MvRequest {
+                query: MvQuery {
+                    source: "/a".to_string(),
+                    dest: "/b".to_string(),
+                },
+            })
+            .await
+            .unwrap();
+        client
+            .files_cp(CpRequest {
+                query: CpQuery {
+                    source: "/ipfs/QmFile".to_string(),
+                    dest: "/demo/1.json".to_string(),
+                    parents: Some(true),
+                },
+            })
+            .await
+            .unwrap();
+        mv.assert_async().await;
+        cp.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn files_chcid_sets_the_cid_version() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/files/chcid")
+            .match_query(Matcher::Exact("arg=%2Fdemo&cid-version=1".to_string()))
+            .create_async()
+            .await;
+        client(&server)
+            .files_chcid(ChcidRequest {
+                query: ChcidQuery::new_with_arg(&"demo".to_string(), 1),
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_errors() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/files/rm")
+            .match_query(Matcher::Any)
+            .with_status(500)
+            .with_body(r#"{"Message":"file does not exist","Code":0,"Type":"error"}"#)
+            .create_async()
+            .await;
+        let result = client(&server)
+            .files_rm(RmRequest {
+                query: RmQuery {
+                    arg: "/missing".to_string(),
+                    ..Default::default()
+                },
+            })
+            .await;
+        assert!(matches!(result, Err(Error::RequestFailed)));
+    }
+
+    #[tokio::test]
+    async fn pin_add_and_rm_report_the_pins() {
+        let mut server = Server::new_async().await;
+        let add = server
+            .mock("POST", "/api/v0/pin/add")
+            .match_query(Matcher::Exact("arg=QmDir&recursive=true".to_string()))
+            .with_body(r#"{"Pins":["QmDir"]}"#)
+            .create_async()
+            .await;
+        let rm = server
+            .mock("POST", "/api/v0/pin/rm")
+            .match_query(Matcher::Exact("arg=QmDir".to_string()))
+            .with_body(r#"{"Pins":["QmDir"]}"#)
+            .create_async()
+            .await;
+        let client = client(&server);
+        let added = client
+            .pin_add(PinAddRequest {
+                query: PinAddQuery::new_with_arg(&"QmDir".to_string()),
+            })
+            .await
+            .unwrap();
+        let removed = client
+            .pin_rm(PinRmRequest {
+                query: PinRmQuery {
+                    arg: "QmDir".to_string(),
+                    recursive: None,
+                },
+            })
+            .await
+            .unwrap();
+        add.assert_async().await;
+        rm.assert_async().await;
+        assert_eq!(added.pins, vec!["QmDir".to_string()]);
+        assert_eq!(removed.pins, vec!["QmDir".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn pin_ls_returns_keys_by_cid() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/pin/ls")
+            .match_query(Matcher::Exact("type=recursive".to_string()))
+            .with_body(r#"{"Keys":{"QmDir":{"Type":"recursive"},"QmImage":{"Type":"recursive","Name":"image"}}}"#)
+            .create_async()
+            .await;
+        let response = client(&server)
+            .pin_ls(PinLsRequest {
+                query: PinLsQuery {
+                    typ: Some(PinType::Recursive),
+                    ..Default::default()
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.keys.len(), 2);
+        assert_eq!(response.keys["QmDir"].typ, PinType::Recursive);
+        assert_eq!(response.keys["QmImage"].name, "image");
+    }
+
+    #[tokio::test]
+    async fn pin_verify_reads_every_streamed_line() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/pin/verify")
+            .match_query(Matcher::Exact("verbose=true".to_string()))
+            .with_body(concat!(
+                r#"{"Cid":"QmDir","Ok":true}"#,
+                "\n",
+                r#"{"Cid":"QmImage","Ok":false,"BadNodes":[{"Cid":"QmBlock","Err":"merkledag: not found"}]}"#,
+                "\n",
+            ))
+            .create_async()
+            .await;
+        let response = client(&server)
+            .pin_verify(PinVerifyRequest {
+                query: PinVerifyQuery {
+                    verbose: Some(true),
+                    quiet: None,
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.pins.len(), 2);
+        assert!(response.pins[0].ok);
+        assert!(!response.pins[1].ok);
+        assert_eq!(
+            response.pins[1].bad_nodes.as_ref().unwrap()[0].cid,
+            "QmBlock"
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_update_repeats_arg() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/pin/update")
+            .match_query(Matcher::Exact("arg=QmOld&arg=QmNew&unpin=true".to_string()))
+            .with_body(r#"{"Pins":["QmOld","QmNew"]}"#)
+            .create_async()
+            .await;
+        let response = client(&server)
+            .pin_update(PinUpdateRequest {
+                query: PinUpdateQuery {
+                    from: "QmOld".to_string(),
+                    to: "QmNew".to_string(),
+                    unpin: Some(true),
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.pins.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn add_streams_the_body_and_reports_progress() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v0/add")
+            .match_query(Matcher::Exact("progress=true".to_string()))
+            .match_body(Matcher::Regex("first chunk second chunk".to_string()))
+            .with_body(concat!(
+                r#"{"Name":"video.mp4","Bytes":12}"#,
+                "\n",
+                r#"{"Name":"video.mp4","Bytes":24}"#,
+                "\n",
+                r#"{"Name":"video.mp4","Hash":"QmVideo","Size":"35"}"#,
+                "\n",
+            ))
+            .create_async()
+            .await;
+        let chunks: Vec<Result<Vec<u8>, std::io::Error>> =
+            vec![Ok(b"first chunk ".to_vec()), Ok(b"second chunk".to_vec())];
+        let req = AddRequest::new_with_stream(
+            AddQuery::default(),
+            "video.mp4".to_string(),
+            reqwest::Body::wrap_stream(futures_util::stream::iter(chunks)),
+            Some(24),
+        );
+        let mut progress = vec![];
+        let response = client(&server)
+            .add_with_progress(req, |bytes| progress.push(bytes))
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(progress, vec![12, 24]);
+        assert_eq!(response.hash.as_deref(), Some("QmVideo"));
+    }
+
+    #[tokio::test]
+    async fn add_without_a_final_line_fails() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/add")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"Name":"video.mp4","Bytes":12}"#)
+            .create_async()
+            .await;
+        let result = client(&server)
+            .add_with_progress(
+                AddRequest::new_with_file("video.mp4".to_string(), b"partial".to_vec()),
+                |_| {},
+            )
+            .await;
+        assert!(matches!(result, Err(Error::ResponseBodySerializeError)));
+    }
 }
@@ -1,5 +1,6 @@
 pub mod client;
 mod error;
+pub mod remote;
 pub mod req;
 mod request;
 pub mod resp;
@@ -0,0 +1,243 @@
+use std::future::Future;
+
+use reqwest::{Method, Url};
+
+use crate::{
+    error::Error,
+    req::remote::{AddPinRequest, ListPinsRequest},
+    request::{send_with_token, QueryParam},
+    resp::remote::{PinResultsResponse, PinStatusResponse},
+    response::{text, Parsable},
+};
+
+/// The IPFS Pinning Service API, see https://ipfs.github.io/pinning-services-api-spec/.
+pub trait RemotePinning {
+    /**
+     * Add pin object.
+     */
+    fn add_pin(
+        &self,
+        req: AddPinRequest,
+    ) -> impl Future<Output = Result<PinStatusResponse, Error>> + Send;
+
+    /**
+     * List pin objects.
+     */
+    fn list_pins(
+        &self,
+        req: ListPinsRequest,
+    ) -> impl Future<Output = Result<PinResultsResponse, Error>> + Send;
+
+    /**
+     * Get pin object.
+     */
+    fn get_pin(
+        &self,
+        request_id: &str,
+    ) -> impl Future<Output = Result<PinStatusResponse, Error>> + Send;
+
+    /**
+     * Replace pin object, removing the old pin once the new one is pinned.
+     */
+    fn replace_pin(
+        &self,
+        request_id: &str,
+        req: AddPinRequest,
+    ) -> impl Future<Output = Result<PinStatusResponse, Error>> + Send;
+
+    /**
+     * Remove pin object.
+     */
+    fn remove_pin(&self, request_id: &str) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// Client for one remote pinning service, authenticated with its access token.
+#[derive(Debug, Clone)]
+pub struct RemotePinningClient {
+    endpoint: Url,
+    access_token: String,
+}
+
+impl RemotePinningClient {
+    pub fn new(endpoint: String, access_token: String) -> Result<Self, Error> {
+        let endpoint = Url::parse(endpoint.trim_end_matches('/')).map_err(|err| {
+            log::error!("Failed to parse URL: {:?}", err);
+            Error::UrlParse
+        })?;
+        Ok(RemotePinningClient {
+            endpoint,
+            access_token,
+        })
+    }
+
+    fn url(&self, path: &str, query: Option<String>) -> Result<Url, Error> {
+        let base = self.endpoint.as_str().trim_end_matches('/');
+        let url = match query.filter(|query| !query.is_empty()) {
+            Some(query) => format!("{}/{}?{}", base, path, query),
+            None => format!("{}/{}", base, path),
+        };
+        Url::parse(&url).map_err(|err| {
+            log::error!("Failed to parse URL: {:?}", err);
+            Error::UrlParse
+        })
+    }
+}
+
+impl RemotePinning for RemotePinningClient {
+    async fn add_pin(&self, req: AddPinRequest) -> Result<PinStatusResponse, Error> {
+        let url = self.url("pins", None)?;
+        let response = send_with_token(Method::POST, url, &self.access_token, Some(&req)).await?;
+        PinStatusResponse::parse(response).await
+    }
+
+    async fn list_pins(&self, req: ListPinsRequest) -> Result<PinResultsResponse, Error> {
+        let url = self.url("pins", Some(req.query.encode()))?;
+        let response = send_with_token::<()>(Method::GET, url, &self.access_token, None).await?;
+        PinResultsResponse::parse(response).await
+    }
+
+    async fn get_pin(&self, request_id: &str) -> Result<PinStatusResponse, Error> {
+        let url = self.url(&format!("pins/{}", request_id), None)?;
+        let response = send_with_token::<()>(Method::GET, url, &self.access_token, None).await?;
+        PinStatusResponse::parse(response).await
+    }
+
+    async fn replace_pin(
+        &self,
+        request_id: &str,
+        req: AddPinRequest,
+    ) -> Result<PinStatusResponse, Error> {
+        let url = self.url(&format!("pins/{}", request_id), None)?;
+        let response = send_with_token(Method::POST, url, &self.access_token, Some(&req)).await?;
+        PinStatusResponse::parse(response).await
+    }
+
+    async fn remove_pin(&self, request_id: &str) -> Result<(), Error> {
+        let url = self.url(&format!("pins/{}", request_id), None)?;
+        let response = send_with_token::<()>(Method::DELETE, url, &self.access_token, None).await?;
+        text(response).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Server};
+
+    use super::*;
+    use crate::req::remote::{ListPinsQuery, RemotePinStatus};
+
+    fn client(server: &Server) -> RemotePinningClient {
+        RemotePinningClient::new(format!("{}/psa/", server.url()), "secret".to_string()).unwrap()
+    }
+
+    fn pin_status(request_id: &str, cid: &str, status: &str) -> String {
+        serde_json::json!({
+            "requestid": request_id,
+            "status": status,
+            "created": "2024-05-26T08:00:00Z",
+            "pin": { "cid": cid, "name": "collection" },
+            "delegates": ["/dnsaddr/pin.example/p2p/QmDelegate"],
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn add_pin_posts_the_cid_with_the_token() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/psa/pins")
+            .match_header("authorization", "Bearer secret")
+            .match_body(Matcher::Json(
+                serde_json::json!({ "cid": "QmDir", "name": "collection" }),
+            ))
+            .with_status(202)
+            .with_body(pin_status("r1", "QmDir", "queued"))
+            .create_async()
+            .await;
+        let mut req = AddPinRequest::new_with_cid(&"QmDir".to_string());
+        req.name = Some("collection".to_string());
+        let response = client(&server).add_pin(req).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.requestid, "r1");
+        assert_eq!(response.status, RemotePinStatus::Queued);
+        assert_eq!(response.pin.cid, "QmDir");
+        assert_eq!(response.delegates.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn list_pins_joins_filters_with_commas() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/psa/pins")
+            .match_query(Matcher::Exact(
+                "cid=QmA%2CQmB&status=queued%2Cpinning&limit=2".to_string(),
+            ))
+            .with_body(format!(
+                r#"{{"count":1,"results":[{}]}}"#,
+                pin_status("r1", "QmA", "pinning")
+            ))
+            .create_async()
+            .await;
+        let response = client(&server)
+            .list_pins(ListPinsRequest {
+                query: ListPinsQuery {
+                    cid: vec!["QmA".to_string(), "QmB".to_string()],
+                    status: vec![RemotePinStatus::Queued, RemotePinStatus::Pinning],
+                    limit: Some(2),
+                    ..Default::default()
+                },
+            })
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.count, 1);
+        assert_eq!(response.results[0].status, RemotePinStatus::Pinning);
+    }
+
+    #[tokio::test]
+    async fn polls_replaces_and_removes_by_request_id() {
+        let mut server = Server::new_async().await;
+        let get = server
+            .mock("GET", "/psa/pins/r1")
+            .with_body(pin_status("r1", "QmOld", "pinned"))
+            .create_async()
+            .await;
+        let replace = server
+            .mock("POST", "/psa/pins/r1")
+            .match_body(Matcher::Json(serde_json::json!({ "cid": "QmNew" })))
+            .with_status(202)
+            .with_body(pin_status("r2", "QmNew", "queued"))
+            .create_async()
+            .await;
+        let remove = server
+            .mock("DELETE", "/psa/pins/r2")
+            .with_status(202)
+            .create_async()
+            .await;
+        let client = client(&server);
+        let status = client.get_pin("r1").await.unwrap();
+        assert_eq!(status.status, RemotePinStatus::Pinned);
+        let replaced = client
+            .replace_pin("r1", AddPinRequest::new_with_cid(&"QmNew".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(replaced.requestid, "r2");
+        client.remove_pin("r2").await.unwrap();
+        get.assert_async().await;
+        replace.assert_async().await;
+        remove.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_fail() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/psa/pins/missing")
+            .with_status(404)
+            .with_body(r#"{"error":{"reason":"NOT_FOUND"}}"#)
+            .create_async()
+            .await;
+        let result = client(&server).get_pin("missing").await;
+        assert!(matches!(result, Err(Error::RequestFailed)));
+    }
+}
@@ -1,5 +1,8 @@
 use ipfs_api_derive::QueryParam;
-use reqwest::multipart::{Form, Part};
+use reqwest::{
+    multipart::{Form, Part},
+    Body,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -46,12 +49,16 @@ pub struct AddQuery {
     pub to_files: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct AddRequest {
     pub query: AddQuery,
     // Form fields
     pub filename: Option<String>,
     pub bytes: Option<Vec<u8>>,
+    // Streamed file content, sent instead of `bytes` without buffering it.
+    pub stream: Option<Body>,
+    // Length of `stream`, if known; otherwise the part is sent chunked.
+    pub length: Option<u64>,
 }
 
 impl AddRequest {
@@ -61,6 +68,8 @@ impl AddRequest {
             query,
             filename: None,
             bytes: None,
+            stream: None,
+            length: None,
         }
     }
 
@@ -70,25 +79,42 @@ impl AddRequest {
             query,
             filename: Some(filename),
             bytes: Some(bytes),
+            stream: None,
+            length: None,
         }
     }
 
     #[allow(dead_code)]
     pub fn new_with_file(filename: String, bytes: Vec<u8>) -> Self {
+        AddRequest::new_with_query_and_file(AddQuery::default(), filename, bytes)
+    }
+
+    /// Adds a file read from `stream`, e.g. `Body::wrap_stream` over an upload.
+    pub fn new_with_stream(
+        query: AddQuery,
+        filename: String,
+        stream: Body,
+        length: Option<u64>,
+    ) -> Self {
         AddRequest {
-            query: AddQuery::default(),
+            query,
             filename: Some(filename),
-            bytes: Some(bytes),
+            bytes: None,
+            stream: Some(stream),
+            length,
         }
     }
 }
 
 impl WithForm for AddRequest {
-    fn form(&self) -> Result<Form, Error> {
-        let form = Form::new();
-        let b = self.bytes.clone().unwrap();
-        let f = self.filename.clone().unwrap();
-        let form = form.part("file", Part::bytes(b).file_name(f));
-        Ok(form)
+    fn form(self) -> Result<Form, Error> {
+        let filename = self.filename.ok_or(Error::NoFilename)?;
+        let part = match (self.bytes, self.stream, self.length) {
+            (Some(bytes), _, _) => Part::bytes(bytes),
+            (None, Some(stream), Some(length)) => Part::stream_with_length(stream, length),
+            (None, Some(stream), None) => Part::stream(stream),
+            (None, None, _) => return Err(Error::NoFileBytes),
+        };
+        Ok(Form::new().part("file", part.file_name(filename)))
     }
 }
@@ -39,7 +39,7 @@ pub struct PutRequest {
 }
 
 impl WithForm for PutRequest {
-    fn form(&self) -> Result<Form, Error> {
+    fn form(self) -> Result<Form, Error> {
         let form = Form::new().part(
             "object data",
             reqwest::multipart::Part::text(serde_json::to_string(&self.object_data).map_err(
@@ -1,12 +1,12 @@
 use crate::{
     error::Error,
-    request::{QueryParam, WithForm},
+    request::{encode_pairs, QueryParam, WithForm},
 };
 use ipfs_api_derive::QueryParam;
 use reqwest::multipart::{Form, Part};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
 #[serde(rename_all = "kebab-case")]
 pub struct ChcidQuery {
     // Path to change. Default: '/'. Required: no.
@@ -17,22 +17,47 @@ pub struct ChcidQuery {
     pub hash: Option<String>,
 }
 
+impl ChcidQuery {
+    pub fn new_with_arg(arg: &String, cid_version: i32) -> Self {
+        let mut arg = arg.clone();
+        if !arg.starts_with("/") {
+            arg = format!("/{}", arg);
+        }
+
+        Self {
+            arg: Some(arg),
+            cid_version: Some(cid_version),
+            hash: None,
+        }
+    }
+}
+
 pub struct ChcidRequest {
     pub query: ChcidQuery,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Both paths are sent as `arg`, which a derived `Serialize` cannot express, so the query
+// is encoded by hand.
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CpQuery {
     // Source IPFS or MFS path to copy. Required: yes.
-    #[serde(rename = "arg")]
     pub source: String,
     // Destination within MFS. Required: yes.
-    #[serde(rename = "arg")]
     pub dest: String,
     // Make parent directories as needed. Required: no.
     pub parents: Option<bool>,
 }
 
+impl QueryParam for CpQuery {
+    fn encode(&self) -> String {
+        encode_pairs(&[
+            ("arg", Some(self.source.clone())),
+            ("arg", Some(self.dest.clone())),
+            ("parents", self.parents.map(|parents| parents.to_string())),
+        ])
+    }
+}
+
 pub struct CpRequest {
     pub query: CpQuery,
 }
@@ -43,11 +68,22 @@ pub struct FlushQuery {
     pub arg: Option<String>,
 }
 
+impl FlushQuery {
+    pub fn new_with_arg(arg: &String) -> Self {
+        let mut arg = arg.clone();
+        if !arg.starts_with("/") {
+            arg = format!("/{}", arg);
+        }
+
+        Self { arg: Some(arg) }
+    }
+}
+
 pub struct FlushRequest {
-    pub query: &'static FlushQuery,
+    pub query: FlushQuery,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
 pub struct LsQuery {
     // Path to show listing for. Defaults to '/'. Required: no.
     pub arg: Option<String>,
@@ -95,21 +131,29 @@ pub struct MkdirRequest {
     pub query: MkdirQuery,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Encoded by hand for the same reason as `CpQuery`.
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct MvQuery {
     // Source file to move. Required: yes.
-    #[serde(rename = "arg")]
     pub source: String,
     // Destination path for file to be moved to. Required: yes.
-    #[serde(rename = "arg")]
     pub dest: String,
 }
 
+impl QueryParam for MvQuery {
+    fn encode(&self) -> String {
+        encode_pairs(&[
+            ("arg", Some(self.source.clone())),
+            ("arg", Some(self.dest.clone())),
+        ])
+    }
+}
+
 pub struct MvRequest {
     pub query: MvQuery,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
 pub struct ReadQuery {
     // Path to file to be read. Required: yes.
     pub arg: String,
@@ -123,7 +167,7 @@ pub struct ReadRequest {
     pub query: ReadQuery,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
 pub struct RmQuery {
     // File to remove. Required: yes.
     pub arg: String,
@@ -223,11 +267,9 @@ pub struct WriteRequest {
 }
 
 impl WithForm for WriteRequest {
-    fn form(&self) -> Result<Form, Error> {
+    fn form(self) -> Result<Form, Error> {
         let form = Form::new();
-        let b = self.bytes.clone();
-        let f = self.filename.clone();
-        let form = form.part("data", Part::bytes(b).file_name(f));
+        let form = form.part("data", Part::bytes(self.bytes).file_name(self.filename));
         Ok(form)
     }
 }
@@ -0,0 +1,106 @@
+use ipfs_api_derive::QueryParam;
+use serde::{Deserialize, Serialize};
+
+use crate::request::{encode_pairs, QueryParam};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PinType {
+    Direct,
+    Indirect,
+    Recursive,
+    All,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
+pub struct PinAddQuery {
+    // Path to object(s) to be pinned. Required: yes.
+    pub arg: String,
+    // Recursively pin the object linked to by the specified object(s). Default: true. Required: no.
+    pub recursive: Option<bool>,
+    // An optional name for created pin(s). Required: no.
+    pub name: Option<String>,
+    // Show progress. Required: no.
+    pub progress: Option<bool>,
+}
+
+impl PinAddQuery {
+    pub fn new_with_arg(arg: &String) -> Self {
+        Self {
+            arg: arg.clone(),
+            recursive: Some(true),
+            name: None,
+            progress: None,
+        }
+    }
+}
+
+pub struct PinAddRequest {
+    pub query: PinAddQuery,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
+pub struct PinRmQuery {
+    // Path to object(s) to be unpinned. Required: yes.
+    pub arg: String,
+    // Recursively unpin the object linked to by the specified object(s). Default: true. Required: no.
+    pub recursive: Option<bool>,
+}
+
+pub struct PinRmRequest {
+    pub query: PinRmQuery,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
+pub struct PinLsQuery {
+    // Path to object(s) to be listed. Required: no.
+    pub arg: Option<String>,
+    // The type of pinned keys to list. Can be "direct", "indirect", "recursive", or "all". Default: all. Required: no.
+    #[serde(rename = "type")]
+    pub typ: Option<PinType>,
+    // Write just hashes of objects. Required: no.
+    pub quiet: Option<bool>,
+    // Enable displaying pin names. Required: no.
+    pub names: Option<bool>,
+}
+
+pub struct PinLsRequest {
+    pub query: PinLsQuery,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, QueryParam)]
+pub struct PinVerifyQuery {
+    // Also write the hashes of non-broken pins. Required: no.
+    pub verbose: Option<bool>,
+    // Write just hashes of broken pins. Required: no.
+    pub quiet: Option<bool>,
+}
+
+pub struct PinVerifyRequest {
+    pub query: PinVerifyQuery,
+}
+
+// Both paths are sent as `arg`, so the query is encoded by hand like `files::CpQuery`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PinUpdateQuery {
+    // Path to old object. Required: yes.
+    pub from: String,
+    // Path to a new object to be pinned. Required: yes.
+    pub to: String,
+    // Remove the old pin. Default: true. Required: no.
+    pub unpin: Option<bool>,
+}
+
+impl QueryParam for PinUpdateQuery {
+    fn encode(&self) -> String {
+        encode_pairs(&[
+            ("arg", Some(self.from.clone())),
+            ("arg", Some(self.to.clone())),
+            ("unpin", self.unpin.map(|unpin| unpin.to_string())),
+        ])
+    }
+}
+
+pub struct PinUpdateRequest {
+    pub query: PinUpdateQuery,
+}
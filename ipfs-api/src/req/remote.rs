@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::request::{encode_pairs, QueryParam};
+
+/// Lifecycle of a pin request on a remote service.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemotePinStatus {
+    Queued,
+    Pinning,
+    Pinned,
+    Failed,
+}
+
+impl RemotePinStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemotePinStatus::Queued => "queued",
+            RemotePinStatus::Pinning => "pinning",
+            RemotePinStatus::Pinned => "pinned",
+            RemotePinStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Body of `POST /pins` and `POST /pins/{requestid}`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AddPinRequest {
+    // Content Identifier (CID) to be pinned recursively. Required: yes.
+    pub cid: String,
+    // Optional name for pinned data; can be used for lookups later. Required: no.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Optional list of multiaddrs known to provide the data. Required: no.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<String>,
+    // Optional metadata for pin object. Required: no.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+}
+
+impl AddPinRequest {
+    pub fn new_with_cid(cid: &String) -> Self {
+        Self {
+            cid: cid.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// List filters are comma separated, so the query is encoded by hand.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ListPinsQuery {
+    // Return pin objects responsible for pinning the specified CID(s). Required: no.
+    pub cid: Vec<String>,
+    // Return pin objects with specified name. Required: no.
+    pub name: Option<String>,
+    // Return pin objects for pins with the specified status. Default: pinned. Required: no.
+    pub status: Vec<RemotePinStatus>,
+    // Return results created (queued) before provided timestamp. Required: no.
+    pub before: Option<String>,
+    // Return results created (queued) after provided timestamp. Required: no.
+    pub after: Option<String>,
+    // Max records to return. Default: 10. Required: no.
+    pub limit: Option<u32>,
+}
+
+fn join(values: impl Iterator<Item = String>) -> Option<String> {
+    let joined = values.collect::<Vec<_>>().join(",");
+    if joined.is_empty() {
+        None
+    } else {
+        Some(joined)
+    }
+}
+
+impl QueryParam for ListPinsQuery {
+    fn encode(&self) -> String {
+        encode_pairs(&[
+            ("cid", join(self.cid.iter().cloned())),
+            ("name", self.name.clone()),
+            (
+                "status",
+                join(self.status.iter().map(|status| status.as_str().to_string())),
+            ),
+            ("before", self.before.clone()),
+            ("after", self.after.clone()),
+            ("limit", self.limit.map(|limit| limit.to_string())),
+        ])
+    }
+}
+
+pub struct ListPinsRequest {
+    pub query: ListPinsQuery,
+}
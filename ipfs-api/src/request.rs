@@ -1,4 +1,4 @@
-use reqwest::{multipart::Form, Client, Response, Url};
+use reqwest::{multipart::Form, Client, Method, Response, Url};
 use serde::Serialize;
 
 use crate::error::Error;
@@ -9,6 +9,16 @@ pub trait QueryParam: Serialize + Send + Default {
     }
 }
 
+/// Encodes `pairs` in order, skipping unset values. Unlike a derived `Serialize`, this can
+/// repeat a key, as endpoints taking several `arg`s require.
+pub fn encode_pairs(pairs: &[(&str, Option<String>)]) -> String {
+    let pairs: Vec<(&str, &String)> = pairs
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| (*key, value)))
+        .collect();
+    serde_urlencoded::to_string(pairs).unwrap()
+}
+
 pub trait Request {
     fn path(&self) -> String;
 }
@@ -69,6 +79,38 @@ async fn _post(url: Url, form: Option<Form>) -> Result<Response, Error> {
     Ok(response)
 }
 
+/// Sends a bearer-authenticated request with an optional JSON body, as the Pinning
+/// Service API expects.
+pub async fn send_with_token<B: Serialize>(
+    method: Method,
+    url: Url,
+    token: &str,
+    body: Option<&B>,
+) -> Result<Response, Error> {
+    let client = Client::new();
+    let mut request_builder = client.request(method, url.clone()).bearer_auth(token);
+    if let Some(body) = body {
+        request_builder = request_builder.json(body);
+    }
+    let response = request_builder.send().await.map_err(|err| {
+        log::error!(
+            "Send request get error: \n\turl:{:?}\n\terror:{:?}",
+            url.clone().to_string(),
+            err
+        );
+        Error::RequestError
+    })?;
+    if !response.status().is_success() {
+        log::error!(
+            "send request failed: \n\turl:{:?}\n\tresponse:{:?}",
+            url.clone().to_string(),
+            response.text().await.unwrap_or_default()
+        );
+        return Err(Error::RequestFailed);
+    }
+    Ok(response)
+}
+
 impl<'a, Q> RequestUrl<'a, Q>
 where
     Q: QueryParam,
@@ -78,6 +120,7 @@ where
     }
 }
 
+/// Builds the multipart form of a request, moving the file content into it.
 pub trait WithForm {
-    fn form(&self) -> Result<Form, Error>;
+    fn form(self) -> Result<Form, Error>;
 }
@@ -1,3 +1,5 @@
+use futures_util::StreamExt;
+use log::error;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -16,8 +18,60 @@ pub struct AddResponse {
 
 impl Parsable for AddResponse {
     async fn parse(response: reqwest::Response) -> Result<AddResponse, Error> {
-        json(response)
-            .await
-            .map(|resp| serde_json::from_value::<AddResponse>(resp).unwrap())
+        json(response).await.and_then(|resp| {
+            serde_json::from_value::<AddResponse>(resp).map_err(|err| {
+                error!("Failed to parse add response: {:?}", err);
+                Error::ResponseBodySerializeError
+            })
+        })
     }
 }
+
+impl AddResponse {
+    /// Reads the lines `add` streams with `progress` set, calling `on_progress` with the bytes
+    /// processed so far, and returns the final line, the one carrying the hash.
+    pub async fn parse_with_progress<P>(
+        response: reqwest::Response,
+        mut on_progress: P,
+    ) -> Result<AddResponse, Error>
+    where
+        P: FnMut(i64) + Send,
+    {
+        let mut body = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut added = None;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|err| {
+                error!("Failed to read response body: {:?}", err);
+                Error::ResponseBodyReadError
+            })?;
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                read_progress_line(&line, &mut added, &mut on_progress)?;
+            }
+        }
+        read_progress_line(&buffer, &mut added, &mut on_progress)?;
+        added.ok_or(Error::ResponseBodySerializeError)
+    }
+}
+
+fn read_progress_line<P: FnMut(i64)>(
+    line: &[u8],
+    added: &mut Option<AddResponse>,
+    on_progress: &mut P,
+) -> Result<(), Error> {
+    if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+        return Ok(());
+    }
+    let line = serde_json::from_slice::<AddResponse>(line).map_err(|err| {
+        error!("Failed to parse add progress: {:?}", err);
+        Error::ResponseBodySerializeError
+    })?;
+    match (&line.hash, line.bytes) {
+        (Some(_), _) => *added = Some(line),
+        (None, Some(bytes)) => on_progress(bytes),
+        (None, None) => {}
+    }
+    Ok(())
+}
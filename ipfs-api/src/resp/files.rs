@@ -1,8 +1,9 @@
-use serde::{Deserialize, Serialize};
+use log::error;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     error::Error,
-    response::{json, Parsable},
+    response::{bytes, json, Parsable},
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -22,24 +23,51 @@ impl Parsable for FlushResponse {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct LsResponse {
+    // The node sends `null` for an empty directory.
+    #[serde(deserialize_with = "null_as_empty")]
     pub entries: Vec<LsObject>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct LsObject {
+    // Empty unless the listing was requested with `long`.
     pub hash: String,
     pub name: String,
     pub size: i64,
+    // 0 for files, 1 for directories.
     #[serde(rename = "Type")]
     pub typ: i32,
 }
 
+fn null_as_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<Vec<T>>::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
 impl Parsable for LsResponse {
     async fn parse(response: reqwest::Response) -> Result<LsResponse, Error> {
-        json(response)
-            .await
-            .map(|resp| serde_json::from_value::<LsResponse>(resp).unwrap())
+        json(response).await.and_then(|resp| {
+            serde_json::from_value::<LsResponse>(resp).map_err(|err| {
+                error!("Failed to parse ls response: {:?}", err);
+                Error::ResponseBodySerializeError
+            })
+        })
+    }
+}
+
+/// Raw content of a file read from MFS.
+#[derive(Debug)]
+pub struct ReadResponse {
+    pub bytes: Vec<u8>,
+}
+
+impl Parsable for ReadResponse {
+    async fn parse(response: reqwest::Response) -> Result<ReadResponse, Error> {
+        bytes(response).await.map(|bytes| ReadResponse { bytes })
     }
 }
 
@@ -1,3 +1,5 @@
 pub mod add;
 pub mod dag;
 pub mod files;
+pub mod pin;
+pub mod remote;
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use log::error;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    req::pin::PinType,
+    response::{json, text, Parsable},
+};
+
+fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, Error> {
+    serde_json::from_value::<T>(value).map_err(|err| {
+        error!("Failed to parse pin response: {:?}", err);
+        Error::ResponseBodySerializeError
+    })
+}
+
+/// Answer of `pin/add`, `pin/rm` and `pin/update`: the CIDs whose pins changed.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PinsResponse {
+    #[serde(default)]
+    pub pins: Vec<String>,
+    pub progress: Option<i64>,
+}
+
+impl Parsable for PinsResponse {
+    async fn parse(response: reqwest::Response) -> Result<PinsResponse, Error> {
+        json(response).await.and_then(from_value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PinLsResponse {
+    #[serde(default)]
+    pub keys: HashMap<String, PinLsObject>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PinLsObject {
+    #[serde(rename = "Type")]
+    pub typ: PinType,
+    #[serde(default)]
+    pub name: String,
+}
+
+impl Parsable for PinLsResponse {
+    async fn parse(response: reqwest::Response) -> Result<PinLsResponse, Error> {
+        json(response).await.and_then(from_value)
+    }
+}
+
+/// `pin/verify` streams one JSON object per checked pin.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PinVerifyResponse {
+    pub pins: Vec<PinVerifyObject>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PinVerifyObject {
+    pub cid: String,
+    pub ok: bool,
+    #[serde(default)]
+    pub bad_nodes: Option<Vec<BadNode>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BadNode {
+    pub cid: String,
+    pub err: String,
+}
+
+impl Parsable for PinVerifyResponse {
+    async fn parse(response: reqwest::Response) -> Result<PinVerifyResponse, Error> {
+        let body = text(response).await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str::<PinVerifyObject>(line).map_err(|err| {
+                    error!("Failed to parse pin verify line: {:?}", err);
+                    Error::ResponseBodySerializeError
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .map(|pins| PinVerifyResponse { pins })
+    }
+}
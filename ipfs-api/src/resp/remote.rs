@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use log::error;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    req::remote::RemotePinStatus,
+    response::{json, Parsable},
+};
+
+fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, Error> {
+    serde_json::from_value::<T>(value).map_err(|err| {
+        error!("Failed to parse remote pin response: {:?}", err);
+        Error::ResponseBodySerializeError
+    })
+}
+
+/// A pin request as tracked by the remote service.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinStatusResponse {
+    pub requestid: String,
+    pub status: RemotePinStatus,
+    // Immutable timestamp indicating when a pin request entered a pinning service.
+    pub created: String,
+    pub pin: RemotePin,
+    // List of multiaddrs designated by pinning service for transferring any new data.
+    #[serde(default)]
+    pub delegates: Vec<String>,
+    #[serde(default)]
+    pub info: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemotePin {
+    pub cid: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub origins: Vec<String>,
+    #[serde(default)]
+    pub meta: HashMap<String, String>,
+}
+
+impl Parsable for PinStatusResponse {
+    async fn parse(response: reqwest::Response) -> Result<PinStatusResponse, Error> {
+        json(response).await.and_then(from_value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PinResultsResponse {
+    // The total number of pin objects that exist for passed query filters.
+    pub count: u64,
+    pub results: Vec<PinStatusResponse>,
+}
+
+impl Parsable for PinResultsResponse {
+    async fn parse(response: reqwest::Response) -> Result<PinResultsResponse, Error> {
+        json(response).await.and_then(from_value)
+    }
+}
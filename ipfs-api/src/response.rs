@@ -20,6 +20,17 @@ pub async fn text(response: reqwest::Response) -> Result<String, Error> {
     })
 }
 
+pub async fn bytes(response: reqwest::Response) -> Result<Vec<u8>, Error> {
+    response
+        .bytes()
+        .await
+        .map(|bytes| bytes.to_vec())
+        .map_err(|err| {
+            error!("Failed to read response body: {:?}", err);
+            Error::ResponseBodyReadError
+        })
+}
+
 pub trait Parsable {
     async fn parse(response: reqwest::Response) -> Result<impl Parsable, Error>;
 }
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE login_nonces (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    address VARCHAR(64) NOT NULL,
+    nonce VARCHAR(64) NOT NULL UNIQUE,
+    expired_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX login_nonces_address_idx ON login_nonces (address);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users
+    ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'user';
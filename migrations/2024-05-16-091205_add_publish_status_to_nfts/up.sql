@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Rows created before this column existed were written to IPFS inline.
+ALTER TABLE nfts
+    ADD COLUMN publish_status VARCHAR(16) NOT NULL DEFAULT 'published';
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE ipfs_jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind VARCHAR(16) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL DEFAULT 10,
+    next_run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT,
+    collection_id UUID REFERENCES collections (id) ON DELETE CASCADE,
+    nft_id UUID REFERENCES nfts (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The worker only ever scans for due jobs that are not finished.
+CREATE INDEX ipfs_jobs_due_idx ON ipfs_jobs (next_run_at)
+    WHERE status IN ('pending', 'running');
+CREATE INDEX ipfs_jobs_collection_id_idx ON ipfs_jobs (collection_id);
+CREATE INDEX ipfs_jobs_nft_id_idx ON ipfs_jobs (nft_id);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX collections_created_at_idx;
+DROP INDEX collections_owner_idx;
+DROP INDEX nft_traits_type_value_idx;
+DROP INDEX nfts_created_at_idx;
+DROP INDEX nfts_owner_idx;
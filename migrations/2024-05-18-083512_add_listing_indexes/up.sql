@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE INDEX nfts_owner_idx ON nfts (owner);
+CREATE INDEX nfts_created_at_idx ON nfts (created_at, id);
+CREATE INDEX nft_traits_type_value_idx ON nft_traits (trait_type, trait_value);
+CREATE INDEX collections_owner_idx ON collections (owner);
+CREATE INDEX collections_created_at_idx ON collections (created_at, id);
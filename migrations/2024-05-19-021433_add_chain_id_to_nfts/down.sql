@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nfts
+    DROP CONSTRAINT nfts_chain_id_collection_token_id_key,
+    ADD CONSTRAINT nfts_token_id_collection_key UNIQUE (token_id, collection),
+    DROP COLUMN chain_id;
+
+ALTER TABLE collections
+    DROP CONSTRAINT collections_chain_id_contract_address_key,
+    ADD CONSTRAINT collections_contract_address_key UNIQUE (contract_address);
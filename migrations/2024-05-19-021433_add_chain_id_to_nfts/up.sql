@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- A contract address is only unique on its chain.
+ALTER TABLE collections
+    DROP CONSTRAINT collections_contract_address_key,
+    ADD CONSTRAINT collections_chain_id_contract_address_key UNIQUE (chain_id, contract_address);
+
+ALTER TABLE nfts
+    ADD COLUMN chain_id INT;
+UPDATE nfts
+SET chain_id = collections.chain_id
+FROM collections
+WHERE collections.contract_address = nfts.collection;
+ALTER TABLE nfts
+    ALTER COLUMN chain_id SET NOT NULL,
+    DROP CONSTRAINT nfts_token_id_collection_key,
+    ADD CONSTRAINT nfts_chain_id_collection_token_id_key UNIQUE (chain_id, collection, token_id);
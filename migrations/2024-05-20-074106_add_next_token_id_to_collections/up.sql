@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE collections
+    ADD COLUMN next_token_id INT NOT NULL DEFAULT 1;
+UPDATE collections
+SET next_token_id = minted.max_token_id + 1
+FROM (
+    SELECT chain_id, collection, MAX(token_id) AS max_token_id
+    FROM nfts
+    GROUP BY chain_id, collection
+) AS minted
+WHERE minted.chain_id = collections.chain_id
+    AND minted.collection = collections.contract_address;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nfts
+    DROP COLUMN deleted_at;
+ALTER TABLE collections
+    DROP COLUMN deleted_at;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE collections
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE nfts
+    ADD COLUMN deleted_at TIMESTAMPTZ;
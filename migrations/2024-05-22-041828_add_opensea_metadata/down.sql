@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE collections
+    DROP COLUMN metadata_format;
+
+ALTER TABLE nft_traits
+    DROP COLUMN display_type,
+    DROP COLUMN max_value;
+
+ALTER TABLE nfts
+    DROP COLUMN animation_url,
+    DROP COLUMN background_color;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE nfts
+    ADD COLUMN animation_url TEXT,
+    ADD COLUMN background_color VARCHAR(6);
+
+ALTER TABLE nft_traits
+    ADD COLUMN display_type VARCHAR(32),
+    ADD COLUMN max_value DOUBLE PRECISION;
+
+-- Existing collections keep the layout their published files already have.
+ALTER TABLE collections
+    ADD COLUMN metadata_format VARCHAR(16) NOT NULL DEFAULT 'legacy';
+ALTER TABLE collections
+    ALTER COLUMN metadata_format SET DEFAULT 'opensea';
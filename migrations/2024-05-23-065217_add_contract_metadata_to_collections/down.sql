@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE collections
+    DROP COLUMN description,
+    DROP COLUMN banner_url,
+    DROP COLUMN external_link,
+    DROP COLUMN seller_fee_basis_points,
+    DROP COLUMN fee_recipient,
+    DROP COLUMN contract_cid;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE collections
+    ADD COLUMN description TEXT,
+    ADD COLUMN banner_url VARCHAR(255),
+    ADD COLUMN external_link VARCHAR(255),
+    ADD COLUMN seller_fee_basis_points INTEGER NOT NULL DEFAULT 0
+        CHECK (seller_fee_basis_points BETWEEN 0 AND 10000),
+    ADD COLUMN fee_recipient VARCHAR(64),
+    ADD COLUMN contract_cid VARCHAR(64);
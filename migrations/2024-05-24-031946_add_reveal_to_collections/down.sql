@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE collections
+    DROP CONSTRAINT collections_placeholder_check,
+    DROP COLUMN revealed,
+    DROP COLUMN placeholder_name,
+    DROP COLUMN placeholder_description,
+    DROP COLUMN placeholder_image_url;
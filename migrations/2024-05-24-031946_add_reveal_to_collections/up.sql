@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE collections
+    ADD COLUMN revealed BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN placeholder_name VARCHAR(255),
+    ADD COLUMN placeholder_description TEXT,
+    ADD COLUMN placeholder_image_url VARCHAR(255),
+    ADD CONSTRAINT collections_placeholder_check
+        CHECK (revealed OR placeholder_image_url IS NOT NULL);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE pins (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    cid VARCHAR(128) NOT NULL UNIQUE,
+    kind VARCHAR(16) NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pinned',
+    last_error TEXT,
+    collection_id UUID REFERENCES collections (id) ON DELETE CASCADE,
+    verified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- A collection keeps one directory pin, moved along with every flush.
+CREATE UNIQUE INDEX pins_collection_directory_idx ON pins (collection_id)
+    WHERE kind = 'directory';
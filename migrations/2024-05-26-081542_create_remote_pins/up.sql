@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE remote_pins (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    service VARCHAR(64) NOT NULL,
+    cid VARCHAR(128) NOT NULL,
+    request_id VARCHAR(255) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    collection_id UUID REFERENCES collections (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Media is pinned once per service; a collection directory keeps one request per service,
+-- replaced whenever the directory changes.
+CREATE UNIQUE INDEX remote_pins_service_cid_idx ON remote_pins (service, cid)
+    WHERE collection_id IS NULL;
+CREATE UNIQUE INDEX remote_pins_service_collection_idx ON remote_pins (service, collection_id)
+    WHERE collection_id IS NOT NULL;
+-- Status polling only looks at requests the services have not settled.
+CREATE INDEX remote_pins_unsettled_idx ON remote_pins (updated_at)
+    WHERE status IN ('queued', 'pinning');
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX pins_cid_kind_idx;
+DROP INDEX pins_cid_collection_kind_idx;
+DELETE FROM pins duplicate USING pins kept
+    WHERE duplicate.cid = kept.cid AND duplicate.id > kept.id;
+ALTER TABLE pins ADD CONSTRAINT pins_cid_key UNIQUE (cid);
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Identical content may be pinned for several collections or uploads. Each reference keeps
+-- its own row, and the node only unpins a CID once no row references it.
+ALTER TABLE pins DROP CONSTRAINT pins_cid_key;
+CREATE UNIQUE INDEX pins_cid_collection_kind_idx ON pins (cid, collection_id, kind)
+    WHERE collection_id IS NOT NULL;
+CREATE UNIQUE INDEX pins_cid_kind_idx ON pins (cid, kind)
+    WHERE collection_id IS NULL;
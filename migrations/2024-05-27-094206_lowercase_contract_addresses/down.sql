@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- The original casing is not kept; lowercase addresses remain valid.
+SELECT 1;
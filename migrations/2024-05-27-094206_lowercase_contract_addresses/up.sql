@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Contract addresses are stored lowercase, like user addresses, so lookups do not depend
+-- on the checksum casing a client sent.
+UPDATE collections SET contract_address = lower(contract_address)
+    WHERE contract_address <> lower(contract_address);
+UPDATE nfts SET collection = lower(collection)
+    WHERE collection <> lower(collection);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users
+    ADD CONSTRAINT users_role_check CHECK (role IN ('user', 'moderator', 'admin'));
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX nfts_chain_id_collection_token_id_idx;
+ALTER TABLE nfts
+    ADD CONSTRAINT nfts_chain_id_collection_token_id_key UNIQUE (chain_id, collection, token_id);
+
+DROP INDEX collections_chain_id_contract_address_idx;
+ALTER TABLE collections
+    ADD CONSTRAINT collections_chain_id_contract_address_key UNIQUE (chain_id, contract_address);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Soft-deleted collections and NFTs no longer hold on to their contract address and token ids,
+-- so a contract can be registered again after its collection was deleted.
+ALTER TABLE collections DROP CONSTRAINT collections_chain_id_contract_address_key;
+CREATE UNIQUE INDEX collections_chain_id_contract_address_idx
+    ON collections (chain_id, contract_address)
+    WHERE deleted_at IS NULL;
+
+ALTER TABLE nfts DROP CONSTRAINT nfts_chain_id_collection_token_id_key;
+CREATE UNIQUE INDEX nfts_chain_id_collection_token_id_idx
+    ON nfts (chain_id, collection, token_id)
+    WHERE deleted_at IS NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX ipfs_jobs_unfinished_collection_idx;
+ALTER TABLE ipfs_jobs ALTER COLUMN created_at SET DEFAULT now();
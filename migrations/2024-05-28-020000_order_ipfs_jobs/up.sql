@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Jobs of one collection run in the order they were queued, including jobs queued by the
+-- same transaction, so `now()` is not precise enough to order them.
+ALTER TABLE ipfs_jobs ALTER COLUMN created_at SET DEFAULT clock_timestamp();
+CREATE INDEX ipfs_jobs_unfinished_collection_idx ON ipfs_jobs (collection_id, created_at)
+    WHERE status IN ('pending', 'running');
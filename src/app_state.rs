@@ -1,22 +1,44 @@
+use std::sync::Arc;
+
+use async_graphql::dataloader::DataLoader;
 use async_graphql::{MergedObject, MergedSubscription, Schema};
+use ipfs_api::client::LocalIPFSClient;
+
+use crate::config::Config;
 
+use crate::domain::api_key::{ApiKeyMutation, ApiKeyQuery};
 use crate::domain::collection::{CollectionMutation, CollectionQuery};
-use crate::domain::file::FileMutation;
+use crate::domain::file::{FileMutation, FileSubscription, UploadProgressHub};
+use crate::domain::ipfs_job::{IpfsJobMutation, IpfsJobQuery};
+use crate::domain::loader::{CollectionLoader, NFTTraitLoader, UserLoader};
 use crate::domain::nft::{NFTMutation, NFTQuery};
+use crate::domain::session::{SessionMutation, SessionQuery};
 use crate::domain::token::{TokenMutation, TokenQuery, TokenSubscription};
 use crate::domain::user::{UserMutation, UserQuery};
+use crate::models::Database;
 
 #[derive(MergedObject, Default)]
-pub struct QueryRoot(TokenQuery, CollectionQuery, UserQuery, NFTQuery);
+pub struct QueryRoot(
+    TokenQuery,
+    SessionQuery,
+    ApiKeyQuery,
+    CollectionQuery,
+    UserQuery,
+    NFTQuery,
+    IpfsJobQuery,
+);
 #[derive(MergedSubscription, Default)]
-pub struct SubscriptionRoot(TokenSubscription);
+pub struct SubscriptionRoot(TokenSubscription, FileSubscription);
 #[derive(MergedObject, Default)]
 pub struct MutationRoot(
     TokenMutation,
+    SessionMutation,
+    ApiKeyMutation,
     FileMutation,
     UserMutation,
     CollectionMutation,
     NFTMutation,
+    IpfsJobMutation,
 );
 
 pub type SchemaRoot = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
@@ -24,15 +46,43 @@ pub type SchemaRoot = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 #[derive(Clone)]
 pub struct AppState {
     pub schema: SchemaRoot,
+    pub database: Database,
+    pub config: Arc<Config>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        let schema = Schema::new(
+    pub fn new(config: Arc<Config>, database: Database) -> Self {
+        let schema = Schema::build(
             QueryRoot::default(),
             MutationRoot::default(),
             SubscriptionRoot::default(),
-        );
-        Self { schema }
+        )
+        .data(database.clone())
+        .data(config.clone())
+        .data(
+            config
+                .chain_rpcs()
+                .expect("configuration was validated on load"),
+        )
+        .data(LocalIPFSClient::new(config.ipfs.api_url.clone()))
+        .data(UploadProgressHub::default())
+        .data(DataLoader::new(
+            NFTTraitLoader::new(database.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            CollectionLoader::new(database.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            UserLoader::new(database.clone()),
+            tokio::spawn,
+        ))
+        .finish();
+        Self {
+            schema,
+            database,
+            config,
+        }
     }
 }
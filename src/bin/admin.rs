@@ -0,0 +1,211 @@
+use clap::{Parser, Subcommand};
+use diesel::{Connection, PgConnection};
+use dotenv::dotenv;
+
+use nft_marketplace_rs::{
+    config::Config,
+    domain::collection::insert_collection,
+    domain::guard::Role,
+    domain::nft::{insert_nft, republish_collection, MetadataFormat, NewNFT, NewNFTTrait},
+    errors::AppError,
+    models::{
+        self,
+        collection::{Collection, CollectionQuery, InsertedCollection},
+        user::{InsertedUser, User},
+        Database,
+    },
+};
+
+/// Owner of the seeded data: the first default account of anvil and hardhat. Addresses are
+/// stored lowercase.
+const DEMO_OWNER: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+/// Where the first contract deployed by that account ends up on a fresh local chain.
+const DEMO_CONTRACT_ADDRESS: &str = "0x5fbdb2315678afecb367f032d93f642f64180aa3";
+const DEMO_CHAIN_ID: i32 = 31337;
+
+#[derive(Parser)]
+#[command(
+    name = "nft-marketplace-admin",
+    about = "Maintenance tasks for the marketplace backend"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply pending database migrations.
+    Migrate,
+    /// Revert the most recently applied migrations.
+    Rollback {
+        #[arg(long, default_value_t = 1)]
+        steps: u32,
+    },
+    /// Insert a demo user, collection and NFTs for local development.
+    Seed {
+        #[arg(long, default_value = DEMO_OWNER)]
+        owner: String,
+    },
+    /// Queue the IPFS directory and metadata files of a collection again.
+    RepublishCollection {
+        contract_address: String,
+        /// Chain the contract is deployed on; required so production runs never fall back
+        /// to the local dev chain.
+        #[arg(long)]
+        chain_id: i32,
+    },
+    /// Create a user with the admin role, or promote an existing one.
+    CreateAdmin {
+        address: String,
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+fn seed(connection: &mut PgConnection, owner: String) -> Result<(), AppError> {
+    if User::find_by_address(connection, owner.clone())?.is_none() {
+        InsertedUser {
+            name: Some("Demo Creator".to_string()),
+            address: owner.clone(),
+            email: None,
+            avatar_url: None,
+        }
+        .insert(connection)?;
+    }
+
+    let collection_query = CollectionQuery {
+        contract_address: Some(DEMO_CONTRACT_ADDRESS.to_string()),
+        chain_id: Some(DEMO_CHAIN_ID),
+        ..Default::default()
+    };
+    if Collection::find_by_query(connection, collection_query)?.is_some() {
+        println!("demo collection {} already exists", DEMO_CONTRACT_ADDRESS);
+        return Ok(());
+    }
+    let collection = insert_collection(
+        connection,
+        &InsertedCollection {
+            name: "Demo Collection".to_string(),
+            symbol: "DEMO".to_string(),
+            owner: owner.clone(),
+            pic_url: "https://picsum.photos/seed/demo/512".to_string(),
+            contract_address: DEMO_CONTRACT_ADDRESS.to_string(),
+            chain_id: DEMO_CHAIN_ID,
+            dir_name: "demo-collection".to_string(),
+            dir_hash: String::new(),
+            metadata_format: MetadataFormat::OpenSea.as_str().to_string(),
+            description: Some("Seeded for local development.".to_string()),
+            banner_url: None,
+            external_link: None,
+            seller_fee_basis_points: 0,
+            fee_recipient: None,
+            contract_cid: None,
+            revealed: true,
+            placeholder_name: None,
+            placeholder_description: None,
+            placeholder_image_url: None,
+        },
+    )?;
+
+    for (token_id, background) in [(1, "blue"), (2, "green"), (3, "red")] {
+        let new_nft = NewNFT {
+            token_id,
+            name: format!("Demo #{}", token_id),
+            description: Some("Seeded for local development.".to_string()),
+            image_url: format!("https://picsum.photos/seed/demo-{}/512", token_id),
+            supply: 1,
+            external_link: None,
+            collection: collection.contract_address.clone(),
+            chain_id: collection.chain_id,
+            traits: Some(vec![NewNFTTrait {
+                trait_type: "Background".to_string(),
+                trait_value: background.to_string(),
+                display_type: None,
+                max_value: None,
+            }]),
+            animation_url: None,
+            background_color: None,
+        };
+        insert_nft(connection, &collection, &new_nft, owner.clone())?;
+    }
+    println!(
+        "seeded collection {} with 3 NFTs owned by {}",
+        collection.contract_address, owner
+    );
+    Ok(())
+}
+
+fn run(connection: &mut PgConnection, command: Command) -> Result<(), AppError> {
+    match command {
+        Command::Migrate => {
+            let versions = models::run_pending_migrations(connection)?;
+            println!("applied {} migrations", versions.len());
+            for version in versions {
+                println!("  {}", version);
+            }
+        }
+        Command::Rollback { steps } => {
+            for _ in 0..steps {
+                let version = models::revert_last_migration(connection)?;
+                println!("reverted {}", version);
+            }
+        }
+        Command::Seed { owner } => {
+            connection.transaction(|connection| seed(connection, owner.to_lowercase()))?;
+        }
+        Command::RepublishCollection {
+            contract_address,
+            chain_id,
+        } => {
+            let count = connection.transaction(|connection| {
+                let collection_query = CollectionQuery {
+                    contract_address: Some(contract_address.clone()),
+                    chain_id: Some(chain_id),
+                    ..Default::default()
+                };
+                let collection = Collection::find_by_query(connection, collection_query)?
+                    .ok_or(AppError::CollectionNotFound)?;
+                republish_collection(connection, &collection)
+            })?;
+            println!("queued {} NFTs of {} for IPFS", count, contract_address);
+        }
+        Command::CreateAdmin { address, name } => {
+            let address = address.to_lowercase();
+            let role = Role::Admin.as_str().to_string();
+            let user = connection.transaction(|connection| {
+                if User::find_by_address(connection, address.clone())?.is_none() {
+                    InsertedUser {
+                        name,
+                        address: address.clone(),
+                        email: None,
+                        avatar_url: None,
+                    }
+                    .insert(connection)?;
+                }
+                User::update_role(connection, address.clone(), role)?.ok_or(AppError::UserNotFound)
+            })?;
+            println!("{} is now {}", user.address, user.role);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    tracing_subscriber::fmt::init();
+
+    let cli = Cli::parse();
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("invalid configuration: {}", err);
+        std::process::exit(1)
+    });
+    let result = Database::from_config(&config.database)
+        .run(move |connection| run(connection, cli.command))
+        .await;
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
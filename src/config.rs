@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use ::config::{Environment, File};
+use chrono::Duration;
+use ipfs_api::remote::RemotePinningClient;
+use reqwest::Url;
+use serde::Deserialize;
+use web3_api::chain::ChainRpcRegistry;
+
+/// Remote pinning service clients by configured name.
+pub type RemotePinningServices = HashMap<String, RemotePinningClient>;
+
+/// Directory holding `default.toml`, one `<profile>.toml` per profile and an optional,
+/// untracked `local.toml`.
+const DEFAULT_CONFIG_DIR: &str = "config";
+const PROFILES: [&str; 3] = ["dev", "test", "prod"];
+
+/// Application settings, loaded once at startup and handed to whatever needs them.
+///
+/// Sources, later ones winning: `config/default.toml`, `config/<APP_PROFILE>.toml`,
+/// `config/local.toml`, then `APP_*` environment variables with `__` between sections,
+/// e.g. `APP_SERVER__PORT=9000`. `DATABASE_URL` is honoured too, as diesel's CLI uses it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub profile: String,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub ipfs: IpfsConfig,
+    pub worker: WorkerConfig,
+    pub jwt: JwtConfig,
+    pub siwe: SiweConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub request_timeout_seconds: u64,
+    /// Reverse proxies whose `X-Forwarded-For` is believed; other peers are taken as the client.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_max_size: u32,
+    pub pool_timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IpfsConfig {
+    /// Kubo RPC endpoint, e.g. `http://127.0.0.1:5001/api/v0`.
+    pub api_url: String,
+    /// Public gateway content is linked through, e.g. `http://127.0.0.1:8080`.
+    pub gateway_url: String,
+    /// Services published content is also pinned to, for redundancy beyond our node.
+    #[serde(default)]
+    pub remote_pinning: Vec<RemotePinningConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemotePinningConfig {
+    /// Identifies the service in `remote_pins`; renaming it pins everything again.
+    pub name: String,
+    /// Pinning Service API base URL, e.g. `https://api.pinata.cloud/psa`.
+    pub endpoint: String,
+    pub access_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkerConfig {
+    pub poll_seconds: u64,
+    pub batch_size: i64,
+    /// How long a claimed job stays invisible to other workers.
+    pub lease_seconds: i64,
+    /// Interval between checks that every recorded CID is still pinned.
+    pub reconcile_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtConfig {
+    /// Legacy HS256 secret; kept as a fallback when `keys_file` is set.
+    pub secret: Option<String>,
+    /// JSON manifest of asymmetric signing keys, see `keys::KeyStore`.
+    pub keys_file: Option<PathBuf>,
+    pub keys_reload_seconds: u64,
+    pub access_token_ttl_minutes: i64,
+    pub refresh_token_ttl_days: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiweConfig {
+    /// Domain login messages must be issued for.
+    pub domain: String,
+    /// RPC endpoints used to reach contract wallets, as `<chain_id>=<url>,...`.
+    pub chain_rpc_urls: String,
+}
+
+impl Config {
+    /// Loads and validates the configuration for `APP_PROFILE` (default `dev`).
+    pub fn load() -> Result<Self, String> {
+        let dir = env::var("APP_CONFIG_DIR").unwrap_or_else(|_| DEFAULT_CONFIG_DIR.to_string());
+        let profile = env::var("APP_PROFILE").unwrap_or_else(|_| "dev".to_string());
+        Self::load_from(Path::new(&dir), &profile)
+    }
+
+    pub fn load_from(dir: &Path, profile: &str) -> Result<Self, String> {
+        if !PROFILES.contains(&profile) {
+            return Err(format!(
+                "unknown profile `{}`, expected one of {}",
+                profile,
+                PROFILES.join(", ")
+            ));
+        }
+        let config: Config = ::config::Config::builder()
+            .add_source(File::from(dir.join("default.toml")).required(false))
+            .add_source(File::from(dir.join(format!("{}.toml", profile))).required(false))
+            .add_source(File::from(dir.join("local.toml")).required(false))
+            .add_source(
+                Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .set_override("profile", profile)
+            .and_then(|builder| {
+                builder.set_override_option("database.url", env::var("DATABASE_URL").ok())
+            })
+            .and_then(|builder| builder.build())
+            .and_then(|config| config.try_deserialize())
+            .map_err(|err| format!("load configuration: {}", err))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what deserializing cannot, so a bad value fails at startup instead of on
+    /// the first request that needs it.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.database.url.starts_with("postgres://")
+            && !self.database.url.starts_with("postgresql://")
+        {
+            return Err("database.url must be a postgres:// URL".to_string());
+        }
+        if self.database.pool_max_size == 0 {
+            return Err("database.pool_max_size must be at least 1".to_string());
+        }
+        if self.database.pool_timeout_seconds == 0 {
+            return Err("database.pool_timeout_seconds must be positive".to_string());
+        }
+        if self.server.request_timeout_seconds == 0 {
+            return Err("server.request_timeout_seconds must be positive".to_string());
+        }
+        parse_http_url("ipfs.api_url", &self.ipfs.api_url)?;
+        parse_http_url("ipfs.gateway_url", &self.ipfs.gateway_url)?;
+        self.remote_pinning_services()?;
+        if self.worker.poll_seconds == 0
+            || self.worker.batch_size < 1
+            || self.worker.lease_seconds < 1
+        {
+            return Err(
+                "worker.poll_seconds, worker.batch_size and worker.lease_seconds must be positive"
+                    .to_string(),
+            );
+        }
+        if self.worker.reconcile_seconds == 0 {
+            return Err("worker.reconcile_seconds must be positive".to_string());
+        }
+        if self.jwt.secret.as_deref().unwrap_or_default().is_empty() && self.jwt.keys_file.is_none()
+        {
+            return Err("jwt.secret or jwt.keys_file must be set".to_string());
+        }
+        if self.jwt.access_token_ttl_minutes < 1 || self.jwt.refresh_token_ttl_days < 1 {
+            return Err("jwt token lifetimes must be positive".to_string());
+        }
+        if self.jwt.keys_reload_seconds == 0 {
+            return Err("jwt.keys_reload_seconds must be positive".to_string());
+        }
+        if self.siwe.domain.is_empty() {
+            return Err("siwe.domain must be set".to_string());
+        }
+        self.chain_rpcs()?;
+        Ok(())
+    }
+
+    pub fn chain_rpcs(&self) -> Result<ChainRpcRegistry, String> {
+        ChainRpcRegistry::parse(&self.siwe.chain_rpc_urls).map_err(|err| {
+            format!(
+                "siwe.chain_rpc_urls must be `<chain_id>=<url>,...`: {}",
+                err
+            )
+        })
+    }
+
+    /// One client per configured remote pinning service, by name.
+    pub fn remote_pinning_services(&self) -> Result<RemotePinningServices, String> {
+        let mut services = HashMap::new();
+        for service in &self.ipfs.remote_pinning {
+            if service.name.is_empty() || service.name.len() > 64 {
+                return Err("ipfs.remote_pinning names must be 1 to 64 characters".to_string());
+            }
+            if service.access_token.is_empty() {
+                return Err(format!(
+                    "ipfs.remote_pinning `{}` needs an access_token",
+                    service.name
+                ));
+            }
+            let endpoint = parse_http_url("ipfs.remote_pinning endpoint", &service.endpoint)?;
+            let client =
+                RemotePinningClient::new(endpoint.to_string(), service.access_token.clone())
+                    .map_err(|err| format!("ipfs.remote_pinning `{}`: {}", service.name, err))?;
+            if services.insert(service.name.clone(), client).is_some() {
+                return Err(format!(
+                    "ipfs.remote_pinning `{}` is configured twice",
+                    service.name
+                ));
+            }
+        }
+        Ok(services)
+    }
+}
+
+impl JwtConfig {
+    pub fn refresh_token_ttl(&self) -> Duration {
+        Duration::days(self.refresh_token_ttl_days)
+    }
+}
+
+fn parse_http_url(name: &str, value: &str) -> Result<Url, String> {
+    Url::parse(value)
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .ok_or_else(|| format!("{} must be an http(s) URL, got `{}`", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(profile: &str) -> Result<Config, String> {
+        Config::load_from(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_CONFIG_DIR),
+            profile,
+        )
+    }
+
+    #[test]
+    fn profile_overrides_defaults() {
+        let config = load("test").unwrap();
+        assert_eq!(config.profile, "test");
+        assert_eq!(config.jwt.access_token_ttl_minutes, 5);
+        assert_eq!(config.jwt.refresh_token_ttl_days, 30);
+        assert_eq!(config.server.port, 8000);
+        assert!(config.server.trusted_proxies.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut config = load("dev").unwrap();
+        config.ipfs.gateway_url = "not a url".to_string();
+        assert!(config.validate().unwrap_err().contains("ipfs.gateway_url"));
+        let mut config = load("dev").unwrap();
+        config.database.pool_timeout_seconds = 0;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("database.pool_timeout_seconds"));
+        let mut config = load("dev").unwrap();
+        config.server.request_timeout_seconds = 0;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("server.request_timeout_seconds"));
+        let mut config = load("dev").unwrap();
+        config.jwt.keys_reload_seconds = 0;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("jwt.keys_reload_seconds"));
+        let mut config = load("dev").unwrap();
+        config.worker.lease_seconds = 0;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("worker.lease_seconds"));
+        assert!(load("staging").is_err());
+    }
+
+    #[test]
+    fn validates_remote_pinning_services() {
+        let mut config = load("dev").unwrap();
+        assert!(config.remote_pinning_services().unwrap().is_empty());
+        let service = RemotePinningConfig {
+            name: "backup".to_string(),
+            endpoint: "https://pins.example/psa".to_string(),
+            access_token: "token".to_string(),
+        };
+        config.ipfs.remote_pinning = vec![service.clone()];
+        assert_eq!(config.remote_pinning_services().unwrap().len(), 1);
+        config.ipfs.remote_pinning = vec![service.clone(), service.clone()];
+        assert!(config.validate().unwrap_err().contains("configured twice"));
+        config.ipfs.remote_pinning = vec![RemotePinningConfig {
+            access_token: String::new(),
+            ..service
+        }];
+        assert!(config.validate().unwrap_err().contains("access_token"));
+    }
+}
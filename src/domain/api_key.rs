@@ -0,0 +1,184 @@
+use async_graphql::{Context, Object, SimpleObject};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::api_key::{ApiKey, InsertedApiKey},
+    models::Database,
+};
+
+use super::guard::{current_user_info, RequireSession, Role, Scope};
+use super::token::EncryptUserInfo;
+
+/// Prefix of every issued key, so leaked keys are easy to recognise and scan for.
+const API_KEY_PREFIX: &str = "nftm_";
+/// Characters of the key kept in clear text to tell keys apart in listings.
+const API_KEY_DISPLAY_LENGTH: usize = 12;
+/// Longest lifetime a key can be issued with.
+const API_KEY_MAX_DAYS: i32 = 3650;
+
+#[derive(Default)]
+pub struct ApiKeyMutation;
+#[derive(Default)]
+pub struct ApiKeyQuery;
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ApiKeyResult {
+    pub id: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expired_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<ApiKey> for ApiKeyResult {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            id: api_key.id.to_string(),
+            name: api_key.name,
+            key_prefix: api_key.key_prefix,
+            scopes: Scope::split(&api_key.scopes),
+            expired_at: api_key.expired_at,
+            last_used_at: api_key.last_used_at,
+            created_at: api_key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct CreateApiKeyResult {
+    /// Shown only once; send it in the `X-Api-Key` header.
+    pub key: String,
+    pub api_key: ApiKeyResult,
+}
+
+fn generate_api_key() -> String {
+    format!(
+        "{}{}{}",
+        API_KEY_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// When a key issued now for `expires_in_days` expires; `None` never expires.
+fn api_key_expiry(expires_in_days: Option<i32>) -> Result<Option<NaiveDateTime>, AppError> {
+    let Some(days) = expires_in_days else {
+        return Ok(None);
+    };
+    if !(1..=API_KEY_MAX_DAYS).contains(&days) {
+        return Err(AppError::InvalidApiKeyExpiry);
+    }
+    Utc::now()
+        .checked_add_signed(Duration::days(days as i64))
+        .map(|expired_at| Some(expired_at.naive_utc()))
+        .ok_or(AppError::InvalidApiKeyExpiry)
+}
+
+fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+/// Resolves an `X-Api-Key` header to the principal it acts as.
+/// API keys never carry more than the plain user role.
+pub async fn authenticate(database: &Database, api_key: &str) -> Result<EncryptUserInfo, AppError> {
+    let key_hash = hash_api_key(api_key);
+    let api_key = database
+        .run(move |connection| ApiKey::touch_active_by_hash(connection, key_hash))
+        .await?
+        .ok_or(AppError::InvalidApiKey)?;
+    Ok(EncryptUserInfo {
+        address: api_key.address,
+        sid: api_key.id,
+        jti: api_key.id.to_string(),
+        iat: api_key.created_at.and_utc().timestamp() as usize,
+        exp: api_key
+            .expired_at
+            .map(|expired_at| expired_at.and_utc().timestamp() as usize)
+            .unwrap_or(usize::MAX),
+        role: Role::User,
+        scopes: Some(Scope::split(&api_key.scopes)),
+    })
+}
+
+#[Object]
+impl ApiKeyMutation {
+    /// Issues an API key acting as the current user with the given scopes.
+    #[graphql(guard = "RequireSession")]
+    pub async fn create_api_key(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        scopes: Vec<Scope>,
+        expires_in_days: Option<i32>,
+    ) -> Result<CreateApiKeyResult, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let expired_at = api_key_expiry(expires_in_days)?;
+        let key = generate_api_key();
+        let inserted_api_key = InsertedApiKey {
+            address: encrypt_user_info.address.clone(),
+            name,
+            key_prefix: key[..API_KEY_DISPLAY_LENGTH].to_string(),
+            key_hash: hash_api_key(&key),
+            scopes: Scope::join(&scopes),
+            expired_at,
+        };
+        let api_key = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| inserted_api_key.insert(connection))
+            .await?;
+        Ok(CreateApiKeyResult {
+            key,
+            api_key: ApiKeyResult::from(api_key),
+        })
+    }
+
+    #[graphql(guard = "RequireSession")]
+    pub async fn revoke_api_key(&self, ctx: &Context<'_>, id: String) -> Result<bool, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let id = Uuid::parse_str(&id).map_err(|_| AppError::ApiKeyNotFound)?;
+        let address = encrypt_user_info.address.clone();
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| ApiKey::revoke(connection, id, address))
+            .await?
+            .ok_or(AppError::ApiKeyNotFound)?;
+        Ok(true)
+    }
+}
+
+#[Object]
+impl ApiKeyQuery {
+    #[graphql(guard = "RequireSession")]
+    pub async fn my_api_keys(&self, ctx: &Context<'_>) -> Result<Vec<ApiKeyResult>, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        let api_keys = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| ApiKey::list_by_address(connection, address))
+            .await?;
+        Ok(api_keys.into_iter().map(ApiKeyResult::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_is_bounded() {
+        assert_eq!(api_key_expiry(None), Ok(None));
+        assert!(api_key_expiry(Some(1)).unwrap().unwrap() > Utc::now().naive_utc());
+        assert!(api_key_expiry(Some(API_KEY_MAX_DAYS)).unwrap().is_some());
+        for days in [0, -1, API_KEY_MAX_DAYS + 1, i32::MAX, i32::MIN] {
+            assert_eq!(
+                api_key_expiry(Some(days)),
+                Err(AppError::InvalidApiKeyExpiry)
+            );
+        }
+    }
+}
@@ -1,15 +1,33 @@
-use async_graphql::{Context, InputObject, Object, SimpleObject};
-use ipfs_api::client::{Client, LocalIPFSClient};
-use ipfs_api::req::files::{MkdirQuery, MkdirRequest};
+use std::sync::Arc;
+
+use async_graphql::connection::Connection as GraphQLConnection;
+use async_graphql::{
+    ComplexObject, Context, Enum, InputObject, MaybeUndefined, Object, SimpleObject,
+};
+use chrono::NaiveDateTime;
+use diesel::{Connection, PgConnection};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{
+    config::Config,
     domain::AppResponse,
     errors::AppError,
-    models::collection::{Collection, InsertedCollection},
+    models::collection::{
+        Collection, CollectionCursor, CollectionOrder, InsertedCollection, UpdatedCollection,
+    },
+    models::ipfs_job::IpfsJob,
+    models::nft::NFT,
+    models::Database,
+    siwe::is_address,
 };
 
-use super::token::Token;
+use super::file::IPFSFile;
+use super::guard::{current_user_info, RequireCollectionOwner, RequireScope, Scope};
+use super::ipfs_job::{self, IpfsOperation};
+use super::nft::{enqueue_collection_metadata, republish_collection, MetadataFormat};
+use super::pagination::{self, Cursor};
+use super::pin::enqueue_remote_unpins;
 
 #[derive(Default)]
 pub struct CollectionMutation;
@@ -25,6 +43,60 @@ pub struct NewCollection {
     pub chain_id: i32,
     pub dir_name: String,
     pub dir_hash: String,
+    /// Defaults to `OPEN_SEA`.
+    pub metadata_format: Option<MetadataFormat>,
+    pub description: Option<String>,
+    pub banner_url: Option<String>,
+    pub external_link: Option<String>,
+    /// Secondary sale royalty, in hundredths of a percent. Defaults to 0.
+    pub seller_fee_basis_points: Option<i32>,
+    /// Address receiving the royalty.
+    pub fee_recipient: Option<String>,
+    /// Creates the collection unrevealed: every token shows this until `revealCollection`.
+    pub placeholder: Option<NewPlaceholder>,
+}
+
+/// Metadata shared by all tokens of an unrevealed collection.
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
+pub struct NewPlaceholder {
+    /// Defaults to the collection name.
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image_url: String,
+}
+
+/// Fields to change; omitted ones are left as they are and `null` clears optional ones.
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
+pub struct UpdateCollection {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub pic_url: Option<String>,
+    /// Changing it rewrites every NFT's metadata file.
+    pub metadata_format: Option<MetadataFormat>,
+    pub description: MaybeUndefined<String>,
+    pub banner_url: MaybeUndefined<String>,
+    pub external_link: MaybeUndefined<String>,
+    pub seller_fee_basis_points: Option<i32>,
+    pub fee_recipient: MaybeUndefined<String>,
+}
+
+impl From<UpdateCollection> for UpdatedCollection {
+    fn from(changes: UpdateCollection) -> Self {
+        Self {
+            name: changes.name,
+            symbol: changes.symbol,
+            pic_url: changes.pic_url,
+            metadata_format: changes
+                .metadata_format
+                .map(|metadata_format| metadata_format.as_str().to_string()),
+            description: changes.description.into(),
+            banner_url: changes.banner_url.into(),
+            external_link: changes.external_link.into(),
+            seller_fee_basis_points: changes.seller_fee_basis_points,
+            fee_recipient: Option::<Option<String>>::from(changes.fee_recipient)
+                .map(|fee_recipient| fee_recipient.map(|address| address.to_lowercase())),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
@@ -38,6 +110,14 @@ pub struct CreateCollectionResult {
     pub chain_id: i32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct RevealCollectionResult {
+    pub collection: CollectionResult,
+    /// NFTs whose real metadata was queued. Once the collection's `unfinishedJobs` is back
+    /// to 0, its `dirHash` holds the directory for the contract's `setBaseURI`.
+    pub queued: i32,
+}
+
 impl From<Collection> for CreateCollectionResult {
     fn from(collection: Collection) -> Self {
         Self {
@@ -52,6 +132,125 @@ impl From<Collection> for CreateCollectionResult {
     }
 }
 
+/// Collection-level metadata in OpenSea's `contractURI` format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContractMetadata {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banner_image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_link: Option<String>,
+    pub seller_fee_basis_points: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_recipient: Option<String>,
+}
+
+impl ContractMetadata {
+    pub fn render(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// The metadata once `changes` are saved.
+    fn updated(mut self, changes: &UpdatedCollection) -> Self {
+        if let Some(name) = &changes.name {
+            self.name = name.clone();
+        }
+        if let Some(description) = &changes.description {
+            self.description = description.clone();
+        }
+        if let Some(pic_url) = &changes.pic_url {
+            self.image = pic_url.clone();
+        }
+        if let Some(banner_url) = &changes.banner_url {
+            self.banner_image = banner_url.clone();
+        }
+        if let Some(external_link) = &changes.external_link {
+            self.external_link = external_link.clone();
+        }
+        if let Some(seller_fee_basis_points) = changes.seller_fee_basis_points {
+            self.seller_fee_basis_points = seller_fee_basis_points;
+        }
+        if let Some(fee_recipient) = &changes.fee_recipient {
+            self.fee_recipient = fee_recipient.clone();
+        }
+        self
+    }
+}
+
+impl From<&Collection> for ContractMetadata {
+    fn from(collection: &Collection) -> Self {
+        Self {
+            name: collection.name.clone(),
+            description: collection.description.clone(),
+            image: collection.pic_url.clone(),
+            banner_image: collection.banner_url.clone(),
+            external_link: collection.external_link.clone(),
+            seller_fee_basis_points: collection.seller_fee_basis_points,
+            fee_recipient: collection.fee_recipient.clone(),
+        }
+    }
+}
+
+impl From<&InsertedCollection> for ContractMetadata {
+    fn from(collection: &InsertedCollection) -> Self {
+        Self {
+            name: collection.name.clone(),
+            description: collection.description.clone(),
+            image: collection.pic_url.clone(),
+            banner_image: collection.banner_url.clone(),
+            external_link: collection.external_link.clone(),
+            seller_fee_basis_points: collection.seller_fee_basis_points,
+            fee_recipient: collection.fee_recipient.clone(),
+        }
+    }
+}
+
+/// Royalties are capped at 100% and must go to an address.
+pub fn validate_contract_metadata(metadata: &ContractMetadata) -> Result<(), AppError> {
+    if !(0..=10_000).contains(&metadata.seller_fee_basis_points) {
+        return Err(AppError::InvalidCollectionMetadata);
+    }
+    match &metadata.fee_recipient {
+        Some(fee_recipient) if !is_address(fee_recipient) => {
+            Err(AppError::InvalidCollectionMetadata)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The collection's `contract.json`, for `contractURI` lookups.
+pub fn find_contract_metadata(
+    connection: &mut PgConnection,
+    chain_id: i32,
+    contract_address: String,
+) -> Result<String, AppError> {
+    let collection = find_collection(connection, chain_id, contract_address)?;
+    Ok(ContractMetadata::from(&collection).render())
+}
+
+/// Name of the collection metadata file in the collection directory.
+pub const CONTRACT_METADATA_FILE: &str = "contract.json";
+
+pub fn contract_metadata_path(collection: &Collection) -> String {
+    format!("/{}/{}", collection.dir_name, CONTRACT_METADATA_FILE)
+}
+
+/// Queues the collection's `contract.json`. The caller queues the flush.
+pub fn enqueue_contract_metadata(
+    connection: &mut PgConnection,
+    collection: &Collection,
+) -> Result<(), AppError> {
+    let write = IpfsOperation::Write {
+        path: contract_metadata_path(collection),
+        content: ContractMetadata::from(collection).render(),
+    };
+    ipfs_job::enqueue(connection, &write, Some(collection.id), None)?;
+    Ok(())
+}
+
 pub fn convert_to_inserted_collection(
     new_collection: &NewCollection,
     owner: String,
@@ -61,53 +260,212 @@ pub fn convert_to_inserted_collection(
         symbol: new_collection.symbol.clone(),
         owner,
         pic_url: new_collection.pic_url.clone(),
-        contract_address: new_collection.contract_address.clone(),
+        contract_address: new_collection.contract_address.to_lowercase(),
         chain_id: new_collection.chain_id,
         dir_name: new_collection.dir_name.clone(),
         dir_hash: new_collection.dir_hash.clone(),
+        metadata_format: new_collection
+            .metadata_format
+            .unwrap_or_default()
+            .as_str()
+            .to_string(),
+        description: new_collection.description.clone(),
+        banner_url: new_collection.banner_url.clone(),
+        external_link: new_collection.external_link.clone(),
+        seller_fee_basis_points: new_collection.seller_fee_basis_points.unwrap_or_default(),
+        fee_recipient: new_collection
+            .fee_recipient
+            .as_ref()
+            .map(|address| address.to_lowercase()),
+        contract_cid: None,
+        revealed: new_collection.placeholder.is_none(),
+        placeholder_name: new_collection
+            .placeholder
+            .as_ref()
+            .and_then(|placeholder| placeholder.name.clone()),
+        placeholder_description: new_collection
+            .placeholder
+            .as_ref()
+            .and_then(|placeholder| placeholder.description.clone()),
+        placeholder_image_url: new_collection
+            .placeholder
+            .as_ref()
+            .map(|placeholder| placeholder.image_url.clone()),
     }
 }
 
+pub fn find_collection(
+    connection: &mut PgConnection,
+    chain_id: i32,
+    contract_address: String,
+) -> Result<Collection, AppError> {
+    let collection_query = crate::models::collection::CollectionQuery {
+        contract_address: Some(contract_address),
+        chain_id: Some(chain_id),
+        ..Default::default()
+    };
+    Collection::find_by_query(connection, collection_query)?.ok_or(AppError::CollectionNotFound)
+}
+
+/// Inserts the collection and queues its IPFS directory and `contract.json`.
+/// Run it inside a transaction.
+pub fn insert_collection(
+    connection: &mut PgConnection,
+    inserted_collection: &InsertedCollection,
+) -> Result<Collection, AppError> {
+    let collection = inserted_collection.insert(connection)?;
+    let mkdir = IpfsOperation::Mkdir {
+        path: collection.contract_address.clone(),
+    };
+    ipfs_job::enqueue(connection, &mkdir, Some(collection.id), None)?;
+    enqueue_contract_metadata(connection, &collection)?;
+    ipfs_job::enqueue_directory_pin(connection, &collection)?;
+    Ok(collection)
+}
+
 #[Object]
 impl CollectionMutation {
+    /// Creates the collection and queues its `contract.json`. The file's CID is only known
+    /// once the worker has published it, so read it from the `collection` query's
+    /// `contractMetadata` afterwards. Alternatively point `contractURI` at
+    /// `/metadata/:chain_id/:contract_address/contract.json`, served right away.
+    #[graphql(guard = "RequireScope(Scope::WriteCollections)")]
     pub async fn create_collection<'a>(
         &self,
         ctx: &Context<'_>,
         new_collection: NewCollection,
     ) -> AppResponse<CreateCollectionResult> {
         tracing::info!("Creating collection: {:?}", &new_collection);
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
+        let encrypt_user_info = current_user_info(ctx)?;
         tracing::info!(
             "Creating collection for user: {}",
             encrypt_user_info.address
         );
 
         // Create a new collection
-        let collection = convert_to_inserted_collection(&new_collection, encrypt_user_info.address)
-            .insert()
+        let inserted_collection =
+            convert_to_inserted_collection(&new_collection, encrypt_user_info.address.clone());
+        validate_contract_metadata(&ContractMetadata::from(&inserted_collection))?;
+        let collection = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                connection
+                    .transaction(|connection| insert_collection(connection, &inserted_collection))
+            })
             .await?;
 
-        // mkdir collection dir in IPFS
-        let mkdir_request = MkdirRequest {
-            query: MkdirQuery::new_with_arg(&new_collection.contract_address),
-        };
-        let _ = LocalIPFSClient::default()
-            .files_mkdir(mkdir_request)
+        Ok(Some(CreateCollectionResult::from(collection)))
+    }
+
+    #[graphql(
+        guard = "RequireScope(Scope::WriteCollections).and(RequireCollectionOwner::new(chain_id, &contract_address))"
+    )]
+    pub async fn update_collection(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        contract_address: String,
+        changes: UpdateCollection,
+    ) -> Result<CollectionResult, AppError> {
+        let changes = UpdatedCollection::from(changes);
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    // Locked, so concurrent updates compare against each other's changes.
+                    let collection =
+                        Collection::find_for_update(connection, chain_id, contract_address)?
+                            .ok_or(AppError::CollectionNotFound)?;
+                    let current_metadata = ContractMetadata::from(&collection);
+                    let contract_metadata = current_metadata.clone().updated(&changes);
+                    let contract_metadata_changed =
+                        contract_metadata != current_metadata || collection.contract_cid.is_none();
+                    if contract_metadata_changed {
+                        validate_contract_metadata(&contract_metadata)?;
+                    }
+                    let updated = Collection::update(connection, collection.id, &changes)?;
+                    if updated.metadata_format != collection.metadata_format {
+                        republish_collection(connection, &updated)?;
+                    } else if contract_metadata_changed {
+                        enqueue_contract_metadata(connection, &updated)?;
+                        ipfs_job::enqueue_directory_pin(connection, &updated)?;
+                    }
+                    Ok(updated)
+                })
+            })
             .await
-            .map_err(|err| {
-                tracing::error!("IPFS mkdir error: {:?}", err);
-                AppError::RequestIpfsError
-            })?;
+            .map(CollectionResult::from)
+    }
 
-        Ok(Some(CreateCollectionResult::from(collection)))
+    /// Marks the collection revealed and queues the real metadata of every NFT over its
+    /// placeholder, then the directory pin. NFTs minted afterwards are published as usual.
+    ///
+    /// The new directory CID is not known yet when this returns. Poll the `collection` query
+    /// until `unfinishedJobs` is 0, then pass its `dirHash` to the contract's `setBaseURI`.
+    /// Jobs that ran out of attempts are listed by `ipfsJobs` with status `FAILED`.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteCollections).and(RequireCollectionOwner::new(chain_id, &contract_address))"
+    )]
+    pub async fn reveal_collection(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        contract_address: String,
+    ) -> Result<RevealCollectionResult, AppError> {
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    // Locked, so NFTs minted meanwhile wait and are published revealed.
+                    let collection =
+                        Collection::find_for_update(connection, chain_id, contract_address)?
+                            .ok_or(AppError::CollectionNotFound)?;
+                    if collection.revealed {
+                        return Err(AppError::CollectionAlreadyRevealed);
+                    }
+                    // A placeholder write still queued could land after the real file.
+                    if IpfsJob::count_unfinished(connection, collection.id)? > 0 {
+                        return Err(AppError::IpfsJobsPending);
+                    }
+                    let collection = Collection::reveal(connection, collection.id)?
+                        .ok_or(AppError::CollectionAlreadyRevealed)?;
+                    let queued = enqueue_collection_metadata(connection, &collection)?;
+                    ipfs_job::enqueue_directory_pin(connection, &collection)?;
+                    Ok(RevealCollectionResult {
+                        collection: CollectionResult::from(collection),
+                        queued: queued as i32,
+                    })
+                })
+            })
+            .await
+    }
+
+    /// Hides the collection and its NFTs, and queues the removal of its remote pins. Files
+    /// already on the node are left in place.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteCollections).and(RequireCollectionOwner::new(chain_id, &contract_address))"
+    )]
+    pub async fn delete_collection(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        contract_address: String,
+    ) -> Result<bool, AppError> {
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    let collection =
+                        find_collection(connection, chain_id, contract_address.clone())?;
+                    Collection::soft_delete(connection, collection.id)?;
+                    NFT::soft_delete_by_collection(connection, chain_id, contract_address)?;
+                    enqueue_remote_unpins(connection, collection.id)?;
+                    Ok(true)
+                })
+            })
+            .await
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct CollectionResult {
     pub id: String,
     pub name: String,
@@ -116,6 +474,44 @@ pub struct CollectionResult {
     pub pic_url: String,
     pub contract_address: String,
     pub chain_id: i32,
+    pub metadata_format: MetadataFormat,
+    pub description: Option<String>,
+    pub banner_url: Option<String>,
+    pub external_link: Option<String>,
+    pub seller_fee_basis_points: i32,
+    pub fee_recipient: Option<String>,
+    pub revealed: bool,
+    #[graphql(skip)]
+    pub dir_hash: String,
+    #[graphql(skip)]
+    pub contract_cid: Option<String>,
+}
+
+#[ComplexObject]
+impl CollectionResult {
+    /// Hash of the collection's IPFS directory. Only its owner may see it.
+    #[graphql(guard = "RequireCollectionOwner::new(self.chain_id, &self.contract_address)")]
+    async fn dir_hash(&self) -> String {
+        self.dir_hash.clone()
+    }
+
+    /// Publishing jobs still queued or running. `dirHash` is final once there are none.
+    #[graphql(guard = "RequireCollectionOwner::new(self.chain_id, &self.contract_address)")]
+    async fn unfinished_jobs(&self, ctx: &Context<'_>) -> Result<i32, AppError> {
+        let id = Uuid::parse_str(&self.id).map_err(|_| AppError::CollectionNotFound)?;
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| IpfsJob::count_unfinished(connection, id))
+            .await
+            .map(|count| count as i32)
+    }
+
+    /// The collection's `contract.json`, for the contract's `contractURI`.
+    async fn contract_metadata(&self, ctx: &Context<'_>) -> Option<IPFSFile> {
+        let gateway_url = &ctx.data_unchecked::<Arc<Config>>().ipfs.gateway_url;
+        self.contract_cid
+            .as_ref()
+            .map(|cid| IPFSFile::new(gateway_url, cid))
+    }
 }
 
 impl From<Collection> for CollectionResult {
@@ -128,6 +524,15 @@ impl From<Collection> for CollectionResult {
             pic_url: collection.pic_url,
             contract_address: collection.contract_address,
             chain_id: collection.chain_id,
+            metadata_format: MetadataFormat::parse(&collection.metadata_format),
+            description: collection.description,
+            banner_url: collection.banner_url,
+            external_link: collection.external_link,
+            seller_fee_basis_points: collection.seller_fee_basis_points,
+            fee_recipient: collection.fee_recipient,
+            revealed: collection.revealed,
+            dir_hash: collection.dir_hash,
+            contract_cid: collection.contract_cid,
         }
     }
 }
@@ -135,41 +540,238 @@ impl From<Collection> for CollectionResult {
 #[derive(Serialize, Deserialize, InputObject)]
 pub struct FindCollectionInput {
     pub collection_address: Option<String>,
+    pub chain_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, InputObject)]
+pub struct CollectionFilter {
+    pub owner: Option<String>,
+    pub chain_id: Option<i32>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+}
+
+impl From<CollectionFilter> for crate::models::collection::CollectionQuery {
+    fn from(filter: CollectionFilter) -> Self {
+        Self {
+            owner: filter.owner,
+            chain_id: filter.chain_id,
+            created_after: filter.created_after,
+            created_before: filter.created_before,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+pub enum CollectionOrderBy {
+    CreatedAtAsc,
+    #[default]
+    CreatedAtDesc,
+    NameAsc,
+    NameDesc,
+}
+
+impl From<CollectionOrderBy> for CollectionOrder {
+    fn from(order_by: CollectionOrderBy) -> Self {
+        match order_by {
+            CollectionOrderBy::CreatedAtAsc => CollectionOrder::CreatedAtAsc,
+            CollectionOrderBy::CreatedAtDesc => CollectionOrder::CreatedAtDesc,
+            CollectionOrderBy::NameAsc => CollectionOrder::NameAsc,
+            CollectionOrderBy::NameDesc => CollectionOrder::NameDesc,
+        }
+    }
 }
 
 #[Object]
 impl CollectionQuery {
+    /// A collection by its contract. Public.
+    pub async fn collection(
+        &self,
+        ctx: &Context<'_>,
+        contract_address: String,
+        chain_id: i32,
+    ) -> AppResponse<CollectionResult> {
+        let collection_query = crate::models::collection::CollectionQuery {
+            contract_address: Some(contract_address),
+            chain_id: Some(chain_id),
+            ..Default::default()
+        };
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| Collection::find_by_query(connection, collection_query))
+            .await
+            .map(|collection| collection.map(CollectionResult::from))
+    }
+
+    /// Collections matching `filter`, newest first unless `orderBy` says otherwise. Public.
+    pub async fn collections(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: CollectionFilter,
+        #[graphql(default)] order_by: CollectionOrderBy,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<GraphQLConnection<Cursor<CollectionCursor>, CollectionResult>, AppError> {
+        let page_size = pagination::page_size(first)?;
+        let after = pagination::decode_after::<CollectionCursor>(after)?;
+        let has_previous_page = after.is_some();
+        let collection_query = crate::models::collection::CollectionQuery::from(filter);
+        let collections = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                Collection::list(
+                    connection,
+                    &collection_query,
+                    order_by.into(),
+                    after,
+                    page_size + 1,
+                )
+            })
+            .await?;
+        Ok(pagination::into_connection(
+            collections,
+            page_size,
+            has_previous_page,
+            |collection: &Collection| CollectionCursor::from(collection),
+            CollectionResult::from,
+        ))
+    }
+
+    #[graphql(guard = "RequireScope(Scope::Read)")]
     pub async fn list_collections_for_owner(
         &self,
         ctx: &Context<'_>,
     ) -> Result<Vec<CollectionResult>, AppError> {
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-        let collections = Collection::find_by_owner(encrypt_user_info.address).await?;
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        let collections = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| Collection::find_by_owner(connection, address))
+            .await?;
         Ok(collections
             .into_iter()
             .map(CollectionResult::from)
             .collect())
     }
 
+    #[graphql(guard = "RequireScope(Scope::Read)")]
     pub async fn find_collection_for_owner(
         &self,
         ctx: &Context<'_>,
         input: FindCollectionInput,
     ) -> AppResponse<CollectionResult> {
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
+        let encrypt_user_info = current_user_info(ctx)?;
         let collection_query = crate::models::collection::CollectionQuery {
-            owner: Some(encrypt_user_info.address),
+            owner: Some(encrypt_user_info.address.clone()),
             contract_address: input.collection_address,
+            chain_id: input.chain_id,
             ..Default::default()
         };
-        Collection::find_by_query(collection_query)
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| Collection::find_by_query(connection, collection_query))
             .await
             .map(|collection| collection.map(CollectionResult::from))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract_metadata() -> ContractMetadata {
+        ContractMetadata {
+            name: "Demo".to_string(),
+            description: Some("A demo collection".to_string()),
+            image: "ipfs://image".to_string(),
+            banner_image: None,
+            external_link: None,
+            seller_fee_basis_points: 250,
+            fee_recipient: Some("0x0000000000000000000000000000000000000001".to_string()),
+        }
+    }
+
+    #[test]
+    fn contract_metadata_follows_changes() {
+        let changes = UpdatedCollection {
+            symbol: Some("DEMO2".to_string()),
+            description: Some(None),
+            banner_url: Some(Some("ipfs://banner".to_string())),
+            ..Default::default()
+        };
+        let updated = contract_metadata().updated(&changes);
+        assert_eq!(updated.description, None);
+        assert_eq!(updated.banner_image.as_deref(), Some("ipfs://banner"));
+        assert_eq!(updated.name, "Demo");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&updated.render()).unwrap(),
+            serde_json::json!({
+                "name": "Demo",
+                "image": "ipfs://image",
+                "banner_image": "ipfs://banner",
+                "seller_fee_basis_points": 250,
+                "fee_recipient": "0x0000000000000000000000000000000000000001"
+            })
+        );
+        assert_eq!(
+            contract_metadata().updated(&UpdatedCollection::default()),
+            contract_metadata()
+        );
+    }
+
+    #[test]
+    fn omitted_fields_are_not_updated() {
+        let changes = UpdatedCollection::from(UpdateCollection {
+            name: Some("Renamed".to_string()),
+            symbol: None,
+            pic_url: None,
+            metadata_format: None,
+            description: MaybeUndefined::Null,
+            banner_url: MaybeUndefined::Undefined,
+            external_link: MaybeUndefined::Value("https://example.com".to_string()),
+            seller_fee_basis_points: None,
+            fee_recipient: MaybeUndefined::Value(
+                "0x00000000000000000000000000000000000000AB".to_string(),
+            ),
+        });
+        assert_eq!(changes.name.as_deref(), Some("Renamed"));
+        assert_eq!(changes.symbol, None);
+        assert_eq!(changes.description, Some(None));
+        assert_eq!(changes.banner_url, None);
+        assert_eq!(
+            changes.external_link,
+            Some(Some("https://example.com".to_string()))
+        );
+        assert_eq!(
+            changes.fee_recipient,
+            Some(Some(
+                "0x00000000000000000000000000000000000000ab".to_string()
+            ))
+        );
+        let updated = contract_metadata().updated(&changes);
+        assert_eq!(updated.name, "Renamed");
+        assert_eq!(updated.description, None);
+        assert_eq!(updated.image, "ipfs://image");
+        assert_eq!(updated.seller_fee_basis_points, 250);
+    }
+
+    #[test]
+    fn royalties_are_validated() {
+        assert!(validate_contract_metadata(&contract_metadata()).is_ok());
+        let too_high = ContractMetadata {
+            seller_fee_basis_points: 10_001,
+            ..contract_metadata()
+        };
+        assert_eq!(
+            validate_contract_metadata(&too_high),
+            Err(AppError::InvalidCollectionMetadata)
+        );
+        let bad_recipient = ContractMetadata {
+            fee_recipient: Some("alice".to_string()),
+            ..contract_metadata()
+        };
+        assert_eq!(
+            validate_contract_metadata(&bad_recipient),
+            Err(AppError::InvalidCollectionMetadata)
+        );
+    }
+}
@@ -1,32 +1,48 @@
-use async_graphql::{Context, Object, SimpleObject, Upload};
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, SimpleObject, Subscription, Upload};
+use diesel::Connection;
+use futures_util::Stream;
 use ipfs_api::client::{Client, LocalIPFSClient};
 use ipfs_api::req::{
-    add::AddRequest,
+    add::{AddQuery, AddRequest},
     files::{MkdirQuery, MkdirRequest, StatQuery, StatRequest},
 };
 use ipfs_api::resp::add::AddResponse;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
 use uuid::Uuid;
 
-use crate::{errors::AppError, util::parse_upload};
+use crate::{
+    config::Config,
+    errors::AppError,
+    models::Database,
+    util::{parse_upload, UploadStream},
+};
 
-use super::token::Token;
+use super::guard::{current_user_info, RequireScope, Scope};
+use super::pin::{enqueue_remote_pins, record_image_pin};
 use super::AppResponse;
 
+/// Progress events buffered per subscriber before the slowest ones start skipping.
+const UPLOAD_PROGRESS_CAPACITY: usize = 256;
+
 #[derive(Default)]
 pub struct FileMutation;
+#[derive(Default)]
+pub struct FileSubscription;
 
-#[derive(Serialize, Deserialize, Debug, Default, SimpleObject)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
 pub struct IPFSFile {
     pub url: String,
     pub hash: String,
 }
 
 impl IPFSFile {
-    pub fn new(hash: &String) -> Self {
+    pub fn new(gateway_url: &str, hash: &String) -> Self {
         Self {
             hash: hash.clone(),
-            url: generate_url_by_hash(hash),
+            url: generate_url_by_hash(gateway_url, hash),
         }
     }
 }
@@ -39,77 +55,270 @@ pub struct IPFSFileStat {
 }
 
 impl IPFSFileStat {
-    pub fn new(name: &String, hash: &String) -> Self {
+    pub fn new(gateway_url: &str, name: &String, hash: &String) -> Self {
         Self {
             name: name.clone(),
             hash: hash.clone(),
-            url: generate_url_by_hash(hash),
+            url: generate_url_by_hash(gateway_url, hash),
+        }
+    }
+}
+
+/// Progress of an `uploadFile` call that was given an `uploadId`.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct UploadProgress {
+    pub upload_id: String,
+    /// Bytes the IPFS node has processed so far.
+    pub bytes: i64,
+    pub total: i64,
+    /// Set on the last event; `file` is `None` then if the upload failed.
+    pub done: bool,
+    pub file: Option<IPFSFile>,
+    /// Address of the uploader, the only one who may follow the upload.
+    #[graphql(skip)]
+    pub owner: String,
+}
+
+impl UploadProgress {
+    fn new(upload_id: String, owner: String, total: i64) -> Self {
+        Self {
+            upload_id,
+            bytes: 0,
+            total,
+            done: false,
+            file: None,
+            owner,
+        }
+    }
+
+    fn at(&self, bytes: i64) -> Self {
+        Self {
+            bytes,
+            ..self.clone()
+        }
+    }
+
+    fn finished(&self, file: Option<IPFSFile>) -> Self {
+        Self {
+            bytes: if file.is_some() {
+                self.total
+            } else {
+                self.bytes
+            },
+            done: true,
+            file,
+            ..self.clone()
         }
     }
 }
 
-pub fn generate_url_by_hash(hash: &String) -> String {
-    format!("http://127.0.0.1:8080/ipfs/{}", hash.clone())
+/// Fans upload progress out to `uploadProgress` subscribers.
+#[derive(Clone)]
+pub struct UploadProgressHub {
+    sender: broadcast::Sender<UploadProgress>,
+}
+
+impl UploadProgressHub {
+    pub fn publish(&self, progress: UploadProgress) {
+        // no subscriber is not an error
+        let _ = self.sender.send(progress);
+    }
+
+    /// Events of `upload_id` by `owner`, ending after the last one.
+    pub fn subscribe(
+        &self,
+        upload_id: String,
+        owner: String,
+    ) -> impl Stream<Item = UploadProgress> + Send + 'static {
+        let receiver = self.sender.subscribe();
+        futures_util::stream::unfold(Some(receiver), move |receiver| {
+            let (upload_id, owner) = (upload_id.clone(), owner.clone());
+            async move {
+                let mut receiver = receiver?;
+                loop {
+                    match receiver.recv().await {
+                        Ok(progress)
+                            if progress.upload_id == upload_id && progress.owner == owner =>
+                        {
+                            let receiver = if progress.done { None } else { Some(receiver) };
+                            return Some((progress, receiver));
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Default for UploadProgressHub {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(UPLOAD_PROGRESS_CAPACITY);
+        Self { sender }
+    }
+}
+
+pub fn generate_url_by_hash(gateway_url: &str, hash: &String) -> String {
+    format!("{}/ipfs/{}", gateway_url.trim_end_matches('/'), hash)
+}
+
+/// Streams `upload` to IPFS, publishing its progress if it has an id, then records the pin.
+async fn add_upload(
+    ctx: &Context<'_>,
+    upload: UploadStream,
+    progress: Option<&UploadProgress>,
+    hub: &UploadProgressHub,
+) -> Result<IPFSFile, AppError> {
+    let add_request = AddRequest::new_with_stream(
+        AddQuery::default(),
+        upload.filename,
+        upload.body,
+        Some(upload.length),
+    );
+    let client = ctx.data_unchecked::<LocalIPFSClient>();
+    let response: AddResponse = match progress {
+        Some(progress) => {
+            client
+                .add_with_progress(add_request, |bytes| hub.publish(progress.at(bytes)))
+                .await
+        }
+        None => client.add(add_request).await,
+    }
+    .map_err(|err| {
+        tracing::error!("upload file to ipfs error: {:?}", err);
+        AppError::RequestIpfsFailed
+    })?;
+    let hash = response.hash.ok_or(AppError::HashMismatch)?;
+    let config = ctx.data_unchecked::<Arc<Config>>();
+    let cid = hash.clone();
+    let services: Vec<String> = config
+        .ipfs
+        .remote_pinning
+        .iter()
+        .map(|service| service.name.clone())
+        .collect();
+    ctx.data_unchecked::<Database>()
+        .run(move |connection| {
+            connection.transaction(|connection| {
+                record_image_pin(connection, cid.clone())?;
+                enqueue_remote_pins(connection, &services, &cid, None)
+            })
+        })
+        .await?;
+    Ok(IPFSFile::new(&config.ipfs.gateway_url, &hash))
 }
 
 #[Object]
 impl FileMutation {
-    async fn upload_file(&self, ctx: &Context<'_>, file: Upload) -> AppResponse<IPFSFile> {
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-        let (filename, bytes) = parse_upload(ctx, file)?;
-        let add_request = AddRequest::new_with_file(filename, bytes);
-        let response: AddResponse =
-            LocalIPFSClient::default()
-                .add(add_request)
-                .await
-                .map_err(|err| {
-                    tracing::error!("upload file to ipfs error: {:?}", err);
-                    AppError::RequestIpfsFailed
-                })?;
-        response
-            .hash
-            .map(|hash| Some(IPFSFile::new(&hash)))
-            .ok_or(AppError::HashMismatch)
+    /// Streams the file to IPFS, pinned, records the pin for reconciliation and queues it for
+    /// the remote pinning services. With an `uploadId`, progress is published to
+    /// `uploadProgress`.
+    #[graphql(guard = "RequireScope(Scope::UploadFiles)")]
+    async fn upload_file(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+        upload_id: Option<String>,
+    ) -> AppResponse<IPFSFile> {
+        let upload = parse_upload(ctx, file)?;
+        let progress = upload_id.map(|upload_id| {
+            let owner = current_user_info(ctx)
+                .map(|user_info| user_info.address.clone())
+                .unwrap_or_default();
+            UploadProgress::new(upload_id, owner, upload.length as i64)
+        });
+        let hub = ctx.data_unchecked::<UploadProgressHub>();
+        let result = add_upload(ctx, upload, progress.as_ref(), hub).await;
+        if let Some(progress) = &progress {
+            hub.publish(progress.finished(result.clone().ok()));
+        }
+        result.map(Some)
     }
 
+    #[graphql(guard = "RequireScope(Scope::UploadFiles)")]
     async fn files_mkdir(&self, ctx: &Context<'_>) -> AppResponse<IPFSFileStat> {
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-
         // generate dir name
         let arg = Uuid::new_v4().to_string();
         // mkdir
         let mkdir_request = MkdirRequest {
             query: MkdirQuery::new_with_arg(&arg),
         };
-        let _ = LocalIPFSClient::default()
-            .files_mkdir(mkdir_request)
-            .await
-            .map_err(|err| {
-                tracing::error!("mkdir to ipfs error: {:?}", err);
-                AppError::RequestIpfsFailed
-            })?;
+        let client = ctx.data_unchecked::<LocalIPFSClient>();
+        let _ = client.files_mkdir(mkdir_request).await.map_err(|err| {
+            tracing::error!("mkdir to ipfs error: {:?}", err);
+            AppError::RequestIpfsFailed
+        })?;
         // get dir hash
         let stat_request = StatRequest {
             query: StatQuery::new_with_arg(&arg),
         };
-        let response = LocalIPFSClient::default()
-            .files_stat(stat_request)
-            .await
-            .map_err(|err| {
-                tracing::error!("ipfs files stat error: {:?}", err);
-                AppError::RequestIpfsFailed
-            })?;
+        let response = client.files_stat(stat_request).await.map_err(|err| {
+            tracing::error!("ipfs files stat error: {:?}", err);
+            AppError::RequestIpfsFailed
+        })?;
+        let gateway_url = &ctx.data_unchecked::<Arc<Config>>().ipfs.gateway_url;
         response
             .hash
-            .map(|hash| Some(IPFSFileStat::new(&arg, &hash)))
+            .map(|hash| Some(IPFSFileStat::new(gateway_url, &arg, &hash)))
             .ok_or(AppError::HashMismatch)
     }
 }
+
+#[Subscription]
+impl FileSubscription {
+    /// Progress of the caller's `uploadFile` call with the same `uploadId`. Subscribe before
+    /// starting the upload; the stream ends with the event that has `done` set.
+    #[graphql(guard = "RequireScope(Scope::UploadFiles)")]
+    async fn upload_progress(
+        &self,
+        ctx: &Context<'_>,
+        upload_id: String,
+    ) -> Result<impl Stream<Item = UploadProgress>, AppError> {
+        let owner = current_user_info(ctx)?.address.clone();
+        Ok(ctx
+            .data_unchecked::<UploadProgressHub>()
+            .subscribe(upload_id, owner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn subscribers_only_see_their_own_upload_until_it_is_done() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let hub = UploadProgressHub::default();
+            let events = hub.subscribe("u1".to_string(), "0xowner".to_string());
+            let progress = UploadProgress::new("u1".to_string(), "0xowner".to_string(), 10);
+            let other = UploadProgress::new("u1".to_string(), "0xother".to_string(), 10);
+            hub.publish(progress.at(4));
+            hub.publish(other.at(5));
+            hub.publish(
+                progress.finished(Some(IPFSFile::new("http://gateway", &"QmFile".to_string()))),
+            );
+            hub.publish(progress.at(7));
+
+            let events: Vec<UploadProgress> = events.collect().await;
+            assert_eq!(events.len(), 2);
+            assert_eq!((events[0].bytes, events[0].done), (4, false));
+            assert_eq!((events[1].bytes, events[1].done), (10, true));
+            assert_eq!(events[1].file.as_ref().unwrap().hash, "QmFile");
+        });
+    }
+
+    #[test]
+    fn failed_uploads_keep_their_last_progress() {
+        let progress = UploadProgress::new("u1".to_string(), "0xowner".to_string(), 10).at(6);
+        let failed = progress.finished(None);
+        assert!(failed.done);
+        assert_eq!(failed.bytes, 6);
+        assert!(failed.file.is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use std::str::FromStr;
+
+use async_graphql::{Context, Enum, Guard};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::AppError,
+    models::collection::{Collection, CollectionQuery},
+    models::Database,
+};
+
+use super::api_key;
+use super::token::{EncryptUserInfo, Token};
+
+/// Roles are ordered by privilege: every role includes the ones before it.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Enum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(AppError::InvalidRole),
+        }
+    }
+}
+
+/// What an API key may be used for. Scopes are stored space separated on the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    WriteCollections,
+    WriteNfts,
+    UploadFiles,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::WriteCollections => "write_collections",
+            Scope::WriteNfts => "write_nfts",
+            Scope::UploadFiles => "upload_files",
+        }
+    }
+
+    pub fn join(scopes: &[Scope]) -> String {
+        scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Parses a stored scope string, skipping scopes this version does not know.
+    pub fn split(scopes: &str) -> Vec<Scope> {
+        scopes
+            .split_whitespace()
+            .filter_map(|scope| Scope::from_str(scope).ok())
+            .collect()
+    }
+}
+
+impl FromStr for Scope {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "read" => Ok(Scope::Read),
+            "write_collections" => Ok(Scope::WriteCollections),
+            "write_nfts" => Ok(Scope::WriteNfts),
+            "upload_files" => Ok(Scope::UploadFiles),
+            _ => Err(AppError::InvalidScope),
+        }
+    }
+}
+
+/// Result of checking the request's bearer token or API key, stored in the request data
+/// once so resolvers and guards do not parse the credentials again.
+pub struct Authentication(pub Result<EncryptUserInfo, AppError>);
+
+impl Authentication {
+    pub async fn from_token(database: &Database, token: &Token) -> Self {
+        Self(token.parse(database).await)
+    }
+
+    pub async fn from_api_key(database: &Database, api_key: &str) -> Self {
+        Self(api_key::authenticate(database, api_key).await)
+    }
+}
+
+/// Claims of the authenticated caller. Resolvers behind any of the guards below can rely on it.
+pub fn current_user_info<'a>(ctx: &'a Context<'_>) -> Result<&'a EncryptUserInfo, AppError> {
+    match ctx.data_opt::<Authentication>() {
+        Some(Authentication(Ok(encrypt_user_info))) => Ok(encrypt_user_info),
+        Some(Authentication(Err(err))) => Err(*err),
+        None => Err(AppError::MissingCredentials),
+    }
+}
+
+/// Passes for callers holding `scope`. Wallet sessions hold every scope.
+pub struct RequireScope(pub Scope);
+
+impl Guard for RequireScope {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if current_user_info(ctx)?.has_scope(self.0) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.into())
+        }
+    }
+}
+
+/// Passes only for wallet sessions, keeping account management out of reach of API keys.
+pub struct RequireSession;
+
+impl Guard for RequireSession {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if current_user_info(ctx)?.scopes.is_none() {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.into())
+        }
+    }
+}
+
+pub struct RequireRole(pub Role);
+
+impl Guard for RequireRole {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if current_user_info(ctx)?.role >= self.0 {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.into())
+        }
+    }
+}
+
+/// Passes when the caller owns the collection at `contract_address` on `chain_id`, or is an
+/// admin.
+pub struct RequireCollectionOwner {
+    chain_id: i32,
+    contract_address: String,
+}
+
+impl RequireCollectionOwner {
+    pub fn new(chain_id: i32, contract_address: &str) -> Self {
+        Self {
+            chain_id,
+            contract_address: contract_address.to_string(),
+        }
+    }
+}
+
+impl Guard for RequireCollectionOwner {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        if encrypt_user_info.role == Role::Admin {
+            return Ok(());
+        }
+        let collection_query = CollectionQuery {
+            owner: Some(encrypt_user_info.address.clone()),
+            contract_address: Some(self.contract_address.clone()),
+            chain_id: Some(self.chain_id),
+            ..Default::default()
+        };
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| Collection::find_by_query(connection, collection_query))
+            .await?
+            .ok_or(AppError::CollectionNotFound)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use async_graphql::{Context, Enum, Object, SimpleObject};
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::collection::{Collection, CollectionQuery},
+    models::ipfs_job::{InsertedIpfsJob, IpfsJob},
+    models::Database,
+};
+
+use super::guard::{RequireCollectionOwner, RequireRole, RequireScope, Role, Scope};
+
+#[derive(Default)]
+pub struct IpfsJobMutation;
+#[derive(Default)]
+pub struct IpfsJobQuery;
+
+/// An IPFS call queued in the outbox, stored as the job's payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IpfsOperation {
+    Mkdir {
+        path: String,
+    },
+    /// Replaces the file at `path` with `content`.
+    Write {
+        path: String,
+        content: String,
+    },
+    /// Flushes the whole MFS tree.
+    Flush,
+    Pin {
+        cid: String,
+    },
+    /// Flushes the collection directory at `path` and moves its pin to the new CID.
+    #[serde(rename = "pin_directory")]
+    PinDirectory {
+        path: String,
+    },
+    /// Pins `cid` to the remote pinning service configured as `service`.
+    #[serde(rename = "remote_pin")]
+    RemotePin {
+        service: String,
+        cid: String,
+    },
+    /// Removes the request `request_id` from the remote pinning service `service`.
+    #[serde(rename = "remote_unpin")]
+    RemoteUnpin {
+        service: String,
+        request_id: String,
+    },
+}
+
+impl IpfsOperation {
+    pub fn kind(&self) -> IpfsJobKind {
+        match self {
+            IpfsOperation::Mkdir { .. } => IpfsJobKind::Mkdir,
+            IpfsOperation::Write { .. } => IpfsJobKind::Write,
+            IpfsOperation::Flush => IpfsJobKind::Flush,
+            IpfsOperation::Pin { .. } => IpfsJobKind::Pin,
+            IpfsOperation::PinDirectory { .. } => IpfsJobKind::PinDirectory,
+            IpfsOperation::RemotePin { .. } => IpfsJobKind::RemotePin,
+            IpfsOperation::RemoteUnpin { .. } => IpfsJobKind::RemoteUnpin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum IpfsJobKind {
+    Mkdir,
+    Write,
+    Flush,
+    Pin,
+    #[serde(rename = "pin_directory")]
+    PinDirectory,
+    #[serde(rename = "remote_pin")]
+    RemotePin,
+    #[serde(rename = "remote_unpin")]
+    RemoteUnpin,
+}
+
+impl IpfsJobKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IpfsJobKind::Mkdir => "mkdir",
+            IpfsJobKind::Write => "write",
+            IpfsJobKind::Flush => "flush",
+            IpfsJobKind::Pin => "pin",
+            IpfsJobKind::PinDirectory => "pin_directory",
+            IpfsJobKind::RemotePin => "remote_pin",
+            IpfsJobKind::RemoteUnpin => "remote_unpin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mkdir" => Some(IpfsJobKind::Mkdir),
+            "write" => Some(IpfsJobKind::Write),
+            "flush" => Some(IpfsJobKind::Flush),
+            "pin" => Some(IpfsJobKind::Pin),
+            "pin_directory" => Some(IpfsJobKind::PinDirectory),
+            "remote_pin" => Some(IpfsJobKind::RemotePin),
+            "remote_unpin" => Some(IpfsJobKind::RemoteUnpin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum IpfsJobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl IpfsJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IpfsJobStatus::Pending => "pending",
+            IpfsJobStatus::Running => "running",
+            IpfsJobStatus::Done => "done",
+            IpfsJobStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "running" => IpfsJobStatus::Running,
+            "done" => IpfsJobStatus::Done,
+            "failed" => IpfsJobStatus::Failed,
+            _ => IpfsJobStatus::Pending,
+        }
+    }
+}
+
+/// Queues `operation` for the worker. Call it inside the transaction of the change it belongs
+/// to, so the job exists if and only if the change was committed.
+pub fn enqueue(
+    connection: &mut PgConnection,
+    operation: &IpfsOperation,
+    collection_id: Option<Uuid>,
+    nft_id: Option<Uuid>,
+) -> Result<IpfsJob, AppError> {
+    let payload = serde_json::to_value(operation).map_err(|err| {
+        tracing::error!("serialize ipfs job error: {:?}", err);
+        AppError::CreateIpfsJobFailed
+    })?;
+    InsertedIpfsJob {
+        kind: operation.kind().as_str().to_string(),
+        payload,
+        collection_id,
+        nft_id,
+    }
+    .insert(connection)
+}
+
+/// Queues the flush that ends every change to a collection's files, pinning the
+/// directory's new CID.
+pub fn enqueue_directory_pin(
+    connection: &mut PgConnection,
+    collection: &Collection,
+) -> Result<IpfsJob, AppError> {
+    let operation = IpfsOperation::PinDirectory {
+        path: collection.dir_name.clone(),
+    };
+    enqueue(connection, &operation, Some(collection.id), None)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct IpfsJobResult {
+    pub id: String,
+    pub kind: Option<IpfsJobKind>,
+    pub status: IpfsJobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub next_run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub nft_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<IpfsJob> for IpfsJobResult {
+    fn from(job: IpfsJob) -> Self {
+        Self {
+            id: job.id.to_string(),
+            kind: IpfsJobKind::parse(&job.kind),
+            status: IpfsJobStatus::parse(&job.status),
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            next_run_at: job.next_run_at,
+            last_error: job.last_error,
+            nft_id: job.nft_id.map(|nft_id| nft_id.to_string()),
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        }
+    }
+}
+
+#[Object]
+impl IpfsJobQuery {
+    /// Publishing jobs of a collection, newest first.
+    #[graphql(
+        guard = "RequireScope(Scope::Read).and(RequireCollectionOwner::new(chain_id, &collection))"
+    )]
+    pub async fn ipfs_jobs(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        status: Option<IpfsJobStatus>,
+    ) -> Result<Vec<IpfsJobResult>, AppError> {
+        let jobs = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                let collection_query = CollectionQuery {
+                    contract_address: Some(collection),
+                    chain_id: Some(chain_id),
+                    ..Default::default()
+                };
+                let collection = Collection::find_by_query(connection, collection_query)?
+                    .ok_or(AppError::CollectionNotFound)?;
+                IpfsJob::list_by_collection_id(
+                    connection,
+                    collection.id,
+                    status.map(|status| status.as_str().to_string()),
+                )
+            })
+            .await?;
+        Ok(jobs.into_iter().map(IpfsJobResult::from).collect())
+    }
+}
+
+#[Object]
+impl IpfsJobMutation {
+    /// Requeues a job that ran out of attempts, e.g. after the IPFS node was fixed.
+    #[graphql(guard = "RequireRole(Role::Admin)")]
+    pub async fn retry_ipfs_job(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> Result<IpfsJobResult, AppError> {
+        let id = Uuid::parse_str(&id).map_err(|_| AppError::IpfsJobNotFound)?;
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| IpfsJob::retry(connection, id))
+            .await?
+            .map(IpfsJobResult::from)
+            .ok_or(AppError::IpfsJobNotFound)
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use async_graphql::dataloader::Loader;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::{collection::Collection, nft_trait::NFTTrait, user::User, Database},
+};
+
+/// Batches the trait lookups of every NFT resolved in the same request tick.
+pub struct NFTTraitLoader {
+    database: Database,
+}
+
+impl NFTTraitLoader {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+}
+
+impl Loader<Uuid> for NFTTraitLoader {
+    type Value = Vec<NFTTrait>;
+    type Error = AppError;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let nft_ids = keys.to_vec();
+        let nft_traits = self
+            .database
+            .run(move |connection| NFTTrait::list_by_nft_ids(connection, nft_ids))
+            .await?;
+        let mut traits_by_nft_id: HashMap<Uuid, Vec<NFTTrait>> = HashMap::new();
+        for nft_trait in nft_traits {
+            traits_by_nft_id
+                .entry(nft_trait.nft_id)
+                .or_default()
+                .push(nft_trait);
+        }
+        Ok(traits_by_nft_id)
+    }
+}
+
+/// Loads collections by chain id and contract address.
+pub struct CollectionLoader {
+    database: Database,
+}
+
+impl CollectionLoader {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+}
+
+impl Loader<(i32, String)> for CollectionLoader {
+    type Value = Collection;
+    type Error = AppError;
+
+    async fn load(
+        &self,
+        keys: &[(i32, String)],
+    ) -> Result<HashMap<(i32, String), Self::Value>, Self::Error> {
+        let contract_addresses = keys
+            .iter()
+            .map(|(_, contract_address)| contract_address.clone())
+            .collect();
+        let collections = self
+            .database
+            .run(move |connection| {
+                Collection::list_by_contract_addresses(connection, contract_addresses)
+            })
+            .await?;
+        Ok(collections
+            .into_iter()
+            .map(|collection| {
+                let key = (collection.chain_id, collection.contract_address.clone());
+                (key, collection)
+            })
+            .collect())
+    }
+}
+
+/// Loads users by wallet address.
+pub struct UserLoader {
+    database: Database,
+}
+
+impl UserLoader {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+}
+
+impl Loader<String> for UserLoader {
+    type Value = User;
+    type Error = AppError;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let addresses = keys.to_vec();
+        let users = self
+            .database
+            .run(move |connection| User::list_by_addresses(connection, addresses))
+            .await?;
+        Ok(users
+            .into_iter()
+            .map(|user| (user.address.clone(), user))
+            .collect())
+    }
+}
@@ -1,8 +1,15 @@
 use crate::errors::AppError;
 
+pub mod api_key;
 pub mod collection;
 pub mod file;
+pub mod guard;
+pub mod ipfs_job;
+pub mod loader;
 pub mod nft;
+pub mod pagination;
+pub mod pin;
+pub mod session;
 pub mod token;
 pub mod user;
 
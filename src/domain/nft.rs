@@ -1,6 +1,12 @@
-use async_graphql::{Context, InputObject, Object, SimpleObject};
-use ipfs_api::client::{Client, LocalIPFSClient};
-use ipfs_api::req::files::{WriteQuery, WriteRequest};
+use std::collections::HashMap;
+
+use async_graphql::connection::Connection as GraphQLConnection;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{
+    ComplexObject, Context, Enum, Guard, InputObject, MaybeUndefined, Object, SimpleObject,
+};
+use chrono::NaiveDateTime;
+use diesel::{Connection, PgConnection};
 use serde::{Deserialize, Serialize};
 
 use crate::models;
@@ -8,12 +14,21 @@ use crate::{
     errors::AppError,
     models::{
         collection::{Collection, CollectionQuery},
-        nft::{InsertedNFT, NFT},
+        nft::{InsertedNFT, NFTCursor, NFTOrder, UpdatedNFT, NFT},
         nft_trait::{BatchInsertedNFTTrait, InsertedNFTTrait, NFTTrait},
+        Database,
     },
 };
 
-use super::{token::Token, AppResponse};
+use super::{
+    collection::{enqueue_contract_metadata, find_collection, CollectionResult},
+    guard::{current_user_info, RequireCollectionOwner, RequireScope, Scope},
+    ipfs_job::{self, IpfsOperation},
+    loader::{CollectionLoader, NFTTraitLoader, UserLoader},
+    pagination::{self, Cursor},
+    user::UserProfileResult,
+    AppResponse,
+};
 
 #[derive(Default)]
 pub struct NFTMutation;
@@ -29,38 +44,254 @@ pub struct NewNFT {
     pub supply: i32,
     pub external_link: Option<String>,
     pub collection: String,
+    pub chain_id: i32,
     pub traits: Option<Vec<NewNFTTrait>>,
+    pub animation_url: Option<String>,
+    /// Six hex digits without the leading `#`.
+    pub background_color: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
 pub struct NewNFTTrait {
     pub trait_type: String,
+    /// A number for numeric display types, or a unix timestamp for `DATE`.
     pub trait_value: String,
+    pub display_type: Option<TraitDisplayType>,
+    /// Upper bound shown next to `NUMBER` traits.
+    pub max_value: Option<f64>,
+}
+
+/// How marketplaces render a trait; text traits have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum TraitDisplayType {
+    Number,
+    BoostNumber,
+    BoostPercentage,
+    Date,
+}
+
+impl TraitDisplayType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraitDisplayType::Number => "number",
+            TraitDisplayType::BoostNumber => "boost_number",
+            TraitDisplayType::BoostPercentage => "boost_percentage",
+            TraitDisplayType::Date => "date",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "number" => Some(TraitDisplayType::Number),
+            "boost_number" => Some(TraitDisplayType::BoostNumber),
+            "boost_percentage" => Some(TraitDisplayType::BoostPercentage),
+            "date" => Some(TraitDisplayType::Date),
+            _ => None,
+        }
+    }
+}
+
+/// Layout of a collection's metadata files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Enum)]
+pub enum MetadataFormat {
+    /// The ERC-721 metadata JSON schema, with OpenSea's extensions.
+    #[default]
+    OpenSea,
+    /// The format used before OpenSea support, for collections whose consumers expect it.
+    Legacy,
+}
+
+impl MetadataFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetadataFormat::OpenSea => "opensea",
+            MetadataFormat::Legacy => "legacy",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "legacy" => MetadataFormat::Legacy,
+            _ => MetadataFormat::OpenSea,
+        }
+    }
+}
+
+/// Fields to change; omitted ones are left as they are and `null` clears optional ones.
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
+pub struct UpdateNFT {
+    pub name: Option<String>,
+    pub description: MaybeUndefined<String>,
+    pub image_url: Option<String>,
+    pub external_link: MaybeUndefined<String>,
+    pub animation_url: MaybeUndefined<String>,
+    pub background_color: MaybeUndefined<String>,
+}
+
+impl From<UpdateNFT> for UpdatedNFT {
+    fn from(changes: UpdateNFT) -> Self {
+        Self {
+            name: changes.name,
+            description: changes.description.into(),
+            image_url: changes.image_url,
+            external_link: changes.external_link.into(),
+            animation_url: changes.animation_url.into(),
+            background_color: changes.background_color.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, InputObject)]
+pub struct NFTFilter {
+    /// Contract address of the collection.
+    pub collection: Option<String>,
+    pub owner: Option<String>,
+    pub trait_type: Option<String>,
+    pub trait_value: Option<String>,
+    pub chain_id: Option<i32>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+}
+
+impl From<NFTFilter> for models::nft::NFTQuery {
+    fn from(filter: NFTFilter) -> Self {
+        Self {
+            owner: filter.owner,
+            collection: filter.collection,
+            trait_type: filter.trait_type,
+            trait_value: filter.trait_value,
+            chain_id: filter.chain_id,
+            created_after: filter.created_after,
+            created_before: filter.created_before,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+pub enum NFTOrderBy {
+    #[default]
+    TokenIdAsc,
+    TokenIdDesc,
+    CreatedAtAsc,
+    CreatedAtDesc,
+}
+
+impl From<NFTOrderBy> for NFTOrder {
+    fn from(order_by: NFTOrderBy) -> Self {
+        match order_by {
+            NFTOrderBy::TokenIdAsc => NFTOrder::TokenIdAsc,
+            NFTOrderBy::TokenIdDesc => NFTOrder::TokenIdDesc,
+            NFTOrderBy::CreatedAtAsc => NFTOrder::CreatedAtAsc,
+            NFTOrderBy::CreatedAtDesc => NFTOrder::CreatedAtDesc,
+        }
+    }
+}
+
+/// Whether an NFT's metadata file has made it to IPFS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishStatus {
+    Pending,
+    Published,
+}
+
+impl PublishStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublishStatus::Pending => "pending",
+            PublishStatus::Published => "published",
+        }
+    }
+
+    /// Anything not known to be published is treated as pending, so it gets retried.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "published" => PublishStatus::Published,
+            _ => PublishStatus::Pending,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct NFTResult {
+    #[graphql(skip)]
+    pub id: uuid::Uuid,
     pub token_id: i32,
     pub name: String,
     pub description: Option<String>,
     pub image_url: String,
     pub supply: i32,
     pub external_link: Option<String>,
+    #[graphql(skip)]
     pub owner: String,
+    #[graphql(skip)]
     pub collection: String,
-    pub traits: Option<Vec<NFTTraitResult>>,
+    pub chain_id: i32,
+    pub animation_url: Option<String>,
+    pub background_color: Option<String>,
+    pub publish_status: PublishStatus,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
 }
 
+#[ComplexObject]
+impl NFTResult {
+    async fn traits(&self, ctx: &Context<'_>) -> Result<Vec<NFTTraitResult>, AppError> {
+        let nft_traits = ctx
+            .data_unchecked::<DataLoader<NFTTraitLoader>>()
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        Ok(nft_traits.into_iter().map(NFTTraitResult::from).collect())
+    }
+
+    async fn collection(&self, ctx: &Context<'_>) -> Result<CollectionResult, AppError> {
+        ctx.data_unchecked::<DataLoader<CollectionLoader>>()
+            .load_one((self.chain_id, self.collection.clone()))
+            .await?
+            .map(CollectionResult::from)
+            .ok_or(AppError::CollectionNotFound)
+    }
+
+    /// `null` when the owner has not created a profile.
+    async fn owner(&self, ctx: &Context<'_>) -> Result<Option<UserProfileResult>, AppError> {
+        Ok(ctx
+            .data_unchecked::<DataLoader<UserLoader>>()
+            .load_one(self.owner.clone())
+            .await?
+            .map(UserProfileResult::from))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, SimpleObject)]
 pub struct NFTTraitResult {
     pub trait_type: String,
     pub trait_value: String,
+    pub display_type: Option<TraitDisplayType>,
+    pub max_value: Option<f64>,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
 }
 
+impl From<NFTTrait> for NFTTraitResult {
+    fn from(nft_trait: NFTTrait) -> Self {
+        Self {
+            trait_type: nft_trait.trait_type,
+            trait_value: nft_trait.trait_value,
+            display_type: nft_trait
+                .display_type
+                .as_deref()
+                .and_then(TraitDisplayType::parse),
+            max_value: nft_trait.max_value,
+            created_at: nft_trait.created_at,
+            updated_at: nft_trait.updated_at,
+        }
+    }
+}
+
 pub fn convert_to_inserted_nft(new_nft: &NewNFT, owner: String) -> InsertedNFT {
     InsertedNFT {
         token_id: new_nft.token_id,
@@ -70,7 +301,26 @@ pub fn convert_to_inserted_nft(new_nft: &NewNFT, owner: String) -> InsertedNFT {
         supply: new_nft.supply,
         external_link: new_nft.external_link.clone(),
         owner,
-        collection: new_nft.collection.clone(),
+        collection: new_nft.collection.to_lowercase(),
+        publish_status: PublishStatus::Pending.as_str().to_string(),
+        chain_id: new_nft.chain_id,
+        animation_url: new_nft.animation_url.clone(),
+        background_color: new_nft.background_color.clone(),
+    }
+}
+
+pub fn convert_to_inserted_nft_trait(
+    trait_item: &NewNFTTrait,
+    nft_id: &uuid::Uuid,
+) -> InsertedNFTTrait {
+    InsertedNFTTrait {
+        nft_id: nft_id.clone(),
+        trait_type: trait_item.trait_type.clone(),
+        trait_value: trait_item.trait_value.clone(),
+        display_type: trait_item
+            .display_type
+            .map(|display_type| display_type.as_str().to_string()),
+        max_value: trait_item.max_value,
     }
 }
 
@@ -86,17 +336,14 @@ pub fn convert_to_batch_inserted_nft_trait(
         .as_ref()
         .unwrap()
         .into_iter()
-        .map(|trait_item| InsertedNFTTrait {
-            nft_id: nft_id.clone(),
-            trait_type: trait_item.trait_type.clone(),
-            trait_value: trait_item.trait_value.clone(),
-        })
+        .map(|trait_item| convert_to_inserted_nft_trait(trait_item, nft_id))
         .collect();
     BatchInsertedNFTTrait { traits }
 }
 
-pub fn convert_to_nft_result(nft: &NFT, nft_traits: &Vec<NFTTrait>) -> Option<NFTResult> {
-    Some(NFTResult {
+pub fn convert_to_nft_result(nft: &NFT) -> NFTResult {
+    NFTResult {
+        id: nft.id,
         token_id: nft.token_id,
         name: nft.name.clone(),
         description: nft.description.clone(),
@@ -105,24 +352,45 @@ pub fn convert_to_nft_result(nft: &NFT, nft_traits: &Vec<NFTTrait>) -> Option<NF
         external_link: nft.external_link.clone(),
         owner: nft.owner.clone(),
         collection: nft.collection.clone(),
-        traits: Some(
-            nft_traits
-                .into_iter()
-                .map(|trait_item| NFTTraitResult {
-                    trait_type: trait_item.trait_type.clone(),
-                    trait_value: trait_item.trait_value.clone(),
-                    created_at: trait_item.created_at,
-                    updated_at: trait_item.updated_at,
-                })
-                .collect(),
-        ),
+        chain_id: nft.chain_id,
+        animation_url: nft.animation_url.clone(),
+        background_color: nft.background_color.clone(),
+        publish_status: PublishStatus::parse(&nft.publish_status),
         created_at: nft.created_at,
         updated_at: nft.updated_at,
-    })
+    }
 }
 
+/// Token metadata in the ERC-721 metadata JSON schema, with OpenSea's extensions.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NFTMetadata {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animation_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+    pub attributes: Vec<NFTAttribute>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NFTAttribute {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_type: Option<TraitDisplayType>,
+    pub trait_type: String,
+    /// A JSON number for numeric and date traits, a string otherwise.
+    pub value: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<f64>,
+}
+
+/// Metadata as written before OpenSea support; see `MetadataFormat::Legacy`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LegacyNFTMetadata {
     pub dna: uuid::Uuid,
     pub name: String,
     pub description: Option<String>,
@@ -137,8 +405,78 @@ pub struct NFTTraitMetadata {
     pub value: String,
 }
 
+/// Token ids are non-negative, and below `i32::MAX` so the collection's counter can move
+/// past them.
+pub fn validate_token_id(token_id: i32) -> Result<(), AppError> {
+    if (0..i32::MAX).contains(&token_id) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidTokenId)
+    }
+}
+
+/// Checks what the database cannot: colors are hex and numeric traits hold numbers.
+pub fn validate_nft_metadata(
+    background_color: Option<&str>,
+    traits: &[NewNFTTrait],
+) -> Result<(), AppError> {
+    if let Some(background_color) = background_color {
+        if background_color.len() != 6 || !background_color.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AppError::InvalidNFTMetadata);
+        }
+    }
+    for trait_item in traits {
+        let valid = match trait_item.display_type {
+            None => true,
+            Some(TraitDisplayType::Date) => trait_item.trait_value.parse::<i64>().is_ok(),
+            Some(_) => trait_item.trait_value.parse::<f64>().is_ok(),
+        };
+        if !valid {
+            return Err(AppError::InvalidNFTMetadata);
+        }
+    }
+    Ok(())
+}
+
+fn attribute_value(display_type: Option<TraitDisplayType>, value: &str) -> serde_json::Value {
+    if display_type.is_none() {
+        return serde_json::Value::String(value.to_string());
+    }
+    value
+        .parse::<i64>()
+        .map(serde_json::Value::from)
+        .or_else(|_| value.parse::<f64>().map(serde_json::Value::from))
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()))
+}
+
 pub fn convert_to_nft_metadata(nft: &NFT, nft_traits: &Vec<NFTTrait>) -> NFTMetadata {
     NFTMetadata {
+        name: nft.name.clone(),
+        description: nft.description.clone(),
+        image: nft.image_url.clone(),
+        external_url: nft.external_link.clone(),
+        animation_url: nft.animation_url.clone(),
+        background_color: nft.background_color.clone(),
+        attributes: nft_traits
+            .into_iter()
+            .map(|trait_item| {
+                let display_type = trait_item
+                    .display_type
+                    .as_deref()
+                    .and_then(TraitDisplayType::parse);
+                NFTAttribute {
+                    display_type,
+                    trait_type: trait_item.trait_type.clone(),
+                    value: attribute_value(display_type, &trait_item.trait_value),
+                    max_value: trait_item.max_value,
+                }
+            })
+            .collect(),
+    }
+}
+
+pub fn convert_to_legacy_nft_metadata(nft: &NFT, nft_traits: &Vec<NFTTrait>) -> LegacyNFTMetadata {
+    LegacyNFTMetadata {
         dna: nft.id,
         name: nft.name.clone(),
         description: nft.description.clone(),
@@ -156,94 +494,632 @@ pub fn convert_to_nft_metadata(nft: &NFT, nft_traits: &Vec<NFTTrait>) -> NFTMeta
     }
 }
 
+/// The NFT's metadata file, in the format its collection is set to.
+pub fn render_nft_metadata(
+    collection: &Collection,
+    nft: &NFT,
+    nft_traits: &Vec<NFTTrait>,
+) -> String {
+    match MetadataFormat::parse(&collection.metadata_format) {
+        MetadataFormat::OpenSea => {
+            serde_json::to_string(&convert_to_nft_metadata(nft, nft_traits)).unwrap()
+        }
+        MetadataFormat::Legacy => {
+            serde_json::to_string(&convert_to_legacy_nft_metadata(nft, nft_traits)).unwrap()
+        }
+    }
+}
+
+/// The metadata every token of an unrevealed collection shows until the reveal.
+pub fn convert_to_placeholder_metadata(collection: &Collection) -> NFTMetadata {
+    NFTMetadata {
+        name: collection
+            .placeholder_name
+            .clone()
+            .unwrap_or_else(|| collection.name.clone()),
+        description: collection.placeholder_description.clone(),
+        image: collection.placeholder_image_url.clone().unwrap_or_default(),
+        external_url: None,
+        animation_url: None,
+        background_color: None,
+        attributes: vec![],
+    }
+}
+
+/// The NFT's metadata file as it may be shown now: the shared placeholder until its
+/// collection is revealed.
+pub fn render_published_nft_metadata(
+    collection: &Collection,
+    nft: &NFT,
+    nft_traits: &Vec<NFTTrait>,
+) -> String {
+    if collection.revealed {
+        render_nft_metadata(collection, nft, nft_traits)
+    } else {
+        serde_json::to_string(&convert_to_placeholder_metadata(collection)).unwrap()
+    }
+}
+
+pub fn nft_metadata_path(collection: &Collection, token_id: i32) -> String {
+    format!("/{}/{}.json", collection.dir_name, token_id)
+}
+
+/// Whether the caller may see the real NFTs of an unrevealed collection.
+async fn can_see_unrevealed(ctx: &Context<'_>, chain_id: i32, collection: &str) -> bool {
+    RequireCollectionOwner::new(chain_id, collection)
+        .check(ctx)
+        .await
+        .is_ok()
+}
+
+/// Queues the NFT's metadata file for the collection directory.
+/// The worker marks the NFT published once the write has gone through.
+pub fn enqueue_nft_metadata(
+    connection: &mut PgConnection,
+    collection: &Collection,
+    nft: &NFT,
+    nft_traits: &Vec<NFTTrait>,
+) -> Result<(), AppError> {
+    let write = IpfsOperation::Write {
+        path: nft_metadata_path(collection, nft.token_id),
+        content: render_published_nft_metadata(collection, nft, nft_traits),
+    };
+    ipfs_job::enqueue(connection, &write, Some(collection.id), Some(nft.id))?;
+    Ok(())
+}
+
+/// The token's metadata file as its collection publishes it, for `baseURI` lookups.
+pub fn find_nft_metadata(
+    connection: &mut PgConnection,
+    chain_id: i32,
+    contract_address: String,
+    token_id: i32,
+) -> Result<String, AppError> {
+    let collection = find_collection(connection, chain_id, contract_address.clone())?;
+    let nft =
+        NFT::find_by_collection_and_token_id(connection, chain_id, contract_address, token_id)?;
+    let nft_traits = NFTTrait::list_by_nft_id(connection, nft.id)?;
+    Ok(render_published_nft_metadata(
+        &collection,
+        &nft,
+        &nft_traits,
+    ))
+}
+
+/// Queues every NFT's metadata as the collection now publishes it, e.g. the real metadata
+/// after a reveal. The caller queues the directory pin. Returns how many NFTs were queued.
+pub fn enqueue_collection_metadata(
+    connection: &mut PgConnection,
+    collection: &Collection,
+) -> Result<usize, AppError> {
+    let nfts = NFT::list_by_collection(
+        connection,
+        collection.chain_id,
+        collection.contract_address.clone(),
+    )?;
+    let mut nft_traits: HashMap<uuid::Uuid, Vec<NFTTrait>> = HashMap::new();
+    for nft_trait in NFTTrait::list_by_nft_ids(connection, nfts.iter().map(|nft| nft.id).collect())?
+    {
+        nft_traits
+            .entry(nft_trait.nft_id)
+            .or_default()
+            .push(nft_trait);
+    }
+    for nft in &nfts {
+        let nft = NFT::update_publish_status(
+            connection,
+            nft.id,
+            PublishStatus::Pending.as_str().to_string(),
+        )?;
+        let traits = nft_traits.remove(&nft.id).unwrap_or_default();
+        enqueue_nft_metadata(connection, collection, &nft, &traits)?;
+    }
+    Ok(nfts.len())
+}
+
+/// Inserts the NFT and its traits and queues its metadata, followed by a flush.
+/// Run it inside a transaction so nothing is queued for an NFT that was rolled back.
+pub fn insert_nft(
+    connection: &mut PgConnection,
+    collection: &Collection,
+    new_nft: &NewNFT,
+    owner: String,
+) -> Result<(NFT, Vec<NFTTrait>), AppError> {
+    validate_token_id(new_nft.token_id)?;
+    validate_nft_metadata(
+        new_nft.background_color.as_deref(),
+        new_nft.traits.as_deref().unwrap_or_default(),
+    )?;
+    // Create a new NFT
+    let nft = convert_to_inserted_nft(new_nft, owner).insert(connection)?;
+    Collection::advance_token_id(connection, collection.id, nft.token_id)?;
+    // Create a new NFT trait
+    let nft_traits = convert_to_batch_inserted_nft_trait(new_nft, &nft.id).insert(connection)?;
+    enqueue_nft_metadata(connection, collection, &nft, &nft_traits)?;
+    ipfs_job::enqueue_directory_pin(connection, collection)?;
+    Ok((nft, nft_traits))
+}
+
+/// Queues the metadata file of an NFT that changed, followed by a flush. The write replaces
+/// the previous file.
+pub fn requeue_nft_metadata(
+    connection: &mut PgConnection,
+    collection: &Collection,
+    nft: &NFT,
+) -> Result<(), AppError> {
+    let nft_traits = NFTTrait::list_by_nft_id(connection, nft.id)?;
+    enqueue_nft_metadata(connection, collection, nft, &nft_traits)?;
+    ipfs_job::enqueue_directory_pin(connection, collection)?;
+    Ok(())
+}
+
+/// Queues the collection directory, its `contract.json` and every NFT's metadata again, e.g. after the IPFS
+/// node lost its MFS tree. Returns how many NFTs were queued.
+pub fn republish_collection(
+    connection: &mut PgConnection,
+    collection: &Collection,
+) -> Result<usize, AppError> {
+    let mkdir = IpfsOperation::Mkdir {
+        path: collection.contract_address.clone(),
+    };
+    ipfs_job::enqueue(connection, &mkdir, Some(collection.id), None)?;
+    enqueue_contract_metadata(connection, collection)?;
+    let nfts = NFT::list_by_collection(
+        connection,
+        collection.chain_id,
+        collection.contract_address.clone(),
+    )?;
+    for nft in &nfts {
+        let nft = NFT::update_publish_status(
+            connection,
+            nft.id,
+            PublishStatus::Pending.as_str().to_string(),
+        )?;
+        let nft_traits = NFTTrait::list_by_nft_id(connection, nft.id)?;
+        enqueue_nft_metadata(connection, collection, &nft, &nft_traits)?;
+    }
+    ipfs_job::enqueue_directory_pin(connection, collection)?;
+    Ok(nfts.len())
+}
+
 #[Object]
 impl NFTMutation {
+    /// Creates the NFT and its traits, and queues its metadata for IPFS, in one transaction.
+    /// The NFT is returned with `publishStatus` `PENDING` until the worker has written it.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(new_nft.chain_id, &new_nft.collection))"
+    )]
     async fn create_nft(&self, ctx: &Context<'_>, new_nft: NewNFT) -> AppResponse<NFTResult> {
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
+        let owner = current_user_info(ctx)?.address.clone();
+        let (nft, _) = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    // ownership is checked by the guard
+                    let collection_query = CollectionQuery {
+                        contract_address: Some(new_nft.collection.clone()),
+                        chain_id: Some(new_nft.chain_id),
+                        ..Default::default()
+                    };
+                    let collection = Collection::find_by_query(connection, collection_query)?
+                        .ok_or(AppError::CollectionNotFound)?;
+                    insert_nft(connection, &collection, &new_nft, owner)
+                })
+            })
+            .await?;
 
-        // check collection is valid
-        let collection_query = CollectionQuery {
-            owner: Some(encrypt_user_info.address.clone()),
-            contract_address: Some(new_nft.collection.clone()),
-            ..Default::default()
-        };
-        let collection = Collection::find_by_query(collection_query)
+        Ok(Some(convert_to_nft_result(&nft)))
+    }
+
+    /// Reserves a token id for a mint transaction about to be sent. Every call returns a new
+    /// id, even when called concurrently; ids of mints that never happen are skipped.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &contract_address))"
+    )]
+    async fn reserve_token_id(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        contract_address: String,
+    ) -> Result<i32, AppError> {
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                Collection::reserve_token_id(connection, chain_id, contract_address)
+            })
             .await?
-            .ok_or(AppError::CollectionNotFound)?;
+            .ok_or(AppError::CollectionNotFound)
+    }
 
-        // Create a new NFT
-        let nft = convert_to_inserted_nft(&new_nft, encrypt_user_info.address)
-            .insert()
+    /// Queues the metadata of an NFT again, e.g. after its publishing job failed for good.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &collection))"
+    )]
+    async fn publish_nft(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+    ) -> AppResponse<NFTResult> {
+        let nft = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    let nft = NFT::find_by_collection_and_token_id(
+                        connection,
+                        chain_id,
+                        collection.clone(),
+                        token_id,
+                    )?;
+                    let collection = find_collection(connection, chain_id, collection)?;
+                    let nft = NFT::update_publish_status(
+                        connection,
+                        nft.id,
+                        PublishStatus::Pending.as_str().to_string(),
+                    )?;
+                    requeue_nft_metadata(connection, &collection, &nft)?;
+                    Ok(nft)
+                })
+            })
             .await?;
-        // Create a new NFT trait
-        let nft_traits = convert_to_batch_inserted_nft_trait(&new_nft, &nft.id)
-            .insert()
+
+        Ok(Some(convert_to_nft_result(&nft)))
+    }
+
+    /// Edits an NFT and queues its rewritten metadata file.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &collection))"
+    )]
+    async fn update_nft(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+        changes: UpdateNFT,
+    ) -> AppResponse<NFTResult> {
+        if let MaybeUndefined::Value(background_color) = &changes.background_color {
+            validate_nft_metadata(Some(background_color), &[])?;
+        }
+        let changes = UpdatedNFT::from(changes);
+        let nft = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    let nft = NFT::find_by_collection_and_token_id(
+                        connection,
+                        chain_id,
+                        collection.clone(),
+                        token_id,
+                    )?;
+                    let collection = find_collection(connection, chain_id, collection)?;
+                    let nft = NFT::update(
+                        connection,
+                        nft.id,
+                        &changes,
+                        PublishStatus::Pending.as_str().to_string(),
+                    )?;
+                    requeue_nft_metadata(connection, &collection, &nft)?;
+                    Ok(nft)
+                })
+            })
             .await?;
 
-        // upload nft metadata to IPFS
-        let filename = format!("{}.json", nft.token_id);
-        let path = format!("/{}/{}.json", collection.dir_name.clone(), nft.token_id);
-        let nft_metadata = convert_to_nft_metadata(&nft, &nft_traits);
-        let write_request = WriteRequest {
-            query: WriteQuery::new_with_arg(path),
-            bytes: serde_json::to_vec(&nft_metadata).unwrap(),
-            filename,
-        };
-        let _ = LocalIPFSClient::default()
-            .files_write(write_request)
+        Ok(Some(convert_to_nft_result(&nft)))
+    }
+
+    /// Replaces all traits of an NFT and queues its rewritten metadata file.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &collection))"
+    )]
+    async fn update_nft_traits(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+        traits: Vec<NewNFTTrait>,
+    ) -> AppResponse<NFTResult> {
+        validate_nft_metadata(None, &traits)?;
+        let nft = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    let nft = NFT::find_by_collection_and_token_id(
+                        connection,
+                        chain_id,
+                        collection.clone(),
+                        token_id,
+                    )?;
+                    let collection = find_collection(connection, chain_id, collection)?;
+                    NFTTrait::delete_by_nft_id(connection, nft.id)?;
+                    BatchInsertedNFTTrait {
+                        traits: traits
+                            .iter()
+                            .map(|trait_item| convert_to_inserted_nft_trait(trait_item, &nft.id))
+                            .collect(),
+                    }
+                    .insert(connection)?;
+                    let nft = NFT::update_publish_status(
+                        connection,
+                        nft.id,
+                        PublishStatus::Pending.as_str().to_string(),
+                    )?;
+                    requeue_nft_metadata(connection, &collection, &nft)?;
+                    Ok(nft)
+                })
+            })
+            .await?;
+
+        Ok(Some(convert_to_nft_result(&nft)))
+    }
+
+    /// Hides the NFT. Its metadata file is left on IPFS, as the token still exists on chain.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &collection))"
+    )]
+    async fn delete_nft(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+    ) -> Result<bool, AppError> {
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                let nft = NFT::find_by_collection_and_token_id(
+                    connection, chain_id, collection, token_id,
+                )?;
+                NFT::soft_delete(connection, nft.id)?;
+                Ok(true)
+            })
             .await
-            .map_err(|err| {
-                tracing::error!("IPFS write error: {:?}", err);
-                AppError::RequestIpfsError
-            })?;
-        Ok(convert_to_nft_result(&nft, &nft_traits))
     }
 }
 
 #[Object]
 impl NFTQuery {
-    async fn nft(&self, ctx: &Context<'_>, token_id: i32) -> AppResponse<NFTResult> {
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-        // Find NFT by token_id
-        let nft = NFT::find_by_token_id(token_id).await?;
-        // Find NFT traits by nft_id
-        let nft_traits = NFTTrait::list_by_nft_id(nft.id).await?;
-
-        Ok(convert_to_nft_result(&nft, &nft_traits))
-    }
-
-    async fn next_token_id(&self, ctx: &Context<'_>, contract_address: String) -> AppResponse<i64> {
-        // Check if the user is authenticated
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-
-        // check collection is valid
+    /// An NFT by the chain and contract address of its collection and its token id. Public.
+    async fn nft(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+    ) -> AppResponse<NFTResult> {
+        let (nft, revealed) = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                let revealed = find_collection(connection, chain_id, collection.clone())?.revealed;
+                NFT::find_by_collection_and_token_id(connection, chain_id, collection, token_id)
+                    .map(|nft| (nft, revealed))
+            })
+            .await?;
+        if !revealed && !can_see_unrevealed(ctx, chain_id, &nft.collection).await {
+            return Ok(None);
+        }
+
+        Ok(Some(convert_to_nft_result(&nft)))
+    }
+
+    /// NFTs matching `filter`, a page at a time. Public.
+    async fn nfts(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: NFTFilter,
+        #[graphql(default)] order_by: NFTOrderBy,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<GraphQLConnection<Cursor<NFTCursor>, NFTResult>, AppError> {
+        let page_size = pagination::page_size(first)?;
+        let after = pagination::decode_after::<NFTCursor>(after)?;
+        let has_previous_page = after.is_some();
+        // Owners browsing their own unrevealed collection see the staged NFTs.
+        let hide_unrevealed = match (filter.chain_id, filter.collection.as_deref()) {
+            (Some(chain_id), Some(collection)) => {
+                !can_see_unrevealed(ctx, chain_id, collection).await
+            }
+            _ => true,
+        };
+        let nft_query = models::nft::NFTQuery {
+            hide_unrevealed,
+            ..models::nft::NFTQuery::from(filter)
+        };
+        let nfts = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                nft_query.list(connection, order_by.into(), after, page_size + 1)
+            })
+            .await?;
+
+        Ok(pagination::into_connection(
+            nfts,
+            page_size,
+            has_previous_page,
+            |nft: &NFT| NFTCursor::from(nft),
+            |nft| convert_to_nft_result(&nft),
+        ))
+    }
+
+    /// The id the next reservation would return. Use `reserveTokenId` before minting; this
+    /// id may be taken by the time the mint is sent.
+    #[graphql(
+        guard = "RequireScope(Scope::WriteNfts).and(RequireCollectionOwner::new(chain_id, &contract_address))"
+    )]
+    async fn next_token_id(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i32,
+        contract_address: String,
+    ) -> AppResponse<i64> {
         let collection_query = CollectionQuery {
-            owner: Some(encrypt_user_info.address.clone()),
             contract_address: Some(contract_address),
+            chain_id: Some(chain_id),
             ..Default::default()
         };
-        let collection = Collection::find_by_query(collection_query)
+        let collection = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| Collection::find_by_query(connection, collection_query))
             .await?
             .ok_or(AppError::CollectionNotFound)?;
+        Ok(Some(collection.next_token_id as i64))
+    }
+}
 
-        // Find NFT count by collection
-        let count = models::nft::NFTQuery {
-            collection: Some(collection.contract_address),
-            ..Default::default()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nft_trait(trait_value: &str, display_type: Option<&str>) -> NFTTrait {
+        NFTTrait {
+            id: uuid::Uuid::new_v4(),
+            nft_id: uuid::Uuid::nil(),
+            trait_type: "Level".to_string(),
+            trait_value: trait_value.to_string(),
+            created_at: None,
+            updated_at: None,
+            display_type: display_type.map(str::to_string),
+            max_value: display_type.map(|_| 10.0),
         }
-        .count()
-        .await?;
-        Ok(Some(count + 1))
+    }
+
+    fn new_trait(trait_value: &str, display_type: Option<TraitDisplayType>) -> NewNFTTrait {
+        NewNFTTrait {
+            trait_type: "Level".to_string(),
+            trait_value: trait_value.to_string(),
+            display_type,
+            max_value: None,
+        }
+    }
+
+    #[test]
+    fn opensea_attributes_keep_numeric_values_as_numbers() {
+        let traits = vec![nft_trait("Gold", None), nft_trait("5", Some("number"))];
+        let metadata: serde_json::Value =
+            serde_json::from_str(&render_nft_metadata(&collection(true), &nft(), &traits)).unwrap();
+        assert_eq!(
+            metadata,
+            serde_json::json!({
+                "name": "Demo #1",
+                "image": "ipfs://real",
+                "attributes": [
+                    {"trait_type": "Level", "value": "Gold"},
+                    {
+                        "display_type": "number",
+                        "trait_type": "Level",
+                        "value": 5,
+                        "max_value": 10.0
+                    }
+                ]
+            })
+        );
+    }
+
+    fn collection(revealed: bool) -> Collection {
+        Collection {
+            id: uuid::Uuid::new_v4(),
+            name: "Demo".to_string(),
+            symbol: "DEMO".to_string(),
+            owner: "0x0000000000000000000000000000000000000001".to_string(),
+            pic_url: String::new(),
+            contract_address: "0x0000000000000000000000000000000000000002".to_string(),
+            chain_id: 1,
+            dir_name: "demo".to_string(),
+            dir_hash: String::new(),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+            next_token_id: 2,
+            deleted_at: None,
+            metadata_format: MetadataFormat::OpenSea.as_str().to_string(),
+            description: None,
+            banner_url: None,
+            external_link: None,
+            seller_fee_basis_points: 0,
+            fee_recipient: None,
+            contract_cid: None,
+            revealed,
+            placeholder_name: None,
+            placeholder_description: Some("Revealed soon".to_string()),
+            placeholder_image_url: Some("ipfs://placeholder".to_string()),
+        }
+    }
+
+    fn nft() -> NFT {
+        NFT {
+            id: uuid::Uuid::new_v4(),
+            token_id: 1,
+            name: "Demo #1".to_string(),
+            description: None,
+            image_url: "ipfs://real".to_string(),
+            supply: 1,
+            external_link: None,
+            owner: "0x0000000000000000000000000000000000000001".to_string(),
+            collection: "0x0000000000000000000000000000000000000002".to_string(),
+            created_at: None,
+            updated_at: None,
+            publish_status: PublishStatus::Pending.as_str().to_string(),
+            chain_id: 1,
+            deleted_at: None,
+            animation_url: None,
+            background_color: None,
+        }
+    }
+
+    #[test]
+    fn unrevealed_collections_publish_the_placeholder() {
+        let traits = vec![nft_trait("Gold", None)];
+        let placeholder: serde_json::Value = serde_json::from_str(&render_published_nft_metadata(
+            &collection(false),
+            &nft(),
+            &traits,
+        ))
+        .unwrap();
+        assert_eq!(
+            placeholder,
+            serde_json::json!({
+                "name": "Demo",
+                "description": "Revealed soon",
+                "image": "ipfs://placeholder",
+                "attributes": []
+            })
+        );
+        assert_eq!(
+            render_published_nft_metadata(&collection(true), &nft(), &traits),
+            render_nft_metadata(&collection(true), &nft(), &traits)
+        );
+    }
+
+    #[test]
+    fn token_ids_are_bounded() {
+        assert!(validate_token_id(0).is_ok());
+        assert!(validate_token_id(i32::MAX - 1).is_ok());
+        assert_eq!(validate_token_id(-1), Err(AppError::InvalidTokenId));
+        assert_eq!(validate_token_id(i32::MAX), Err(AppError::InvalidTokenId));
+    }
+
+    #[test]
+    fn metadata_is_validated() {
+        assert!(validate_nft_metadata(Some("a1B2c3"), &[]).is_ok());
+        assert_eq!(
+            validate_nft_metadata(Some("#a1b2c3"), &[]),
+            Err(AppError::InvalidNFTMetadata)
+        );
+        assert!(validate_nft_metadata(
+            None,
+            &[
+                new_trait("Gold", None),
+                new_trait("1.5", Some(TraitDisplayType::BoostPercentage)),
+                new_trait("1716000000", Some(TraitDisplayType::Date)),
+            ]
+        )
+        .is_ok());
+        assert_eq!(
+            validate_nft_metadata(None, &[new_trait("Gold", Some(TraitDisplayType::Number))]),
+            Err(AppError::InvalidNFTMetadata)
+        );
+        assert_eq!(
+            validate_nft_metadata(None, &[new_trait("1.5", Some(TraitDisplayType::Date))]),
+            Err(AppError::InvalidNFTMetadata)
+        );
     }
 }
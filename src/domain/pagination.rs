@@ -0,0 +1,101 @@
+use async_graphql::connection::{Connection, CursorType, Edge};
+use async_graphql::OutputType;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::AppError;
+
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+pub const MAX_PAGE_SIZE: i32 = 100;
+
+/// Opaque keyset cursor: the sort keys of the last row seen, as base64url JSON.
+pub struct Cursor<T>(pub T);
+
+impl<T: Serialize + DeserializeOwned> CursorType for Cursor<T> {
+    type Error = AppError;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| AppError::InvalidCursor)?;
+        serde_json::from_slice(&bytes)
+            .map(Cursor)
+            .map_err(|_| AppError::InvalidCursor)
+    }
+
+    fn encode_cursor(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&self.0).unwrap())
+    }
+}
+
+/// Decodes the `after` argument of a connection field.
+pub fn decode_after<T: Serialize + DeserializeOwned>(
+    after: Option<String>,
+) -> Result<Option<T>, AppError> {
+    after
+        .map(|after| Cursor::<T>::decode_cursor(&after).map(|cursor| cursor.0))
+        .transpose()
+}
+
+/// Validates the `first` argument. One row more than this is fetched to tell whether
+/// there is a next page.
+pub fn page_size(first: Option<i32>) -> Result<i64, AppError> {
+    match first.unwrap_or(DEFAULT_PAGE_SIZE) {
+        first @ 0..=MAX_PAGE_SIZE => Ok(first as i64),
+        _ => Err(AppError::InvalidPageSize),
+    }
+}
+
+/// Builds a page from up to `page_size + 1` rows fetched after the `after` cursor.
+pub fn into_connection<R, C, N>(
+    mut rows: Vec<R>,
+    page_size: i64,
+    has_previous_page: bool,
+    cursor: impl Fn(&R) -> C,
+    node: impl Fn(R) -> N,
+) -> Connection<Cursor<C>, N>
+where
+    C: Serialize + DeserializeOwned + Send + Sync,
+    N: OutputType,
+{
+    let has_next_page = rows.len() as i64 > page_size;
+    rows.truncate(page_size as usize);
+    let mut connection = Connection::new(has_previous_page, has_next_page);
+    connection.edges.extend(
+        rows.into_iter()
+            .map(|row| Edge::new(Cursor(cursor(&row)), node(row))),
+    );
+    connection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip() {
+        let encoded = Cursor((7, "b".to_string())).encode_cursor();
+        assert_eq!(
+            decode_after::<(i32, String)>(Some(encoded)).unwrap(),
+            Some((7, "b".to_string()))
+        );
+        assert_eq!(decode_after::<(i32, String)>(None).unwrap(), None);
+        assert_eq!(
+            decode_after::<(i32, String)>(Some("not a cursor".to_string())).unwrap_err(),
+            AppError::InvalidCursor
+        );
+    }
+
+    #[test]
+    fn page_size_is_bounded() {
+        assert_eq!(page_size(None).unwrap(), DEFAULT_PAGE_SIZE as i64);
+        assert_eq!(page_size(Some(0)).unwrap(), 0);
+        assert_eq!(page_size(Some(-1)), Err(AppError::InvalidPageSize));
+        assert_eq!(
+            page_size(Some(MAX_PAGE_SIZE + 1)),
+            Err(AppError::InvalidPageSize)
+        );
+    }
+}
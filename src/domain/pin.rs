@@ -0,0 +1,101 @@
+use diesel::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::pin::{InsertedPin, Pin},
+    models::remote_pin::RemotePin,
+};
+
+use super::ipfs_job::{self, IpfsOperation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinKind {
+    /// A collection's metadata directory; moved with `pin/update` on every flush.
+    Directory,
+    /// A file uploaded through `uploadFile`.
+    Image,
+}
+
+impl PinKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PinKind::Directory => "directory",
+            PinKind::Image => "image",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "directory" => Some(PinKind::Directory),
+            "image" => Some(PinKind::Image),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinStatus {
+    Pinned,
+    /// Found unpinned or incomplete; a re-pin job is queued.
+    Missing,
+}
+
+impl PinStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PinStatus::Pinned => "pinned",
+            PinStatus::Missing => "missing",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "missing" => PinStatus::Missing,
+            _ => PinStatus::Pinned,
+        }
+    }
+}
+
+/// Records an uploaded file, which `add` already pinned, so reconciliation watches it.
+pub fn record_image_pin(connection: &mut PgConnection, cid: String) -> Result<Pin, AppError> {
+    InsertedPin {
+        cid,
+        kind: PinKind::Image.as_str().to_string(),
+        collection_id: None,
+    }
+    .upsert(connection)
+}
+
+/// Queues a pin of `cid` to each remote service. Directory pins pass their collection, so
+/// the service's previous request for it is replaced.
+pub fn enqueue_remote_pins<'a>(
+    connection: &mut PgConnection,
+    services: impl IntoIterator<Item = &'a String>,
+    cid: &String,
+    collection_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    for service in services {
+        let operation = IpfsOperation::RemotePin {
+            service: service.clone(),
+            cid: cid.clone(),
+        };
+        ipfs_job::enqueue(connection, &operation, collection_id, None)?;
+    }
+    Ok(())
+}
+
+/// Forgets the collection's remote requests and queues their removal from each service.
+pub fn enqueue_remote_unpins(
+    connection: &mut PgConnection,
+    collection_id: Uuid,
+) -> Result<(), AppError> {
+    for remote_pin in RemotePin::delete_by_collection(connection, collection_id)? {
+        let operation = IpfsOperation::RemoteUnpin {
+            service: remote_pin.service,
+            request_id: remote_pin.request_id,
+        };
+        ipfs_job::enqueue(connection, &operation, Some(collection_id), None)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,330 @@
+use std::str::FromStr;
+
+use async_graphql::{Context, Object, SimpleObject};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    keys,
+    models::refresh_token::{InsertedRefreshToken, RefreshToken, RotatedRefreshToken},
+    models::revoked_token::{BatchInsertedRevokedToken, InsertedRevokedToken, RevokedToken},
+    models::user::User,
+    models::Database,
+};
+
+use super::guard::{current_user_info, RequireSession, Role};
+use super::token::{EncryptUserInfo, Token};
+
+#[derive(Default)]
+pub struct SessionMutation;
+#[derive(Default)]
+pub struct SessionQuery;
+
+/// Device details of the HTTP client, recorded against the sessions it starts.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SessionResult {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub current: bool,
+    pub last_used_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl SessionResult {
+    fn new(refresh_token: RefreshToken, current_session_id: &Uuid) -> Self {
+        Self {
+            id: refresh_token.id.to_string(),
+            user_agent: refresh_token.user_agent,
+            ip_address: refresh_token.ip_address,
+            current: &refresh_token.id == current_session_id,
+            last_used_at: refresh_token.last_used_at,
+            expired_at: refresh_token.expired_at,
+            created_at: refresh_token.created_at,
+        }
+    }
+}
+
+fn generate_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn hash_refresh_token(refresh_token: &str) -> String {
+    hex::encode(Sha256::digest(refresh_token.as_bytes()))
+}
+
+/// Opens a new session for `address` and returns its first access/refresh token pair.
+pub async fn start_session(
+    database: &Database,
+    refresh_token_ttl: Duration,
+    address: String,
+    client_info: ClientInfo,
+) -> Result<Token, AppError> {
+    let refresh_token = generate_refresh_token();
+    let token_hash = hash_refresh_token(&refresh_token);
+    let user_info = database
+        .run(move |connection| {
+            let session_id = Uuid::new_v4();
+            let role = user_role(connection, &address)?;
+            let user_info = EncryptUserInfo::new(address.clone(), session_id, role);
+            InsertedRefreshToken {
+                id: session_id,
+                address,
+                token_hash,
+                access_token_jti: user_info.jti.clone(),
+                user_agent: client_info.user_agent,
+                ip_address: client_info.ip_address,
+                expired_at: (Utc::now() + refresh_token_ttl).naive_utc(),
+            }
+            .insert(connection)?;
+            Ok(user_info)
+        })
+        .await?;
+    Token::generate(&user_info).map(|token| token.with_refresh_token(refresh_token))
+}
+
+/// Blocks the latest access token of every given session until it would have expired.
+pub(crate) fn revoke_access_tokens(
+    connection: &mut PgConnection,
+    sessions: &[RefreshToken],
+) -> Result<(), AppError> {
+    let expired_at = (Utc::now() + keys::current().access_token_ttl()).naive_utc();
+    BatchInsertedRevokedToken {
+        tokens: sessions
+            .iter()
+            .map(|session| InsertedRevokedToken {
+                jti: session.access_token_jti.clone(),
+                expired_at,
+            })
+            .collect(),
+    }
+    .insert(connection)?;
+    let _ = RevokedToken::delete_expired(connection);
+    Ok(())
+}
+
+/// Role to embed in the next access token; addresses without a profile are plain users.
+fn user_role(connection: &mut PgConnection, address: &str) -> Result<Role, AppError> {
+    Ok(User::find_by_address(connection, address.to_string())?
+        .and_then(|user| Role::from_str(&user.role).ok())
+        .unwrap_or_default())
+}
+
+/// Rotates the session's refresh token and issues a new access/refresh token pair.
+pub async fn refresh_session(
+    database: &Database,
+    refresh_token: String,
+    client_info: ClientInfo,
+) -> Result<Token, AppError> {
+    let token_hash = hash_refresh_token(&refresh_token);
+    let new_refresh_token = generate_refresh_token();
+    let new_token_hash = hash_refresh_token(&new_refresh_token);
+    let user_info = database
+        .run(move |connection| {
+            let session = match RefreshToken::find_active_by_hash(connection, token_hash.clone())? {
+                Some(session) => session,
+                None => {
+                    if let Some(session) =
+                        RefreshToken::find_by_previous_hash(connection, token_hash)?
+                    {
+                        tracing::warn!("refresh token reused for session {}", session.id);
+                        if let Some(session) =
+                            RefreshToken::revoke(connection, session.id, session.address)?
+                        {
+                            revoke_access_tokens(connection, &[session])?;
+                        }
+                    }
+                    return Err(AppError::InvalidRefreshToken);
+                }
+            };
+
+            let role = user_role(connection, &session.address)?;
+            let user_info = EncryptUserInfo::new(session.address.clone(), session.id, role);
+            RefreshToken::rotate(
+                connection,
+                session.id,
+                token_hash.clone(),
+                RotatedRefreshToken {
+                    token_hash: new_token_hash,
+                    previous_token_hash: token_hash,
+                    access_token_jti: user_info.jti.clone(),
+                    user_agent: client_info.user_agent,
+                    ip_address: client_info.ip_address,
+                    last_used_at: Utc::now().naive_utc(),
+                    updated_at: Utc::now().naive_utc(),
+                },
+            )?
+            .ok_or(AppError::InvalidRefreshToken)?;
+            Ok(user_info)
+        })
+        .await?;
+    Token::generate(&user_info).map(|token| token.with_refresh_token(new_refresh_token))
+}
+
+/// Ends the session `encrypt_user_info` was issued for and blocks its access token.
+/// Returns whether the session was still active.
+pub async fn end_session(
+    database: &Database,
+    encrypt_user_info: &EncryptUserInfo,
+) -> Result<bool, AppError> {
+    let (session_id, address) = (encrypt_user_info.sid, encrypt_user_info.address.clone());
+    let expired_at = DateTime::from_timestamp(encrypt_user_info.exp as i64, 0)
+        .map(|exp| exp.naive_utc())
+        .unwrap_or_else(|| (Utc::now() + keys::current().access_token_ttl()).naive_utc());
+    let revoked_token = InsertedRevokedToken {
+        jti: encrypt_user_info.jti.clone(),
+        expired_at,
+    };
+    let session = database
+        .run(move |connection| {
+            let session = RefreshToken::revoke(connection, session_id, address)?;
+            BatchInsertedRevokedToken {
+                tokens: vec![revoked_token],
+            }
+            .insert(connection)?;
+            Ok(session)
+        })
+        .await?;
+    Ok(session.is_some())
+}
+
+#[Object]
+impl SessionMutation {
+    /// Exchanges a refresh token for a new access/refresh token pair. The presented
+    /// refresh token stops working; presenting it again revokes the whole session.
+    pub async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        refresh_token: String,
+    ) -> Result<Token, AppError> {
+        let client_info = ctx.data_opt::<ClientInfo>().cloned().unwrap_or_default();
+        refresh_session(ctx.data_unchecked::<Database>(), refresh_token, client_info).await
+    }
+
+    /// Ends the session the current access token belongs to.
+    #[graphql(guard = "RequireSession")]
+    pub async fn logout(&self, ctx: &Context<'_>) -> Result<bool, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        end_session(ctx.data_unchecked::<Database>(), encrypt_user_info).await
+    }
+
+    /// Ends one of the current user's sessions, e.g. on a lost device.
+    #[graphql(guard = "RequireSession")]
+    pub async fn revoke_session(&self, ctx: &Context<'_>, id: String) -> Result<bool, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let id = Uuid::parse_str(&id).map_err(|_| AppError::SessionNotFound)?;
+        let address = encrypt_user_info.address.clone();
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                let session = RefreshToken::revoke(connection, id, address)?
+                    .ok_or(AppError::SessionNotFound)?;
+                revoke_access_tokens(connection, &[session])
+            })
+            .await?;
+        Ok(true)
+    }
+
+    /// Ends every session of the current user, including this one.
+    /// Returns the number of sessions revoked.
+    #[graphql(guard = "RequireSession")]
+    pub async fn revoke_all_sessions(&self, ctx: &Context<'_>) -> Result<i32, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        let sessions = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| {
+                let sessions = RefreshToken::revoke_all_by_address(connection, address)?;
+                revoke_access_tokens(connection, &sessions)?;
+                Ok(sessions)
+            })
+            .await?;
+        Ok(sessions.len() as i32)
+    }
+}
+
+#[Object]
+impl SessionQuery {
+    #[graphql(guard = "RequireSession")]
+    pub async fn my_sessions(&self, ctx: &Context<'_>) -> Result<Vec<SessionResult>, AppError> {
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        let sessions = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| RefreshToken::list_active_by_address(connection, address))
+            .await?;
+        Ok(sessions
+            .into_iter()
+            .map(|session| SessionResult::new(session, &encrypt_user_info.sid))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::Duration as StdDuration;
+
+    use super::*;
+    use crate::config::JwtConfig;
+    use crate::models::run_pending_migrations;
+
+    /// Needs a scratch database; run with `cargo test -- --ignored` and `DATABASE_URL_TEST` set.
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    async fn ending_a_session_rejects_its_earlier_access_tokens() {
+        let database_url = env::var("DATABASE_URL_TEST").expect("DATABASE_URL_TEST is not set");
+        let database = Database::new(&database_url, 2, StdDuration::from_secs(5));
+        database
+            .run(|connection| run_pending_migrations(connection))
+            .await
+            .unwrap();
+        // another test may have loaded the keys already
+        let _ = keys::init(&JwtConfig {
+            secret: Some("session test secret".to_string()),
+            keys_file: None,
+            keys_reload_seconds: 3600,
+            access_token_ttl_minutes: 5,
+            refresh_token_ttl_days: 1,
+        });
+        let address = format!("0x{}00000000", Uuid::new_v4().simple());
+
+        let first = start_session(&database, Duration::days(1), address, ClientInfo::default())
+            .await
+            .unwrap();
+        let second = refresh_session(
+            &database,
+            first.refresh_token.clone().unwrap(),
+            ClientInfo::default(),
+        )
+        .await
+        .unwrap();
+        let third = refresh_session(
+            &database,
+            second.refresh_token.clone().unwrap(),
+            ClientInfo::default(),
+        )
+        .await
+        .unwrap();
+        assert!(first.parse(&database).await.is_ok());
+
+        let encrypt_user_info = third.parse(&database).await.unwrap();
+        assert!(end_session(&database, &encrypt_user_info).await.unwrap());
+        for token in [&first, &second, &third] {
+            assert_eq!(
+                token.parse(&database).await.err(),
+                Some(AppError::TokenRevoked)
+            );
+        }
+    }
+}
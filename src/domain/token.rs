@@ -1,14 +1,33 @@
-use std::env;
+use std::str::FromStr;
 use std::string::ToString;
+use std::sync::Arc;
 
 use async_graphql::{Context, Data, Object, SimpleObject, Subscription};
 use chrono::{Duration, Utc};
 use futures_util::Stream;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use once_cell::sync::Lazy;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 
-use crate::{errors::AppError, models::user::User};
+use uuid::Uuid;
+use web3_api::chain::ChainRpcRegistry;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    keys,
+    models::login_nonce::{InsertedLoginNonce, LoginNonce},
+    models::refresh_token::RefreshToken,
+    models::revoked_token::RevokedToken,
+    models::user::User,
+    models::Database,
+    siwe::{self, SiweMessage},
+};
+
+use super::guard::{current_user_info, Authentication, RequireScope, Role, Scope};
+use super::session::{start_session, ClientInfo};
+
+/// How long a nonce handed out by `requestLoginNonce` can be redeemed.
+const LOGIN_NONCE_TTL_MINUTES: i64 = 10;
 
 #[derive(Default)]
 pub struct TokenMutation;
@@ -21,6 +40,8 @@ pub struct TokenSubscription;
 pub struct Token {
     pub secret: String,
     pub token_type: String,
+    /// Only set when a session is started or refreshed.
+    pub refresh_token: Option<String>,
 }
 
 impl Token {
@@ -28,9 +49,15 @@ impl Token {
         Self {
             secret,
             token_type: "Bearer".to_string(),
+            refresh_token: None,
         }
     }
 
+    pub fn with_refresh_token(mut self, refresh_token: String) -> Self {
+        self.refresh_token = Some(refresh_token);
+        self
+    }
+
     pub fn parse_from_access_token(access_token: String) -> Result<Self, AppError> {
         if access_token.starts_with("Bearer ") {
             Ok(Self::new(access_token.replacen("Bearer ", "", 1)))
@@ -39,70 +66,94 @@ impl Token {
         }
     }
 
-    pub fn generate(address: String) -> std::result::Result<Token, AppError> {
-        let user_info = EncryptUserInfo::new(address);
-        return encode(&Header::default(), &user_info, &KEYS.encoding)
+    pub fn generate(user_info: &EncryptUserInfo) -> std::result::Result<Token, AppError> {
+        let key_store = keys::current();
+        let key = key_store
+            .signing_key(Utc::now())
+            .ok_or(AppError::TokenCreation)?;
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.clone();
+        return encode(&header, user_info, &key.encoding)
             .map(|token| Token::new(token))
             .map_err(|_| AppError::TokenCreation);
     }
 
-    pub fn parse(&self) -> std::result::Result<EncryptUserInfo, AppError> {
-        return decode::<EncryptUserInfo>(
+    pub async fn parse(
+        &self,
+        database: &Database,
+    ) -> std::result::Result<EncryptUserInfo, AppError> {
+        let header = decode_header(self.secret.as_str()).map_err(|err| {
+            tracing::error!("token parse error: {:?}", err);
+            AppError::InvalidToken
+        })?;
+        let key_store = keys::current();
+        let key = key_store
+            .verification_key(header.kid.as_deref(), Utc::now())
+            .ok_or(AppError::InvalidToken)?;
+        // Pin the algorithm to the key so a token cannot pick a weaker one.
+        let encrypt_user_info = decode::<EncryptUserInfo>(
             self.secret.as_str(),
-            &KEYS.decoding,
-            &Validation::default(),
+            &key.decoding,
+            &Validation::new(key.algorithm),
         )
         .map(|data| data.claims)
         .map_err(|err| {
             tracing::error!("token parse error: {:?}", err);
             AppError::InvalidToken
-        });
-    }
-
-    pub async fn current_user(&self) -> Result<User, AppError> {
-        let encrypt_user_info = self.parse()?;
-        User::find_by_address(encrypt_user_info.address)
-            .await
-            .map(|user| user.ok_or(AppError::UserNotFound))?
+        })?;
+        // Sessions are ended by revoking their refresh token, which also covers access
+        // tokens issued before the latest refresh.
+        let (jti, session_id) = (encrypt_user_info.jti.clone(), encrypt_user_info.sid);
+        if database
+            .run(move |connection| {
+                Ok(RevokedToken::exists(connection, jti)?
+                    || !RefreshToken::is_active(connection, session_id)?)
+            })
+            .await?
+        {
+            return Err(AppError::TokenRevoked);
+        }
+        Ok(encrypt_user_info)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EncryptUserInfo {
     pub address: String,
+    /// Session (refresh token row) the access token was issued for.
+    pub sid: Uuid,
+    pub jti: String,
+    pub iat: usize,
     pub exp: usize,
+    /// Tokens issued before roles existed carry none and act as plain users.
+    #[serde(default)]
+    pub role: Role,
+    /// Set for API key principals only; session tokens may do anything their role allows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,
 }
 
 impl EncryptUserInfo {
-    pub fn new(address: String) -> Self {
+    pub fn new(address: String, session_id: Uuid, role: Role) -> Self {
+        let issued_at = Utc::now();
         Self {
             address: address.to_lowercase(),
-            exp: (Utc::now().naive_utc() + Duration::days(1))
-                .and_utc()
-                .timestamp() as usize,
+            sid: session_id,
+            jti: Uuid::new_v4().to_string(),
+            iat: issued_at.timestamp() as usize,
+            exp: (issued_at + keys::current().access_token_ttl()).timestamp() as usize,
+            role,
+            scopes: None,
         }
     }
-}
-
-struct Keys {
-    encoding: EncodingKey,
-    decoding: DecodingKey,
-}
 
-impl Keys {
-    fn new(secret: &[u8]) -> Self {
-        Self {
-            encoding: EncodingKey::from_secret(secret),
-            decoding: DecodingKey::from_secret(secret),
-        }
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
     }
 }
 
-static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    Keys::new(secret.as_bytes())
-});
-
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct CurrentUserResult {
     pub id: String,
@@ -110,6 +161,7 @@ pub struct CurrentUserResult {
     pub address: String,
     pub email: Option<String>,
     pub avatar_url: Option<String>,
+    pub role: Role,
 }
 
 impl From<User> for CurrentUserResult {
@@ -120,20 +172,39 @@ impl From<User> for CurrentUserResult {
             address: user.address,
             email: user.email,
             avatar_url: user.avatar_url,
+            role: Role::from_str(&user.role).unwrap_or_default(),
         }
     }
 }
 
 #[Object]
 impl TokenQuery {
+    #[graphql(guard = "RequireScope(Scope::Read)")]
     async fn current_user<'a>(&self, ctx: &Context<'_>) -> Result<CurrentUserResult, AppError> {
-        let token = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?;
-        token
-            .current_user()
-            .await
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| User::find_by_address(connection, address))
+            .await?
             .map(|user| CurrentUserResult::from(user))
+            .ok_or(AppError::UserNotFound)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct LoginNonceResult {
+    pub address: String,
+    pub nonce: String,
+    pub expired_at: chrono::NaiveDateTime,
+}
+
+impl From<LoginNonce> for LoginNonceResult {
+    fn from(login_nonce: LoginNonce) -> Self {
+        Self {
+            address: login_nonce.address,
+            nonce: login_nonce.nonce,
+            expired_at: login_nonce.expired_at,
+        }
     }
 }
 
@@ -149,12 +220,67 @@ impl TokenSubscription {
 
 #[Object]
 impl TokenMutation {
-    pub async fn generate_token(&self, address: String) -> Result<Token, AppError> {
-        Token::generate(address)
+    /// Issues a single-use nonce to be embedded in the EIP-4361 message signed by `address`.
+    pub async fn request_login_nonce(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+    ) -> Result<LoginNonceResult, AppError> {
+        if !siwe::is_address(&address) {
+            return Err(AppError::InvalidSiweMessage);
+        }
+        let inserted_login_nonce = InsertedLoginNonce {
+            address: address.to_lowercase(),
+            nonce: Uuid::new_v4().simple().to_string(),
+            expired_at: (Utc::now() + Duration::minutes(LOGIN_NONCE_TTL_MINUTES)).naive_utc(),
+        };
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                let _ = LoginNonce::delete_expired(connection);
+                inserted_login_nonce.insert(connection)
+            })
+            .await
+            .map(LoginNonceResult::from)
+    }
+
+    /// Verifies a signed EIP-4361 message and starts a session for its signer.
+    pub async fn verify_login(
+        &self,
+        ctx: &Context<'_>,
+        message: String,
+        signature: String,
+    ) -> Result<Token, AppError> {
+        let config = ctx.data_unchecked::<Arc<Config>>();
+        let siwe_message = SiweMessage::from_str(&message)?;
+        siwe_message.validate(&config.siwe.domain, Utc::now())?;
+
+        let chain_rpcs = ctx.data_unchecked::<ChainRpcRegistry>();
+        siwe::verify_signature(chain_rpcs, &siwe_message, &message, &signature).await?;
+
+        let database = ctx.data_unchecked::<Database>();
+        let address = siwe_message.address.to_lowercase();
+        let nonce_address = address.clone();
+        database
+            .run(move |connection| {
+                LoginNonce::consume(connection, nonce_address, siwe_message.nonce)
+            })
+            .await?
+            .ok_or(AppError::InvalidLoginNonce)?;
+        let client_info = ctx.data_opt::<ClientInfo>().cloned().unwrap_or_default();
+        start_session(
+            database,
+            config.jwt.refresh_token_ttl(),
+            address,
+            client_info,
+        )
+        .await
     }
 }
 
-pub async fn on_connection_init(value: serde_json::Value) -> async_graphql::Result<Data> {
+pub async fn on_connection_init(
+    database: Database,
+    value: serde_json::Value,
+) -> async_graphql::Result<Data> {
     #[derive(serde::Deserialize)]
     struct Payload {
         token: String,
@@ -164,6 +290,7 @@ pub async fn on_connection_init(value: serde_json::Value) -> async_graphql::Resu
         let mut data = Data::default();
         return match Token::parse_from_access_token(payload.token) {
             Ok(token) => {
+                data.insert(Authentication::from_token(&database, &token).await);
                 data.insert(token);
                 Ok(data)
             }
@@ -1,10 +1,19 @@
-use async_graphql::{Context, InputObject, Object, SimpleObject};
+use std::str::FromStr;
+
+use async_graphql::{Context, InputObject, MaybeUndefined, Object, SimpleObject};
+use diesel::Connection;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{errors::AppError, models::user::InsertedUser, models::user::User};
+use crate::{
+    errors::AppError,
+    models::refresh_token::RefreshToken,
+    models::user::{InsertedUser, UpdatedUser, User},
+    models::Database,
+};
 
-use super::token::Token;
+use super::guard::{current_user_info, RequireRole, RequireScope, RequireSession, Role, Scope};
+use super::session::revoke_access_tokens;
 
 #[derive(Default)]
 pub struct UserMutation;
@@ -18,6 +27,24 @@ pub struct NewUser {
     pub avatar_url: Option<String>,
 }
 
+/// Fields to change; omitted ones are left as they are and `null` clears them.
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
+pub struct UpdateUserProfile {
+    pub name: MaybeUndefined<String>,
+    pub email: MaybeUndefined<String>,
+    pub avatar_url: MaybeUndefined<String>,
+}
+
+impl From<UpdateUserProfile> for UpdatedUser {
+    fn from(changes: UpdateUserProfile) -> Self {
+        Self {
+            name: changes.name.into(),
+            email: changes.email.into(),
+            avatar_url: changes.avatar_url.into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct CreateUserResult {
     pub id: String,
@@ -25,6 +52,7 @@ pub struct CreateUserResult {
     pub address: String,
     pub email: Option<String>,
     pub avatar_url: Option<String>,
+    pub role: Role,
 }
 
 impl From<User> for CreateUserResult {
@@ -35,6 +63,7 @@ impl From<User> for CreateUserResult {
             address: user.address,
             email: user.email,
             avatar_url: user.avatar_url,
+            role: Role::from_str(&user.role).unwrap_or_default(),
         }
     }
 }
@@ -46,6 +75,7 @@ pub struct UserResult {
     pub address: String,
     pub email: Option<String>,
     pub avatar_url: Option<String>,
+    pub role: Role,
 }
 
 impl From<User> for UserResult {
@@ -56,47 +86,128 @@ impl From<User> for UserResult {
             address: user.address,
             email: user.email,
             avatar_url: user.avatar_url,
+            role: Role::from_str(&user.role).unwrap_or_default(),
+        }
+    }
+}
+
+/// What anyone may see of a user: no email and no role.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct UserProfileResult {
+    pub name: Option<String>,
+    pub address: String,
+    pub avatar_url: Option<String>,
+}
+
+impl From<User> for UserProfileResult {
+    fn from(user: User) -> Self {
+        Self {
+            name: user.name,
+            address: user.address,
+            avatar_url: user.avatar_url,
         }
     }
 }
 
 #[Object]
 impl UserMutation {
+    #[graphql(guard = "RequireSession")]
     pub async fn create_user(
         &self,
         ctx: &Context<'_>,
         user: NewUser,
     ) -> Result<CreateUserResult, AppError> {
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-        let address = encrypt_user_info.address;
-        let exists_user = User::find_by_address(address.clone()).await?;
-        match exists_user {
-            Some(user) => Ok(CreateUserResult::from(user)),
-            None => InsertedUser {
-                name: user.name,
-                email: user.email,
-                avatar_url: user.avatar_url,
-                address: address.clone(),
-            }
-            .insert()
+        let address = current_user_info(ctx)?.address.clone();
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                let exists_user = User::find_by_address(connection, address.clone())?;
+                match exists_user {
+                    Some(user) => Ok(CreateUserResult::from(user)),
+                    None => InsertedUser {
+                        name: user.name,
+                        email: user.email,
+                        avatar_url: user.avatar_url,
+                        address: address.clone(),
+                    }
+                    .insert(connection)
+                    .map(|user| CreateUserResult::from(user)),
+                }
+            })
             .await
-            .map(|user| CreateUserResult::from(user)),
-        }
+    }
+
+    #[graphql(guard = "RequireSession")]
+    pub async fn update_user_profile(
+        &self,
+        ctx: &Context<'_>,
+        changes: UpdateUserProfile,
+    ) -> Result<UserResult, AppError> {
+        let address = current_user_info(ctx)?.address.clone();
+        let changes = UpdatedUser::from(changes);
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| User::update_profile(connection, address, &changes))
+            .await?
+            .map(UserResult::from)
+            .ok_or(AppError::UserNotFound)
+    }
+
+    /// Grants `role` to the user at `address`. A change of role ends all of their sessions,
+    /// so tokens carrying the old role stop working.
+    #[graphql(guard = "RequireRole(Role::Admin)")]
+    pub async fn set_user_role(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+        role: Role,
+    ) -> Result<UserResult, AppError> {
+        let address = address.to_lowercase();
+        ctx.data_unchecked::<Database>()
+            .run(move |connection| {
+                connection.transaction(|connection| {
+                    let previous = match User::find_by_address(connection, address.clone())? {
+                        Some(user) => user.role,
+                        None => return Ok(None),
+                    };
+                    let user =
+                        User::update_role(connection, address.clone(), role.as_str().to_string())?;
+                    if previous != role.as_str() {
+                        let sessions = RefreshToken::revoke_all_by_address(connection, address)?;
+                        revoke_access_tokens(connection, &sessions)?;
+                    }
+                    Ok(user)
+                })
+            })
+            .await?
+            .map(|user| UserResult::from(user))
+            .ok_or(AppError::UserNotFound)
     }
 }
 
 #[Object]
 impl UserQuery {
+    #[graphql(guard = "RequireScope(Scope::Read)")]
     pub async fn find_by_address(&self, ctx: &Context<'_>) -> Result<UserResult, AppError> {
-        let encrypt_user_info = ctx
-            .data_opt::<Token>()
-            .ok_or(AppError::MissingCredentials)?
-            .parse()?;
-        let user = User::find_by_address(encrypt_user_info.address).await?;
+        let encrypt_user_info = current_user_info(ctx)?;
+        let address = encrypt_user_info.address.clone();
+        let user = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| User::find_by_address(connection, address))
+            .await?;
         user.map(|user| UserResult::from(user))
             .ok_or(AppError::UserNotFound)
     }
+
+    /// The public profile of the user at `address`. Public.
+    pub async fn user_profile(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+    ) -> Result<Option<UserProfileResult>, AppError> {
+        let address = address.to_lowercase();
+        let user = ctx
+            .data_unchecked::<Database>()
+            .run(move |connection| User::find_by_address(connection, address))
+            .await?;
+        Ok(user.map(UserProfileResult::from))
+    }
 }
@@ -16,6 +16,32 @@ pub enum AppError {
     WrongCredentials,
     TokenCreation,
     MissingCredentials,
+    TokenRevoked,
+    Forbidden,
+    InvalidRole,
+
+    // SESSION
+    InvalidRefreshToken,
+    SessionNotFound,
+    CreateSessionFailed,
+    SessionQueryError,
+
+    // API KEY
+    InvalidApiKey,
+    InvalidScope,
+    InvalidApiKeyExpiry,
+    ApiKeyNotFound,
+    CreateApiKeyFailed,
+    ApiKeyQueryError,
+
+    // LOGIN
+    InvalidSiweMessage,
+    SiweMessageExpired,
+    SiweMessageNotYetValid,
+    InvalidSignature,
+    InvalidLoginNonce,
+    CreateLoginNonceFailed,
+    LoginNonceQueryError,
 
     // UPLOAD
     UploadMissingFile,
@@ -27,26 +53,55 @@ pub enum AppError {
     RequestIpfsResponseNoBody,
     RequestIpfsResponseBodyDeserializeFailed,
 
+    // IPFS JOB
+    IpfsJobNotFound,
+    IpfsJobQueryError,
+    CreateIpfsJobFailed,
+    UpdateIpfsJobFailed,
+
+    // PIN
+    PinQueryError,
+    CreatePinFailed,
+    UpdatePinFailed,
+    RemotePinQueryError,
+    CreateRemotePinFailed,
+    UpdateRemotePinFailed,
+
     // DATABASE
     NoDatabaseConnection,
+    DatabaseTransactionFailed,
+    MigrationFailed,
 
     // USER
     CreateUserFailed,
     UserNotFound,
     UserQueryError,
+    UpdateUserFailed,
     // COLLECTION
     CollectionNotFound,
     CollectionQueryError,
     CreateCollectionFailed,
+    UpdateCollectionFailed,
+    InvalidCollectionMetadata,
+    CollectionAlreadyRevealed,
+    IpfsJobsPending,
     // NFT
     NftNotFound,
+    NftQueryError,
+    NftAlreadyMinted,
+    InvalidNFTMetadata,
+    InvalidTokenId,
     CreateNFTFailed,
+    UpdateNFTFailed,
     // NFT Trait
     NftTraitNotFound,
     CreateNFTTraitFailed,
+    DeleteNFTTraitFailed,
     CountNFTFailed,
 
-    NotImplemented,
+    // PAGINATION
+    InvalidCursor,
+    InvalidPageSize,
 }
 
 impl IntoResponse for AppError {
@@ -56,6 +111,21 @@ impl IntoResponse for AppError {
             AppError::MissingCredentials => (StatusCode::BAD_REQUEST, "Missing credentials"),
             AppError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"),
             AppError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+            AppError::TokenRevoked | AppError::InvalidRefreshToken => {
+                (StatusCode::UNAUTHORIZED, "Session expired")
+            }
+            AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
+            AppError::InvalidApiKey => (StatusCode::UNAUTHORIZED, "Invalid API key"),
+            AppError::InvalidApiKeyExpiry => (StatusCode::BAD_REQUEST, "Invalid API key expiry"),
+            AppError::InvalidRole => (StatusCode::BAD_REQUEST, "Invalid role"),
+            AppError::InvalidSiweMessage => (StatusCode::BAD_REQUEST, "Invalid login message"),
+            AppError::SiweMessageExpired => (StatusCode::UNAUTHORIZED, "Login message expired"),
+            AppError::SiweMessageNotYetValid => {
+                (StatusCode::UNAUTHORIZED, "Login message not yet valid")
+            }
+            AppError::InvalidSignature | AppError::InvalidLoginNonce => {
+                (StatusCode::UNAUTHORIZED, "Wrong credentials")
+            }
             AppError::RequestIpfsFailed
             | AppError::RequestIpfsError
             | AppError::RequestIpfsResponseNoBody
@@ -64,6 +134,24 @@ impl IntoResponse for AppError {
                 "Failed to upload file to IPFS",
             ),
             AppError::UploadMissingFile => (StatusCode::BAD_REQUEST, "missing file"),
+            AppError::InvalidNFTMetadata => (StatusCode::BAD_REQUEST, "Invalid NFT metadata"),
+            AppError::InvalidTokenId => (StatusCode::BAD_REQUEST, "Invalid token id"),
+            AppError::InvalidCollectionMetadata => {
+                (StatusCode::BAD_REQUEST, "Invalid collection metadata")
+            }
+            AppError::NftAlreadyMinted => (StatusCode::CONFLICT, "NFT already minted"),
+            AppError::CollectionAlreadyRevealed => {
+                (StatusCode::CONFLICT, "Collection already revealed")
+            }
+            AppError::IpfsJobsPending => (
+                StatusCode::CONFLICT,
+                "Collection is still being published, try again later",
+            ),
+            AppError::CollectionNotFound | AppError::NftNotFound => {
+                (StatusCode::NOT_FOUND, "Not found")
+            }
+            AppError::InvalidCursor => (StatusCode::BAD_REQUEST, "Invalid cursor"),
+            AppError::InvalidPageSize => (StatusCode::BAD_REQUEST, "Invalid page size"),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "Unknown Error"),
         };
         let body = Json(json!({
@@ -73,6 +161,14 @@ impl IntoResponse for AppError {
     }
 }
 
+/// Lets diesel transactions, whose closures return `AppError`, report begin/commit failures.
+impl From<diesel::result::Error> for AppError {
+    fn from(err: diesel::result::Error) -> Self {
+        tracing::error!("database transaction error: {:?}", err);
+        AppError::DatabaseTransactionFailed
+    }
+}
+
 impl Display for AppError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -0,0 +1,11 @@
+pub mod app_state;
+pub mod config;
+pub mod domain;
+pub mod errors;
+pub mod keys;
+pub mod middlewares;
+pub mod models;
+pub mod services;
+pub mod siwe;
+pub mod util;
+pub mod worker;
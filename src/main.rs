@@ -1,37 +1,68 @@
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::error_handling::HandleErrorLayer;
 use axum::http::Method;
 use axum::Router;
 use dotenv::dotenv;
+use ipfs_api::client::LocalIPFSClient;
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 
-use app_state::AppState;
-
-mod app_state;
-mod domain;
-mod errors;
-mod middlewares;
-mod models;
-mod services;
-mod util;
+use nft_marketplace_rs::{
+    app_state::AppState,
+    config::Config,
+    keys, middlewares,
+    models::{self, Database},
+    services, worker,
+};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     tracing_subscriber::fmt::init();
 
-    let app_state: AppState = AppState::new();
+    let config = Arc::new(Config::load().unwrap_or_else(|err| {
+        eprintln!("invalid configuration: {}", err);
+        std::process::exit(1)
+    }));
+    if let Err(err) = keys::init(&config.jwt) {
+        eprintln!("invalid JWT keys: {}", err);
+        std::process::exit(1)
+    }
+
+    let database = Database::from_config(&config.database);
+    // `--migrate` applies pending migrations before serving, for single-instance deploys.
+    if env::args().any(|arg| arg == "--migrate") {
+        let versions = database
+            .run(models::run_pending_migrations)
+            .await
+            .expect("Error running migrations");
+        tracing::info!("applied {} migrations: {:?}", versions.len(), versions);
+    }
+    let app_state: AppState = AppState::new(config.clone(), database.clone());
+    keys::spawn_reload(config.jwt.clone());
+    worker::spawn(
+        database,
+        LocalIPFSClient::new(config.ipfs.api_url.clone()),
+        config
+            .remote_pinning_services()
+            .expect("validated with the configuration"),
+        config.worker.clone(),
+    );
 
     let app = Router::new()
         .merge(services::graphql_playground_router())
+        .merge(services::metadata_router(app_state.clone()))
         .merge(services::graphql_router(app_state))
+        .merge(services::well_known_router())
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(middlewares::handle_error))
-                .timeout(Duration::from_secs(30)),
+                .timeout(Duration::from_secs(config.server.request_timeout_seconds)),
         )
         .layer(
             CorsLayer::new()
@@ -40,9 +71,17 @@ async fn main() {
                 .allow_headers(Any),
         );
 
-    println!("Playground: http://localhost:8000/playground");
+    println!(
+        "Playground: http://{}:{}/playground",
+        config.server.host, config.server.port
+    );
 
-    axum::serve(TcpListener::bind("127.0.0.1:8000").await.unwrap(), app)
-        .await
-        .unwrap();
+    axum::serve(
+        TcpListener::bind((config.server.host.as_str(), config.server.port))
+            .await
+            .unwrap(),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
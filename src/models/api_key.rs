@@ -0,0 +1,116 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+use super::schema::api_keys;
+
+/// A long-lived credential for bots and indexers; only the hash of the key is stored.
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub address: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    /// Space separated, as in OAuth scope strings.
+    pub scopes: String,
+    pub expired_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl ApiKey {
+    /// Finds a usable key by hash and records that it was just used.
+    pub fn touch_active_by_hash(
+        connection: &mut PgConnection,
+        key_hash: String,
+    ) -> Result<Option<ApiKey>, AppError> {
+        diesel::update(
+            api_keys::table
+                .filter(api_keys::key_hash.eq(key_hash))
+                .filter(api_keys::revoked_at.is_null())
+                .filter(
+                    api_keys::expired_at
+                        .is_null()
+                        .or(api_keys::expired_at.gt(now)),
+                ),
+        )
+        .set(api_keys::last_used_at.eq(now))
+        .returning(ApiKey::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("find api key error: {:?}", err);
+            AppError::ApiKeyQueryError
+        })
+    }
+
+    pub fn list_by_address(
+        connection: &mut PgConnection,
+        address: String,
+    ) -> Result<Vec<ApiKey>, AppError> {
+        api_keys::table
+            .filter(api_keys::address.eq(address))
+            .filter(api_keys::revoked_at.is_null())
+            .order(api_keys::created_at.desc())
+            .select(ApiKey::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list api key error: {:?}", err);
+                AppError::ApiKeyQueryError
+            })
+    }
+
+    pub fn revoke(
+        connection: &mut PgConnection,
+        id: Uuid,
+        address: String,
+    ) -> Result<Option<ApiKey>, AppError> {
+        diesel::update(
+            api_keys::table
+                .filter(api_keys::id.eq(id))
+                .filter(api_keys::address.eq(address))
+                .filter(api_keys::revoked_at.is_null()),
+        )
+        .set((api_keys::revoked_at.eq(now), api_keys::updated_at.eq(now)))
+        .returning(ApiKey::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("revoke api key error: {:?}", err);
+            AppError::ApiKeyQueryError
+        })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct InsertedApiKey {
+    pub address: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub scopes: String,
+    pub expired_at: Option<NaiveDateTime>,
+}
+
+impl InsertedApiKey {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<ApiKey, AppError> {
+        diesel::insert_into(api_keys::table)
+            .values(self)
+            .returning(ApiKey::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("create api key error: {:?}", err);
+                AppError::CreateApiKeyFailed
+            })
+    }
+}
@@ -1,6 +1,7 @@
 use core::str;
 
 use chrono::NaiveDateTime;
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::{Queryable, Selectable};
 use serde::{Deserialize, Serialize};
@@ -8,9 +9,9 @@ use uuid::Uuid;
 
 use crate::errors::AppError;
 
-use super::{establish_connection, schema::collections};
+use super::schema::collections;
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = collections)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Collection {
@@ -25,6 +26,28 @@ pub struct Collection {
     pub dir_hash: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Lowest token id not yet minted or reserved.
+    pub next_token_id: i32,
+    /// Set when the collection was deleted; deleted collections are left out of every lookup.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Layout of the metadata files: `opensea`, or `legacy` for collections published
+    /// before the switch.
+    pub metadata_format: String,
+    pub description: Option<String>,
+    pub banner_url: Option<String>,
+    pub external_link: Option<String>,
+    /// Secondary sale royalty, in hundredths of a percent.
+    pub seller_fee_basis_points: i32,
+    pub fee_recipient: Option<String>,
+    /// CID of the collection's `contract.json`; `None` if it was never hashed, e.g. for
+    /// collections created before it existed.
+    pub contract_cid: Option<String>,
+    /// `false` while every token's metadata file holds the placeholder.
+    pub revealed: bool,
+    pub placeholder_name: Option<String>,
+    pub placeholder_description: Option<String>,
+    /// Set whenever the collection is unrevealed.
+    pub placeholder_image_url: Option<String>,
 }
 
 #[derive(Default)]
@@ -32,13 +55,73 @@ pub struct CollectionQuery {
     pub id: Option<Uuid>,
     pub owner: Option<String>,
     pub contract_address: Option<String>,
+    pub chain_id: Option<i32>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectionOrder {
+    CreatedAtAsc,
+    CreatedAtDesc,
+    NameAsc,
+    NameDesc,
+}
+
+/// Position of a collection in a listing. Every sort key is kept, with the id as tie-breaker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionCursor {
+    pub created_at: NaiveDateTime,
+    pub name: String,
+    pub id: Uuid,
+}
+
+impl From<&Collection> for CollectionCursor {
+    fn from(collection: &Collection) -> Self {
+        Self {
+            created_at: collection.created_at,
+            name: collection.name.clone(),
+            id: collection.id,
+        }
+    }
+}
+
+impl CollectionQuery {
+    fn filtered(&self) -> collections::BoxedQuery<'static, Pg> {
+        let mut query_builder = collections::table
+            .filter(collections::deleted_at.is_null())
+            .into_boxed();
+        if let Some(_id) = self.id {
+            query_builder = query_builder.filter(collections::id.eq(_id));
+        }
+        if let Some(owner) = self.owner.clone() {
+            query_builder = query_builder.filter(collections::owner.eq(owner.to_lowercase()));
+        }
+        if let Some(contract_address) = self.contract_address.clone() {
+            query_builder = query_builder
+                .filter(collections::contract_address.eq(contract_address.to_lowercase()));
+        }
+        if let Some(chain_id) = self.chain_id {
+            query_builder = query_builder.filter(collections::chain_id.eq(chain_id));
+        }
+        if let Some(created_after) = self.created_after {
+            query_builder = query_builder.filter(collections::created_at.ge(created_after));
+        }
+        if let Some(created_before) = self.created_before {
+            query_builder = query_builder.filter(collections::created_at.lt(created_before));
+        }
+        query_builder
+    }
 }
 
 impl Collection {
-    pub async fn find_by_owner(owner: String) -> Result<Vec<Collection>, AppError> {
-        let connection = &mut establish_connection();
+    pub fn find_by_owner(
+        connection: &mut PgConnection,
+        owner: String,
+    ) -> Result<Vec<Collection>, AppError> {
         collections::table
-            .filter(collections::owner.eq(owner))
+            .filter(collections::owner.eq(owner.to_lowercase()))
+            .filter(collections::deleted_at.is_null())
             .load(connection)
             .map_err(|err| {
                 tracing::error!("find collection error: {:?}", err);
@@ -46,20 +129,34 @@ impl Collection {
             })
     }
 
-    pub async fn find_by_query(query: CollectionQuery) -> Result<Option<Collection>, AppError> {
-        let connection = &mut establish_connection();
-        let mut query_builder = collections::table.into_boxed();
-        if let Some(_id) = query.id {
-            query_builder = query_builder.filter(collections::id.eq(_id));
-        }
-        if let Some(owner) = query.owner {
-            query_builder = query_builder.filter(collections::owner.eq(owner));
-        }
-        if let Some(contract_address) = query.contract_address {
-            query_builder =
-                query_builder.filter(collections::contract_address.eq(contract_address));
-        }
-        match query_builder
+    pub fn list_by_contract_addresses(
+        connection: &mut PgConnection,
+        contract_addresses: Vec<String>,
+    ) -> Result<Vec<Collection>, AppError> {
+        collections::table
+            .filter(
+                collections::contract_address.eq_any(
+                    contract_addresses
+                        .iter()
+                        .map(|contract_address| contract_address.to_lowercase())
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .filter(collections::deleted_at.is_null())
+            .select(Collection::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("find collection by contract_addresses error: {:?}", err);
+                AppError::CollectionQueryError
+            })
+    }
+
+    pub fn find_by_query(
+        connection: &mut PgConnection,
+        query: CollectionQuery,
+    ) -> Result<Option<Collection>, AppError> {
+        match query
+            .filtered()
             .select(Collection::as_select())
             .limit(1)
             .first(connection)
@@ -76,6 +173,220 @@ impl Collection {
             }
         }
     }
+
+    /// The live collection, locked until the transaction ends.
+    pub fn find_for_update(
+        connection: &mut PgConnection,
+        chain_id: i32,
+        contract_address: String,
+    ) -> Result<Option<Collection>, AppError> {
+        collections::table
+            .filter(collections::chain_id.eq(chain_id))
+            .filter(collections::contract_address.eq(contract_address.to_lowercase()))
+            .filter(collections::deleted_at.is_null())
+            .select(Collection::as_select())
+            .for_update()
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("lock collection error: {:?}", err);
+                AppError::CollectionQueryError
+            })
+    }
+
+    /// Hands out the next token id of the collection. Concurrent callers queue on the row
+    /// lock taken by the update, so an id is never handed out twice.
+    pub fn reserve_token_id(
+        connection: &mut PgConnection,
+        chain_id: i32,
+        contract_address: String,
+    ) -> Result<Option<i32>, AppError> {
+        diesel::update(
+            collections::table
+                .filter(collections::chain_id.eq(chain_id))
+                .filter(collections::contract_address.eq(contract_address.to_lowercase()))
+                .filter(collections::deleted_at.is_null()),
+        )
+        .set(collections::next_token_id.eq(collections::next_token_id + 1))
+        .returning(collections::next_token_id)
+        .get_result::<i32>(connection)
+        .optional()
+        .map(|next_token_id| next_token_id.map(|next_token_id| next_token_id - 1))
+        .map_err(|err| {
+            tracing::error!("reserve token id error: {:?}", err);
+            AppError::UpdateCollectionFailed
+        })
+    }
+
+    pub fn update(
+        connection: &mut PgConnection,
+        id: Uuid,
+        changes: &UpdatedCollection,
+    ) -> Result<Collection, AppError> {
+        diesel::update(collections::table.filter(collections::id.eq(id)))
+            .set((changes, collections::updated_at.eq(diesel::dsl::now)))
+            .returning(Collection::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update collection error: {:?}", err);
+                AppError::UpdateCollectionFailed
+            })
+    }
+
+    pub fn soft_delete(connection: &mut PgConnection, id: Uuid) -> Result<(), AppError> {
+        diesel::update(collections::table.filter(collections::id.eq(id)))
+            .set(collections::deleted_at.eq(diesel::dsl::now))
+            .execute(connection)
+            .map(|_| ())
+            .map_err(|err| {
+                tracing::error!("delete collection error: {:?}", err);
+                AppError::UpdateCollectionFailed
+            })
+    }
+
+    /// Marks an unrevealed collection revealed. `None` if it was revealed already.
+    pub fn reveal(connection: &mut PgConnection, id: Uuid) -> Result<Option<Collection>, AppError> {
+        diesel::update(
+            collections::table
+                .filter(collections::id.eq(id))
+                .filter(collections::revealed.eq(false)),
+        )
+        .set((
+            collections::revealed.eq(true),
+            collections::updated_at.eq(diesel::dsl::now),
+        ))
+        .returning(Collection::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("reveal collection error: {:?}", err);
+            AppError::UpdateCollectionFailed
+        })
+    }
+
+    /// Records the CID the collection directory was last flushed and pinned at.
+    pub fn update_dir_hash(
+        connection: &mut PgConnection,
+        id: Uuid,
+        dir_hash: String,
+    ) -> Result<Collection, AppError> {
+        diesel::update(collections::table.filter(collections::id.eq(id)))
+            .set((
+                collections::dir_hash.eq(dir_hash),
+                collections::updated_at.eq(diesel::dsl::now),
+            ))
+            .returning(Collection::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update collection dir hash error: {:?}", err);
+                AppError::UpdateCollectionFailed
+            })
+    }
+
+    /// Records the CID of the collection's `contract.json` as last flushed to the node.
+    pub fn update_contract_cid(
+        connection: &mut PgConnection,
+        id: Uuid,
+        contract_cid: String,
+    ) -> Result<Collection, AppError> {
+        diesel::update(collections::table.filter(collections::id.eq(id)))
+            .set((
+                collections::contract_cid.eq(Some(contract_cid)),
+                collections::updated_at.eq(diesel::dsl::now),
+            ))
+            .returning(Collection::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update collection contract cid error: {:?}", err);
+                AppError::UpdateCollectionFailed
+            })
+    }
+
+    /// Moves the counter past `token_id`, for NFTs minted with an id that was not reserved.
+    pub fn advance_token_id(
+        connection: &mut PgConnection,
+        id: Uuid,
+        token_id: i32,
+    ) -> Result<(), AppError> {
+        let next_token_id = token_id.checked_add(1).ok_or(AppError::InvalidTokenId)?;
+        diesel::update(
+            collections::table
+                .filter(collections::id.eq(id))
+                .filter(collections::next_token_id.le(token_id)),
+        )
+        .set(collections::next_token_id.eq(next_token_id))
+        .execute(connection)
+        .map(|_| ())
+        .map_err(|err| {
+            tracing::error!("advance token id error: {:?}", err);
+            AppError::UpdateCollectionFailed
+        })
+    }
+
+    /// Up to `limit` collections matching `query` in `order`, starting right after `after`.
+    pub fn list(
+        connection: &mut PgConnection,
+        query: &CollectionQuery,
+        order: CollectionOrder,
+        after: Option<CollectionCursor>,
+        limit: i64,
+    ) -> Result<Vec<Collection>, AppError> {
+        let mut query_builder = query.filtered();
+        if let Some(after) = after {
+            query_builder = match order {
+                CollectionOrder::CreatedAtAsc => query_builder.filter(
+                    collections::created_at
+                        .gt(after.created_at)
+                        .or(collections::created_at
+                            .eq(after.created_at)
+                            .and(collections::id.gt(after.id))),
+                ),
+                CollectionOrder::CreatedAtDesc => query_builder.filter(
+                    collections::created_at
+                        .lt(after.created_at)
+                        .or(collections::created_at
+                            .eq(after.created_at)
+                            .and(collections::id.lt(after.id))),
+                ),
+                CollectionOrder::NameAsc => query_builder.filter(
+                    collections::name
+                        .gt(after.name.clone())
+                        .or(collections::name
+                            .eq(after.name)
+                            .and(collections::id.gt(after.id))),
+                ),
+                CollectionOrder::NameDesc => query_builder.filter(
+                    collections::name
+                        .lt(after.name.clone())
+                        .or(collections::name
+                            .eq(after.name)
+                            .and(collections::id.lt(after.id))),
+                ),
+            };
+        }
+        query_builder = match order {
+            CollectionOrder::CreatedAtAsc => {
+                query_builder.order((collections::created_at.asc(), collections::id.asc()))
+            }
+            CollectionOrder::CreatedAtDesc => {
+                query_builder.order((collections::created_at.desc(), collections::id.desc()))
+            }
+            CollectionOrder::NameAsc => {
+                query_builder.order((collections::name.asc(), collections::id.asc()))
+            }
+            CollectionOrder::NameDesc => {
+                query_builder.order((collections::name.desc(), collections::id.desc()))
+            }
+        };
+        query_builder
+            .limit(limit)
+            .select(Collection::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list collection error: {:?}", err);
+                AppError::CollectionQueryError
+            })
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -89,11 +400,37 @@ pub struct InsertedCollection {
     pub chain_id: i32,
     pub dir_name: String,
     pub dir_hash: String,
+    pub metadata_format: String,
+    pub description: Option<String>,
+    pub banner_url: Option<String>,
+    pub external_link: Option<String>,
+    pub seller_fee_basis_points: i32,
+    pub fee_recipient: Option<String>,
+    pub contract_cid: Option<String>,
+    pub revealed: bool,
+    pub placeholder_name: Option<String>,
+    pub placeholder_description: Option<String>,
+    pub placeholder_image_url: Option<String>,
+}
+
+/// Fields of a collection that can be edited. `None` leaves a field unchanged and
+/// `Some(None)` clears an optional one.
+#[derive(Debug, Clone, Default, AsChangeset)]
+#[diesel(table_name = collections)]
+pub struct UpdatedCollection {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub pic_url: Option<String>,
+    pub metadata_format: Option<String>,
+    pub description: Option<Option<String>>,
+    pub banner_url: Option<Option<String>>,
+    pub external_link: Option<Option<String>>,
+    pub seller_fee_basis_points: Option<i32>,
+    pub fee_recipient: Option<Option<String>>,
 }
 
 impl InsertedCollection {
-    pub async fn insert(&self) -> Result<Collection, AppError> {
-        let connection = &mut establish_connection();
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<Collection, AppError> {
         return diesel::insert_into(collections::table)
             .values(self)
             .returning(Collection::as_returning())
@@ -104,3 +441,186 @@ impl InsertedCollection {
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::env;
+    use std::thread;
+
+    use super::*;
+    use crate::models::run_pending_migrations;
+
+    const THREADS: usize = 8;
+    const RESERVATIONS_PER_THREAD: usize = 25;
+
+    const OWNER: &str = "0x00000000000000000000000000000000000000ab";
+
+    fn inserted_collection(owner: &str) -> InsertedCollection {
+        InsertedCollection {
+            name: "Collection Test".to_string(),
+            symbol: "TST".to_string(),
+            owner: owner.to_string(),
+            pic_url: String::new(),
+            contract_address: format!("0x{}", Uuid::new_v4().simple()),
+            chain_id: 31337,
+            dir_name: "collection-test".to_string(),
+            dir_hash: String::new(),
+            metadata_format: "opensea".to_string(),
+            description: Some("A test collection".to_string()),
+            banner_url: Some("ipfs://banner".to_string()),
+            external_link: None,
+            seller_fee_basis_points: 0,
+            fee_recipient: None,
+            contract_cid: None,
+            revealed: true,
+            placeholder_name: None,
+            placeholder_description: None,
+            placeholder_image_url: None,
+        }
+    }
+
+    /// Connects to the scratch database the tests below need; run them with
+    /// `cargo test -- --ignored` and `DATABASE_URL_TEST` set.
+    fn test_database() -> (String, PgConnection) {
+        let database_url = env::var("DATABASE_URL_TEST").expect("DATABASE_URL_TEST is not set");
+        let mut connection = PgConnection::establish(&database_url).unwrap();
+        run_pending_migrations(&mut connection).unwrap();
+        (database_url, connection)
+    }
+
+    fn delete(connection: &mut PgConnection, id: Uuid) {
+        diesel::delete(collections::table.filter(collections::id.eq(id)))
+            .execute(connection)
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    fn only_the_owner_finds_the_collection() {
+        let (_, connection) = &mut test_database();
+        let collection = inserted_collection(OWNER).insert(connection).unwrap();
+        // the query `RequireCollectionOwner` runs for non-admin callers
+        let owned_by = |connection: &mut PgConnection, owner: &str| {
+            Collection::find_by_query(
+                connection,
+                CollectionQuery {
+                    owner: Some(owner.to_string()),
+                    contract_address: Some(collection.contract_address.to_uppercase()),
+                    chain_id: Some(collection.chain_id),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let found = owned_by(connection, OWNER);
+        // wallets hand out EIP-55 checksummed addresses
+        let found_checksummed = owned_by(connection, "0x00000000000000000000000000000000000000AB");
+        let not_found = owned_by(connection, "0x0000000000000000000000000000000000000002");
+        delete(connection, collection.id);
+        assert_eq!(found.map(|found| found.id), Some(collection.id));
+        assert_eq!(found_checksummed.map(|found| found.id), Some(collection.id));
+        assert!(not_found.is_none());
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    fn partial_updates_leave_omitted_fields() {
+        let (_, connection) = &mut test_database();
+        let collection = inserted_collection(OWNER).insert(connection).unwrap();
+        let updated = Collection::update(
+            connection,
+            collection.id,
+            &UpdatedCollection {
+                name: Some("Renamed".to_string()),
+                description: Some(None),
+                external_link: Some(Some("https://example.com".to_string())),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        delete(connection, collection.id);
+        assert_eq!(updated.name, "Renamed");
+        assert_eq!(updated.symbol, collection.symbol);
+        assert_eq!(updated.description, None);
+        assert_eq!(updated.banner_url, collection.banner_url);
+        assert_eq!(
+            updated.external_link.as_deref(),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    fn deleted_collections_disappear_and_free_their_address() {
+        let (_, connection) = &mut test_database();
+        let collection = inserted_collection(OWNER).insert(connection).unwrap();
+        Collection::soft_delete(connection, collection.id).unwrap();
+        let by_address = Collection::find_by_query(
+            connection,
+            CollectionQuery {
+                contract_address: Some(collection.contract_address.clone()),
+                chain_id: Some(collection.chain_id),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let by_addresses = Collection::list_by_contract_addresses(
+            connection,
+            vec![collection.contract_address.clone()],
+        )
+        .unwrap();
+        let recreated = InsertedCollection {
+            contract_address: collection.contract_address.clone(),
+            ..inserted_collection(OWNER)
+        }
+        .insert(connection);
+        if let Ok(recreated) = &recreated {
+            delete(connection, recreated.id);
+        }
+        delete(connection, collection.id);
+        assert!(by_address.is_none());
+        assert!(by_addresses.is_empty());
+        assert!(recreated.is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    fn concurrent_reservations_never_share_a_token_id() {
+        let (database_url, connection) = &mut test_database();
+        let collection = inserted_collection(OWNER).insert(connection).unwrap();
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let database_url = database_url.clone();
+                let contract_address = collection.contract_address.clone();
+                thread::spawn(move || {
+                    let connection = &mut PgConnection::establish(&database_url).unwrap();
+                    (0..RESERVATIONS_PER_THREAD)
+                        .map(|_| {
+                            Collection::reserve_token_id(
+                                connection,
+                                31337,
+                                contract_address.clone(),
+                            )
+                            .unwrap()
+                            .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let token_ids: Vec<i32> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+
+        delete(connection, collection.id);
+        let total = THREADS * RESERVATIONS_PER_THREAD;
+        assert_eq!(token_ids.len(), total);
+        assert_eq!(
+            token_ids.into_iter().collect::<HashSet<_>>(),
+            (1..=total as i32).collect::<HashSet<_>>()
+        );
+    }
+}
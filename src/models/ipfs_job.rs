@@ -0,0 +1,301 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::{now, sql};
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::domain::ipfs_job::IpfsJobStatus;
+use crate::errors::AppError;
+
+use super::schema::ipfs_jobs;
+
+/// Holds for jobs without a collection and for the oldest unfinished job of each collection.
+const NO_OLDER_UNFINISHED_JOB: &str = "NOT EXISTS (
+    SELECT 1 FROM ipfs_jobs AS older
+    WHERE older.collection_id = ipfs_jobs.collection_id
+        AND older.status IN ('pending', 'running')
+        AND older.created_at < ipfs_jobs.created_at
+)";
+
+/// A pending IPFS operation, written in the same transaction as the change that needs it
+/// and drained by the background worker.
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = ipfs_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IpfsJob {
+    pub id: Uuid,
+    /// `mkdir`, `write`, `flush`, `pin`, `pin_directory` or `remote_pin`.
+    pub kind: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `running`, `done` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub next_run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub collection_id: Option<Uuid>,
+    pub nft_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl IpfsJob {
+    /// Locks up to `limit` due jobs, skipping rows other workers hold, and leases them
+    /// until `lease_until`. A job whose worker died is picked up again once the lease ends.
+    ///
+    /// Jobs of a collection run one at a time in the order they were queued: a job waits
+    /// while an older one of its collection is unfinished, e.g. backing off after a failed
+    /// attempt, so a retried write cannot overwrite a newer write to the same file.
+    pub fn claim_due(
+        connection: &mut PgConnection,
+        limit: i64,
+        lease_until: NaiveDateTime,
+    ) -> Result<Vec<IpfsJob>, AppError> {
+        connection.transaction(|connection| {
+            let ids: Vec<Uuid> = ipfs_jobs::table
+                .filter(ipfs_jobs::status.eq_any(vec!["pending", "running"]))
+                .filter(ipfs_jobs::next_run_at.le(now))
+                .filter(sql::<Bool>(NO_OLDER_UNFINISHED_JOB))
+                .order((ipfs_jobs::next_run_at.asc(), ipfs_jobs::created_at.asc()))
+                .limit(limit)
+                .select(ipfs_jobs::id)
+                .for_update()
+                .skip_locked()
+                .load(connection)
+                .map_err(|err| {
+                    tracing::error!("find due ipfs jobs error: {:?}", err);
+                    AppError::IpfsJobQueryError
+                })?;
+            if ids.is_empty() {
+                return Ok(vec![]);
+            }
+            diesel::update(ipfs_jobs::table.filter(ipfs_jobs::id.eq_any(ids)))
+                .set((
+                    ipfs_jobs::status.eq("running"),
+                    ipfs_jobs::attempts.eq(ipfs_jobs::attempts + 1),
+                    ipfs_jobs::next_run_at.eq(lease_until),
+                    ipfs_jobs::updated_at.eq(now),
+                ))
+                .returning(IpfsJob::as_returning())
+                .get_results(connection)
+                .map(|mut jobs: Vec<IpfsJob>| {
+                    // keep jobs of one change in the order they were written
+                    jobs.sort_by_key(|job| job.created_at);
+                    jobs
+                })
+                .map_err(|err| {
+                    tracing::error!("claim ipfs jobs error: {:?}", err);
+                    AppError::UpdateIpfsJobFailed
+                })
+        })
+    }
+
+    pub fn complete(connection: &mut PgConnection, id: Uuid) -> Result<IpfsJob, AppError> {
+        diesel::update(ipfs_jobs::table.filter(ipfs_jobs::id.eq(id)))
+            .set((
+                ipfs_jobs::status.eq("done"),
+                ipfs_jobs::last_error.eq(None::<String>),
+                ipfs_jobs::updated_at.eq(now),
+            ))
+            .returning(IpfsJob::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("complete ipfs job error: {:?}", err);
+                AppError::UpdateIpfsJobFailed
+            })
+    }
+
+    /// Records a failed attempt. The job is retried at `retry_at`, or given up on when `None`.
+    pub fn fail(
+        connection: &mut PgConnection,
+        id: Uuid,
+        last_error: String,
+        retry_at: Option<NaiveDateTime>,
+    ) -> Result<IpfsJob, AppError> {
+        let status = if retry_at.is_some() {
+            "pending"
+        } else {
+            "failed"
+        };
+        diesel::update(ipfs_jobs::table.filter(ipfs_jobs::id.eq(id)))
+            .set((
+                ipfs_jobs::status.eq(status),
+                ipfs_jobs::last_error.eq(Some(last_error)),
+                ipfs_jobs::next_run_at
+                    .eq(retry_at.unwrap_or_else(|| chrono::Utc::now().naive_utc())),
+                ipfs_jobs::updated_at.eq(now),
+            ))
+            .returning(IpfsJob::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("fail ipfs job error: {:?}", err);
+                AppError::UpdateIpfsJobFailed
+            })
+    }
+
+    /// Puts a failed job back in the queue with a fresh attempt budget.
+    pub fn retry(connection: &mut PgConnection, id: Uuid) -> Result<Option<IpfsJob>, AppError> {
+        diesel::update(
+            ipfs_jobs::table
+                .filter(ipfs_jobs::id.eq(id))
+                .filter(ipfs_jobs::status.eq("failed")),
+        )
+        .set((
+            ipfs_jobs::status.eq("pending"),
+            ipfs_jobs::attempts.eq(0),
+            ipfs_jobs::next_run_at.eq(now),
+            ipfs_jobs::updated_at.eq(now),
+        ))
+        .returning(IpfsJob::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("retry ipfs job error: {:?}", err);
+            AppError::UpdateIpfsJobFailed
+        })
+    }
+
+    /// Jobs of the collection that are queued or running, including ones awaiting a retry.
+    pub fn count_unfinished(
+        connection: &mut PgConnection,
+        collection_id: Uuid,
+    ) -> Result<i64, AppError> {
+        ipfs_jobs::table
+            .filter(ipfs_jobs::collection_id.eq(collection_id))
+            .filter(ipfs_jobs::status.eq_any(vec![
+                IpfsJobStatus::Pending.as_str(),
+                IpfsJobStatus::Running.as_str(),
+            ]))
+            .count()
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("count ipfs jobs error: {:?}", err);
+                AppError::IpfsJobQueryError
+            })
+    }
+
+    pub fn list_by_collection_id(
+        connection: &mut PgConnection,
+        collection_id: Uuid,
+        status: Option<String>,
+    ) -> Result<Vec<IpfsJob>, AppError> {
+        let mut query_builder = ipfs_jobs::table
+            .filter(ipfs_jobs::collection_id.eq(collection_id))
+            .into_boxed();
+        if let Some(status) = status {
+            query_builder = query_builder.filter(ipfs_jobs::status.eq(status));
+        }
+        query_builder
+            .order(ipfs_jobs::created_at.desc())
+            .select(IpfsJob::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list ipfs jobs error: {:?}", err);
+                AppError::IpfsJobQueryError
+            })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = ipfs_jobs)]
+pub struct InsertedIpfsJob {
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub collection_id: Option<Uuid>,
+    pub nft_id: Option<Uuid>,
+}
+
+impl InsertedIpfsJob {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<IpfsJob, AppError> {
+        diesel::insert_into(ipfs_jobs::table)
+            .values(self)
+            .returning(IpfsJob::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("create ipfs job error: {:?}", err);
+                AppError::CreateIpfsJobFailed
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::domain::ipfs_job::{enqueue, IpfsOperation};
+    use crate::models::collection::InsertedCollection;
+    use crate::models::run_pending_migrations;
+    use crate::models::schema::collections;
+
+    fn write(content: &str) -> IpfsOperation {
+        IpfsOperation::Write {
+            path: "/ordering-test/1.json".to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    /// Needs a scratch database; run with `cargo test -- --ignored` and `DATABASE_URL_TEST` set.
+    #[test]
+    #[ignore = "needs DATABASE_URL_TEST"]
+    fn retried_writes_run_before_newer_writes_of_the_collection() {
+        let database_url = env::var("DATABASE_URL_TEST").expect("DATABASE_URL_TEST is not set");
+        let connection = &mut PgConnection::establish(&database_url).unwrap();
+        run_pending_migrations(connection).unwrap();
+        let collection = InsertedCollection {
+            name: "Ordering Test".to_string(),
+            symbol: "ORD".to_string(),
+            owner: "0x0000000000000000000000000000000000000001".to_string(),
+            pic_url: String::new(),
+            contract_address: format!("0x{}", Uuid::new_v4().simple()),
+            chain_id: 31337,
+            dir_name: "ordering-test".to_string(),
+            dir_hash: String::new(),
+            metadata_format: "opensea".to_string(),
+            description: None,
+            banner_url: None,
+            external_link: None,
+            seller_fee_basis_points: 0,
+            fee_recipient: None,
+            contract_cid: None,
+            revealed: true,
+            placeholder_name: None,
+            placeholder_description: None,
+            placeholder_image_url: None,
+        }
+        .insert(connection)
+        .unwrap();
+        let claim = |connection: &mut PgConnection| -> Vec<Uuid> {
+            let lease_until = (Utc::now() + Duration::minutes(5)).naive_utc();
+            IpfsJob::claim_due(connection, 100, lease_until)
+                .unwrap()
+                .into_iter()
+                .filter(|job| job.collection_id == Some(collection.id))
+                .map(|job| job.id)
+                .collect()
+        };
+
+        let stale = enqueue(connection, &write("stale"), Some(collection.id), None).unwrap();
+        assert_eq!(claim(connection), vec![stale.id]);
+        let retry_at = (Utc::now() + Duration::minutes(1)).naive_utc();
+        IpfsJob::fail(connection, stale.id, "timeout".to_string(), Some(retry_at)).unwrap();
+        let newer = enqueue(connection, &write("newer"), Some(collection.id), None).unwrap();
+        let while_backing_off = claim(connection);
+
+        let past = (Utc::now() - Duration::seconds(1)).naive_utc();
+        IpfsJob::fail(connection, stale.id, "timeout".to_string(), Some(past)).unwrap();
+        let retried = claim(connection);
+        IpfsJob::complete(connection, stale.id).unwrap();
+        let after_retry = claim(connection);
+
+        diesel::delete(collections::table.filter(collections::id.eq(collection.id)))
+            .execute(connection)
+            .unwrap();
+        assert!(while_backing_off.is_empty());
+        assert_eq!(retried, vec![stale.id]);
+        assert_eq!(after_retry, vec![newer.id]);
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+use super::schema::login_nonces;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = login_nonces)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LoginNonce {
+    pub id: Uuid,
+    pub address: String,
+    pub nonce: String,
+    pub expired_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl LoginNonce {
+    /// Deletes and returns the nonce if it was issued to `address` and has not expired,
+    /// so every nonce can be redeemed at most once.
+    pub fn consume(
+        connection: &mut PgConnection,
+        address: String,
+        nonce: String,
+    ) -> Result<Option<LoginNonce>, AppError> {
+        diesel::delete(
+            login_nonces::table
+                .filter(login_nonces::address.eq(address))
+                .filter(login_nonces::nonce.eq(nonce))
+                .filter(login_nonces::expired_at.gt(now)),
+        )
+        .returning(LoginNonce::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("consume login nonce error: {:?}", err);
+            AppError::LoginNonceQueryError
+        })
+    }
+
+    pub fn delete_expired(connection: &mut PgConnection) -> Result<usize, AppError> {
+        diesel::delete(login_nonces::table.filter(login_nonces::expired_at.le(now)))
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("delete expired login nonce error: {:?}", err);
+                AppError::LoginNonceQueryError
+            })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = login_nonces)]
+pub struct InsertedLoginNonce {
+    pub address: String,
+    pub nonce: String,
+    pub expired_at: NaiveDateTime,
+}
+
+impl InsertedLoginNonce {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<LoginNonce, AppError> {
+        diesel::insert_into(login_nonces::table)
+            .values(self)
+            .returning(LoginNonce::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("create login nonce error: {:?}", err);
+                AppError::CreateLoginNonceFailed
+            })
+    }
+}
@@ -1,14 +1,95 @@
-use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
-use std::env;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::time::Duration;
 
+use crate::config::DatabaseConfig;
+use crate::errors::AppError;
+
+pub mod api_key;
 pub mod collection;
+pub mod ipfs_job;
+pub mod login_nonce;
 pub mod nft;
 pub mod nft_trait;
+pub mod pin;
+pub mod refresh_token;
+pub mod remote_pin;
+pub mod revoked_token;
 pub mod schema;
 pub mod user;
 
-pub fn establish_connection() -> PgConnection {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
+/// Compiled into the binaries, so deployments do not need the diesel CLI.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+/// Shared connection pool. Diesel is blocking, so queries run on tokio's blocking
+/// threads through [`Database::run`] instead of stalling the async workers.
+#[derive(Clone)]
+pub struct Database {
+    pool: DbPool,
+}
+
+impl Database {
+    pub fn new(database_url: &str, max_size: u32, connection_timeout: Duration) -> Self {
+        let manager = ConnectionManager::<PgConnection>::new(database_url);
+        let pool = Pool::builder()
+            .max_size(max_size)
+            .connection_timeout(connection_timeout)
+            .build(manager)
+            .expect(&format!("Error connecting to {}", database_url));
+        Self { pool }
+    }
+
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        Self::new(
+            &config.url,
+            config.pool_max_size,
+            Duration::from_secs(config.pool_timeout_seconds),
+        )
+    }
+
+    /// Runs `f` with a pooled connection on the blocking thread pool.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<T, AppError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = &mut pool.get().map_err(|err| {
+                tracing::error!("get database connection error: {:?}", err);
+                AppError::NoDatabaseConnection
+            })?;
+            f(connection)
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!("database task error: {:?}", err);
+            AppError::NoDatabaseConnection
+        })?
+    }
+}
+
+/// Applies every pending migration and returns the versions that ran.
+pub fn run_pending_migrations(connection: &mut PgConnection) -> Result<Vec<String>, AppError> {
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .map(|versions| versions.iter().map(|version| version.to_string()).collect())
+        .map_err(|err| {
+            tracing::error!("run migrations error: {:?}", err);
+            AppError::MigrationFailed
+        })
+}
+
+/// Reverts the most recently applied migration and returns its version.
+pub fn revert_last_migration(connection: &mut PgConnection) -> Result<String, AppError> {
+    connection
+        .revert_last_migration(MIGRATIONS)
+        .map(|version| version.to_string())
+        .map_err(|err| {
+            tracing::error!("revert migration error: {:?}", err);
+            AppError::MigrationFailed
+        })
 }
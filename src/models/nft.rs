@@ -1,10 +1,14 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+use uuid::Uuid;
 
 use crate::errors::AppError;
 
-use super::schema::nfts;
+use super::schema::{collections, nft_traits, nfts};
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = nfts)]
@@ -21,21 +25,138 @@ pub struct NFT {
     pub collection: String,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
+    /// `pending` until the metadata file has been written to IPFS.
+    pub publish_status: String,
+    /// With `collection` and `token_id`, identifies the token on chain.
+    pub chain_id: i32,
+    /// Set when the NFT was deleted; deleted NFTs are left out of every lookup.
+    pub deleted_at: Option<NaiveDateTime>,
+    pub animation_url: Option<String>,
+    /// Hex color without the leading `#`.
+    pub background_color: Option<String>,
 }
 
 impl NFT {
-    pub async fn find_by_token_id(token_id: i32) -> Result<NFT, AppError> {
-        let connection = &mut super::establish_connection();
-        return nfts::table
+    pub fn find_by_collection_and_token_id(
+        connection: &mut PgConnection,
+        chain_id: i32,
+        collection: String,
+        token_id: i32,
+    ) -> Result<NFT, AppError> {
+        nfts::table
+            .filter(nfts::chain_id.eq(chain_id))
+            .filter(nfts::collection.eq(collection.to_lowercase()))
             .filter(nfts::token_id.eq(token_id))
+            .filter(nfts::deleted_at.is_null())
             .first(connection)
             .map_err(|err| {
-                tracing::error!("find nft by token_id error: {:?}", err);
+                if err == diesel::NotFound {
+                    AppError::NftNotFound
+                } else {
+                    tracing::error!("find nft by collection and token_id error: {:?}", err);
+                    AppError::NftQueryError
+                }
+            })
+    }
+
+    pub fn list_by_collection(
+        connection: &mut PgConnection,
+        chain_id: i32,
+        collection: String,
+    ) -> Result<Vec<NFT>, AppError> {
+        nfts::table
+            .filter(nfts::chain_id.eq(chain_id))
+            .filter(nfts::collection.eq(collection.to_lowercase()))
+            .filter(nfts::deleted_at.is_null())
+            .order(nfts::token_id.asc())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list nft by collection error: {:?}", err);
                 AppError::NftNotFound
-            });
+            })
+    }
+
+    pub fn update_publish_status(
+        connection: &mut PgConnection,
+        id: uuid::Uuid,
+        publish_status: String,
+    ) -> Result<NFT, AppError> {
+        diesel::update(nfts::table.filter(nfts::id.eq(id)))
+            .set((
+                nfts::publish_status.eq(publish_status),
+                nfts::updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update nft publish status error: {:?}", err);
+                AppError::UpdateNFTFailed
+            })
+    }
+
+    /// Applies `changes` and marks the NFT pending, as its metadata file is now stale.
+    pub fn update(
+        connection: &mut PgConnection,
+        id: uuid::Uuid,
+        changes: &UpdatedNFT,
+        publish_status: String,
+    ) -> Result<NFT, AppError> {
+        diesel::update(nfts::table.filter(nfts::id.eq(id)))
+            .set((
+                changes,
+                nfts::publish_status.eq(publish_status),
+                nfts::updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update nft error: {:?}", err);
+                AppError::UpdateNFTFailed
+            })
+    }
+
+    pub fn soft_delete(connection: &mut PgConnection, id: uuid::Uuid) -> Result<(), AppError> {
+        diesel::update(nfts::table.filter(nfts::id.eq(id)))
+            .set(nfts::deleted_at.eq(diesel::dsl::now))
+            .execute(connection)
+            .map(|_| ())
+            .map_err(|err| {
+                tracing::error!("delete nft error: {:?}", err);
+                AppError::UpdateNFTFailed
+            })
+    }
+
+    pub fn soft_delete_by_collection(
+        connection: &mut PgConnection,
+        chain_id: i32,
+        collection: String,
+    ) -> Result<usize, AppError> {
+        diesel::update(
+            nfts::table
+                .filter(nfts::chain_id.eq(chain_id))
+                .filter(nfts::collection.eq(collection.to_lowercase()))
+                .filter(nfts::deleted_at.is_null()),
+        )
+        .set(nfts::deleted_at.eq(diesel::dsl::now))
+        .execute(connection)
+        .map_err(|err| {
+            tracing::error!("delete nft by collection error: {:?}", err);
+            AppError::UpdateNFTFailed
+        })
     }
 }
 
+/// Fields of an NFT that can be edited after minting. `None` leaves a field unchanged;
+/// `Some(None)` clears an optional one.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = nfts)]
+pub struct UpdatedNFT {
+    pub name: Option<String>,
+    pub description: Option<Option<String>>,
+    pub image_url: Option<String>,
+    pub external_link: Option<Option<String>>,
+    pub animation_url: Option<Option<String>>,
+    pub background_color: Option<Option<String>>,
+}
+
 #[derive(Debug, Insertable)]
 #[diesel(table_name = nfts)]
 pub struct InsertedNFT {
@@ -47,17 +168,23 @@ pub struct InsertedNFT {
     pub external_link: Option<String>,
     pub owner: String,
     pub collection: String,
+    pub publish_status: String,
+    pub chain_id: i32,
+    pub animation_url: Option<String>,
+    pub background_color: Option<String>,
 }
 
 impl InsertedNFT {
-    pub async fn insert(&self) -> Result<NFT, AppError> {
-        let connection = &mut super::establish_connection();
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<NFT, AppError> {
         return diesel::insert_into(nfts::table)
             .values(self)
             .get_result(connection)
-            .map_err(|err| {
-                tracing::error!("create nft error: {:?}", err);
-                AppError::CreateNFTFailed
+            .map_err(|err| match err {
+                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => AppError::NftAlreadyMinted,
+                _ => {
+                    tracing::error!("create nft error: {:?}", err);
+                    AppError::CreateNFTFailed
+                }
             });
     }
 }
@@ -68,24 +195,144 @@ pub struct NFTQuery {
     pub token_id: Option<i32>,
     pub owner: Option<String>,
     pub collection: Option<String>,
+    pub chain_id: Option<i32>,
+    /// Matched against the NFT's traits; both set means one trait must match both.
+    pub trait_type: Option<String>,
+    pub trait_value: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    /// Leaves out NFTs of unrevealed collections.
+    pub hide_unrevealed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NFTOrder {
+    TokenIdAsc,
+    TokenIdDesc,
+    CreatedAtAsc,
+    CreatedAtDesc,
+}
+
+/// Position of an NFT in a listing. Every sort key is kept, with the id as tie-breaker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NFTCursor {
+    pub token_id: i32,
+    pub created_at: NaiveDateTime,
+    pub id: Uuid,
+}
+
+impl From<&NFT> for NFTCursor {
+    fn from(nft: &NFT) -> Self {
+        Self {
+            token_id: nft.token_id,
+            created_at: nft.created_at.unwrap_or_default(),
+            id: nft.id,
+        }
+    }
 }
 
 impl NFTQuery {
-    pub async fn count(&self) -> Result<i64, AppError> {
-        let connection = &mut super::establish_connection();
-        let mut query_builder = nfts::table.into_boxed();
+    fn filtered(&self) -> nfts::BoxedQuery<'static, Pg> {
+        let mut query_builder = nfts::table.filter(nfts::deleted_at.is_null()).into_boxed();
         if let Some(token_id) = self.token_id {
             query_builder = query_builder.filter(nfts::token_id.eq(token_id));
         }
         if let Some(owner) = self.owner.clone() {
-            query_builder = query_builder.filter(nfts::owner.eq(owner));
+            query_builder = query_builder.filter(nfts::owner.eq(owner.to_lowercase()));
         }
         if let Some(collection) = self.collection.clone() {
-            query_builder = query_builder.filter(nfts::collection.eq(collection));
+            query_builder = query_builder.filter(nfts::collection.eq(collection.to_lowercase()));
+        }
+        if let Some(chain_id) = self.chain_id {
+            query_builder = query_builder.filter(nfts::chain_id.eq(chain_id));
+        }
+        if self.trait_type.is_some() || self.trait_value.is_some() {
+            let mut traits = nft_traits::table.select(nft_traits::nft_id).into_boxed();
+            if let Some(trait_type) = self.trait_type.clone() {
+                traits = traits.filter(nft_traits::trait_type.eq(trait_type));
+            }
+            if let Some(trait_value) = self.trait_value.clone() {
+                traits = traits.filter(nft_traits::trait_value.eq(trait_value));
+            }
+            query_builder = query_builder.filter(nfts::id.eq_any(traits));
+        }
+        if let Some(created_after) = self.created_after {
+            query_builder = query_builder.filter(nfts::created_at.ge(created_after));
         }
-        return query_builder.count().get_result(connection).map_err(|err| {
-            tracing::error!("count nft error: {:?}", err);
-            AppError::CountNFTFailed
-        });
+        if let Some(created_before) = self.created_before {
+            query_builder = query_builder.filter(nfts::created_at.lt(created_before));
+        }
+        if self.hide_unrevealed {
+            let unrevealed = collections::table
+                .filter(collections::revealed.eq(false))
+                .filter(collections::chain_id.eq(nfts::chain_id))
+                .filter(collections::contract_address.eq(nfts::collection));
+            query_builder = query_builder.filter(diesel::dsl::not(diesel::dsl::exists(unrevealed)));
+        }
+        query_builder
+    }
+
+    pub fn count(&self, connection: &mut PgConnection) -> Result<i64, AppError> {
+        return self
+            .filtered()
+            .count()
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("count nft error: {:?}", err);
+                AppError::CountNFTFailed
+            });
+    }
+
+    /// Up to `limit` matching NFTs in `order`, starting right after `after`.
+    pub fn list(
+        &self,
+        connection: &mut PgConnection,
+        order: NFTOrder,
+        after: Option<NFTCursor>,
+        limit: i64,
+    ) -> Result<Vec<NFT>, AppError> {
+        let mut query_builder = self.filtered();
+        if let Some(after) = after {
+            // `created_at` defaults to now and is never cleared
+            let created_at = nfts::created_at.assume_not_null();
+            query_builder = match order {
+                NFTOrder::TokenIdAsc => query_builder.filter(
+                    nfts::token_id
+                        .gt(after.token_id)
+                        .or(nfts::token_id.eq(after.token_id).and(nfts::id.gt(after.id))),
+                ),
+                NFTOrder::TokenIdDesc => query_builder.filter(
+                    nfts::token_id
+                        .lt(after.token_id)
+                        .or(nfts::token_id.eq(after.token_id).and(nfts::id.lt(after.id))),
+                ),
+                NFTOrder::CreatedAtAsc => query_builder.filter(
+                    created_at
+                        .gt(after.created_at)
+                        .or(created_at.eq(after.created_at).and(nfts::id.gt(after.id))),
+                ),
+                NFTOrder::CreatedAtDesc => query_builder.filter(
+                    created_at
+                        .lt(after.created_at)
+                        .or(created_at.eq(after.created_at).and(nfts::id.lt(after.id))),
+                ),
+            };
+        }
+        query_builder = match order {
+            NFTOrder::TokenIdAsc => query_builder.order((nfts::token_id.asc(), nfts::id.asc())),
+            NFTOrder::TokenIdDesc => query_builder.order((nfts::token_id.desc(), nfts::id.desc())),
+            NFTOrder::CreatedAtAsc => query_builder.order((nfts::created_at.asc(), nfts::id.asc())),
+            NFTOrder::CreatedAtDesc => {
+                query_builder.order((nfts::created_at.desc(), nfts::id.desc()))
+            }
+        };
+        query_builder
+            .limit(limit)
+            .select(NFT::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list nft error: {:?}", err);
+                AppError::NftQueryError
+            })
     }
 }
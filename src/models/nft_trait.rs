@@ -6,7 +6,7 @@ use crate::errors::AppError;
 
 use super::schema::nft_traits;
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = nft_traits)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NFTTrait {
@@ -16,11 +16,16 @@ pub struct NFTTrait {
     pub trait_value: String,
     pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: Option<chrono::NaiveDateTime>,
+    /// `number`, `boost_number`, `boost_percentage` or `date`; `None` for text traits.
+    pub display_type: Option<String>,
+    pub max_value: Option<f64>,
 }
 
 impl NFTTrait {
-    pub async fn list_by_nft_id(nft_id: uuid::Uuid) -> Result<Vec<NFTTrait>, AppError> {
-        let connection = &mut super::establish_connection();
+    pub fn list_by_nft_id(
+        connection: &mut PgConnection,
+        nft_id: uuid::Uuid,
+    ) -> Result<Vec<NFTTrait>, AppError> {
         return nft_traits::table
             .filter(nft_traits::nft_id.eq(nft_id))
             .get_results(connection)
@@ -29,6 +34,31 @@ impl NFTTrait {
                 AppError::NftTraitNotFound
             });
     }
+
+    pub fn list_by_nft_ids(
+        connection: &mut PgConnection,
+        nft_ids: Vec<uuid::Uuid>,
+    ) -> Result<Vec<NFTTrait>, AppError> {
+        nft_traits::table
+            .filter(nft_traits::nft_id.eq_any(nft_ids))
+            .get_results(connection)
+            .map_err(|err| {
+                tracing::error!("find nft trait by nft_ids error: {:?}", err);
+                AppError::NftTraitNotFound
+            })
+    }
+
+    pub fn delete_by_nft_id(
+        connection: &mut PgConnection,
+        nft_id: uuid::Uuid,
+    ) -> Result<usize, AppError> {
+        diesel::delete(nft_traits::table.filter(nft_traits::nft_id.eq(nft_id)))
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("delete nft trait error: {:?}", err);
+                AppError::DeleteNFTTraitFailed
+            })
+    }
 }
 
 #[derive(Debug, Insertable, Serialize, Deserialize)]
@@ -37,6 +67,8 @@ pub struct InsertedNFTTrait {
     pub nft_id: uuid::Uuid,
     pub trait_type: String,
     pub trait_value: String,
+    pub display_type: Option<String>,
+    pub max_value: Option<f64>,
 }
 
 pub struct BatchInsertedNFTTrait {
@@ -44,8 +76,7 @@ pub struct BatchInsertedNFTTrait {
 }
 
 impl InsertedNFTTrait {
-    pub async fn insert(&self) -> Result<NFTTrait, AppError> {
-        let connection = &mut super::establish_connection();
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<NFTTrait, AppError> {
         return diesel::insert_into(nft_traits::table)
             .values(self)
             .get_result(connection)
@@ -57,12 +88,11 @@ impl InsertedNFTTrait {
 }
 
 impl BatchInsertedNFTTrait {
-    pub async fn insert(&self) -> Result<Vec<NFTTrait>, AppError> {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<Vec<NFTTrait>, AppError> {
         if self.traits.is_empty() {
             return Ok(vec![]);
         }
 
-        let connection = &mut super::establish_connection();
         return diesel::insert_into(nft_traits::table)
             .values(&self.traits)
             .get_results(connection)
@@ -0,0 +1,220 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+use super::schema::pins;
+
+/// Content the node must keep pinned, checked against `pin/ls` by the reconciliation job.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = pins)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Pin {
+    pub id: Uuid,
+    pub cid: String,
+    /// `directory` or `image`.
+    pub kind: String,
+    /// `pinned`, or `missing` while a re-pin is queued.
+    pub status: String,
+    pub last_error: Option<String>,
+    /// Set for directory pins. A CID may have one row per collection and kind referencing it.
+    pub collection_id: Option<Uuid>,
+    /// Last time reconciliation found the pin complete.
+    pub verified_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Pin {
+    pub fn list(connection: &mut PgConnection) -> Result<Vec<Pin>, AppError> {
+        pins::table
+            .order(pins::created_at.asc())
+            .select(Pin::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list pins error: {:?}", err);
+                AppError::PinQueryError
+            })
+    }
+
+    pub fn find_directory(
+        connection: &mut PgConnection,
+        collection_id: Uuid,
+    ) -> Result<Option<Pin>, AppError> {
+        pins::table
+            .filter(pins::collection_id.eq(collection_id))
+            .filter(pins::kind.eq("directory"))
+            .select(Pin::as_select())
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("find directory pin error: {:?}", err);
+                AppError::PinQueryError
+            })
+    }
+
+    /// Moves the collection's directory pin from `previous` to `cid`. The row is locked, so
+    /// jobs for one collection complete one at a time; `false` if another job moved the pin
+    /// since `previous` was read.
+    pub fn advance_directory(
+        connection: &mut PgConnection,
+        collection_id: Uuid,
+        previous: Option<String>,
+        cid: String,
+    ) -> Result<bool, AppError> {
+        let current = pins::table
+            .filter(pins::collection_id.eq(collection_id))
+            .filter(pins::kind.eq("directory"))
+            .select(Pin::as_select())
+            .for_update()
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("lock directory pin error: {:?}", err);
+                AppError::PinQueryError
+            })?;
+        match (current, previous) {
+            (Some(current), Some(previous)) if current.cid == previous => {
+                diesel::update(pins::table.filter(pins::id.eq(current.id)))
+                    .set((
+                        pins::cid.eq(cid),
+                        pins::status.eq("pinned"),
+                        pins::last_error.eq(None::<String>),
+                        pins::updated_at.eq(now),
+                    ))
+                    .execute(connection)
+                    .map_err(|err| {
+                        tracing::error!("update directory pin error: {:?}", err);
+                        AppError::UpdatePinFailed
+                    })?;
+                Ok(true)
+            }
+            // a concurrent first pin wins the unique index and this one inserts nothing
+            (None, None) => diesel::insert_into(pins::table)
+                .values(InsertedPin {
+                    cid,
+                    kind: "directory".to_string(),
+                    collection_id: Some(collection_id),
+                })
+                .on_conflict_do_nothing()
+                .execute(connection)
+                .map(|inserted| inserted == 1)
+                .map_err(|err| {
+                    tracing::error!("create directory pin error: {:?}", err);
+                    AppError::CreatePinFailed
+                }),
+            _ => Ok(false),
+        }
+    }
+
+    /// How many rows keep `cid` pinned; the node may only unpin it once there are none.
+    pub fn count_references(connection: &mut PgConnection, cid: String) -> Result<i64, AppError> {
+        pins::table
+            .filter(pins::cid.eq(cid))
+            .count()
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("count pin references error: {:?}", err);
+                AppError::PinQueryError
+            })
+    }
+
+    /// Marks a pin the node lost. `None` if it was already marked, or removed meanwhile,
+    /// so concurrent reconciliations queue a single re-pin.
+    pub fn mark_missing(
+        connection: &mut PgConnection,
+        id: Uuid,
+        last_error: String,
+    ) -> Result<Option<Pin>, AppError> {
+        diesel::update(
+            pins::table
+                .filter(pins::id.eq(id))
+                .filter(pins::status.eq("pinned")),
+        )
+        .set((
+            pins::status.eq("missing"),
+            pins::last_error.eq(Some(last_error)),
+            pins::updated_at.eq(now),
+        ))
+        .returning(Pin::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("mark pin missing error: {:?}", err);
+            AppError::UpdatePinFailed
+        })
+    }
+
+    pub fn mark_pinned(connection: &mut PgConnection, cid: String) -> Result<usize, AppError> {
+        diesel::update(pins::table.filter(pins::cid.eq(cid)))
+            .set((
+                pins::status.eq("pinned"),
+                pins::last_error.eq(None::<String>),
+                pins::verified_at.eq(now),
+                pins::updated_at.eq(now),
+            ))
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("mark pin pinned error: {:?}", err);
+                AppError::UpdatePinFailed
+            })
+    }
+
+    pub fn mark_verified(connection: &mut PgConnection, ids: Vec<Uuid>) -> Result<usize, AppError> {
+        diesel::update(pins::table.filter(pins::id.eq_any(ids)))
+            .set(pins::verified_at.eq(now))
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("mark pins verified error: {:?}", err);
+                AppError::UpdatePinFailed
+            })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = pins)]
+pub struct InsertedPin {
+    pub cid: String,
+    pub kind: String,
+    pub collection_id: Option<Uuid>,
+}
+
+impl InsertedPin {
+    /// Records the pin, or marks the existing row for the same CID, kind and collection
+    /// pinned again.
+    pub fn upsert(&self, connection: &mut PgConnection) -> Result<Pin, AppError> {
+        let inserted = diesel::insert_into(pins::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .returning(Pin::as_returning())
+            .get_result(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("create pin error: {:?}", err);
+                AppError::CreatePinFailed
+            })?;
+        if let Some(pin) = inserted {
+            return Ok(pin);
+        }
+        diesel::update(
+            pins::table
+                .filter(pins::cid.eq(&self.cid))
+                .filter(pins::kind.eq(&self.kind))
+                .filter(pins::collection_id.is_not_distinct_from(self.collection_id)),
+        )
+        .set((
+            pins::status.eq("pinned"),
+            pins::last_error.eq(None::<String>),
+            pins::updated_at.eq(now),
+        ))
+        .returning(Pin::as_returning())
+        .get_result(connection)
+        .map_err(|err| {
+            tracing::error!("update pin error: {:?}", err);
+            AppError::UpdatePinFailed
+        })
+    }
+}
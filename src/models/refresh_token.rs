@@ -0,0 +1,195 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::{exists, now};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+use super::schema::refresh_tokens;
+
+/// A login session, identified by the refresh token that keeps it alive.
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub address: String,
+    pub token_hash: String,
+    pub previous_token_hash: Option<String>,
+    pub access_token_jti: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expired_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub last_used_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl RefreshToken {
+    pub fn find_active_by_hash(
+        connection: &mut PgConnection,
+        token_hash: String,
+    ) -> Result<Option<RefreshToken>, AppError> {
+        refresh_tokens::table
+            .filter(refresh_tokens::token_hash.eq(token_hash))
+            .filter(refresh_tokens::revoked_at.is_null())
+            .filter(refresh_tokens::expired_at.gt(now))
+            .select(RefreshToken::as_select())
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("find refresh token error: {:?}", err);
+                AppError::SessionQueryError
+            })
+    }
+
+    /// Whether the session has not been revoked. Every access token issued for it,
+    /// not only the latest one, stops working once it is.
+    pub fn is_active(connection: &mut PgConnection, id: Uuid) -> Result<bool, AppError> {
+        diesel::select(exists(
+            refresh_tokens::table
+                .filter(refresh_tokens::id.eq(id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        ))
+        .get_result(connection)
+        .map_err(|err| {
+            tracing::error!("find refresh token error: {:?}", err);
+            AppError::SessionQueryError
+        })
+    }
+
+    /// Finds the session whose token was already rotated away from `token_hash`.
+    pub fn find_by_previous_hash(
+        connection: &mut PgConnection,
+        token_hash: String,
+    ) -> Result<Option<RefreshToken>, AppError> {
+        refresh_tokens::table
+            .filter(refresh_tokens::previous_token_hash.eq(token_hash))
+            .select(RefreshToken::as_select())
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("find refresh token error: {:?}", err);
+                AppError::SessionQueryError
+            })
+    }
+
+    pub fn list_active_by_address(
+        connection: &mut PgConnection,
+        address: String,
+    ) -> Result<Vec<RefreshToken>, AppError> {
+        refresh_tokens::table
+            .filter(refresh_tokens::address.eq(address))
+            .filter(refresh_tokens::revoked_at.is_null())
+            .filter(refresh_tokens::expired_at.gt(now))
+            .order(refresh_tokens::last_used_at.desc())
+            .select(RefreshToken::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list refresh token error: {:?}", err);
+                AppError::SessionQueryError
+            })
+    }
+
+    /// Swaps in a new token, provided the session still holds `current_hash`.
+    /// Concurrent refreshes with the same token therefore succeed at most once.
+    pub fn rotate(
+        connection: &mut PgConnection,
+        id: Uuid,
+        current_hash: String,
+        rotated: RotatedRefreshToken,
+    ) -> Result<Option<RefreshToken>, AppError> {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::id.eq(id))
+                .filter(refresh_tokens::token_hash.eq(current_hash))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(&rotated)
+        .returning(RefreshToken::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("rotate refresh token error: {:?}", err);
+            AppError::SessionQueryError
+        })
+    }
+
+    pub fn revoke(
+        connection: &mut PgConnection,
+        id: Uuid,
+        address: String,
+    ) -> Result<Option<RefreshToken>, AppError> {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::id.eq(id))
+                .filter(refresh_tokens::address.eq(address))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(now))
+        .returning(RefreshToken::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("revoke refresh token error: {:?}", err);
+            AppError::SessionQueryError
+        })
+    }
+
+    pub fn revoke_all_by_address(
+        connection: &mut PgConnection,
+        address: String,
+    ) -> Result<Vec<RefreshToken>, AppError> {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::address.eq(address))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(now))
+        .returning(RefreshToken::as_returning())
+        .get_results(connection)
+        .map_err(|err| {
+            tracing::error!("revoke refresh tokens error: {:?}", err);
+            AppError::SessionQueryError
+        })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct InsertedRefreshToken {
+    pub id: Uuid,
+    pub address: String,
+    pub token_hash: String,
+    pub access_token_jti: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expired_at: NaiveDateTime,
+}
+
+impl InsertedRefreshToken {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<RefreshToken, AppError> {
+        diesel::insert_into(refresh_tokens::table)
+            .values(self)
+            .returning(RefreshToken::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("create refresh token error: {:?}", err);
+                AppError::CreateSessionFailed
+            })
+    }
+}
+
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = refresh_tokens)]
+pub struct RotatedRefreshToken {
+    pub token_hash: String,
+    pub previous_token_hash: String,
+    pub access_token_jti: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub last_used_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
@@ -0,0 +1,210 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+use super::schema::remote_pins;
+
+/// A pin request made to a remote pinning service.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = remote_pins)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RemotePin {
+    pub id: Uuid,
+    /// Name of the service in the `ipfs.remote_pinning` configuration.
+    pub service: String,
+    pub cid: String,
+    /// Id the service tracks the request under.
+    pub request_id: String,
+    /// `queued`, `pinning`, `pinned` or `failed`, as last reported by the service.
+    pub status: String,
+    /// Set for collection directories, whose request is replaced on every change.
+    pub collection_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl RemotePin {
+    /// The request for a collection's directory, or for `cid` when `collection_id` is `None`.
+    pub fn find(
+        connection: &mut PgConnection,
+        service: String,
+        collection_id: Option<Uuid>,
+        cid: String,
+    ) -> Result<Option<RemotePin>, AppError> {
+        let query_builder = remote_pins::table
+            .filter(remote_pins::service.eq(service))
+            .into_boxed();
+        let query_builder = match collection_id {
+            Some(collection_id) => {
+                query_builder.filter(remote_pins::collection_id.eq(collection_id))
+            }
+            None => query_builder
+                .filter(remote_pins::collection_id.is_null())
+                .filter(remote_pins::cid.eq(cid)),
+        };
+        query_builder
+            .select(RemotePin::as_select())
+            .first(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("find remote pin error: {:?}", err);
+                AppError::RemotePinQueryError
+            })
+    }
+
+    /// Like `find`, locking the row until the transaction ends.
+    pub fn find_for_update(
+        connection: &mut PgConnection,
+        service: String,
+        collection_id: Option<Uuid>,
+        cid: String,
+    ) -> Result<Option<RemotePin>, AppError> {
+        let query_builder = remote_pins::table.filter(remote_pins::service.eq(service));
+        match collection_id {
+            Some(collection_id) => query_builder
+                .filter(remote_pins::collection_id.eq(collection_id))
+                .select(RemotePin::as_select())
+                .for_update()
+                .first(connection),
+            None => query_builder
+                .filter(remote_pins::collection_id.is_null())
+                .filter(remote_pins::cid.eq(cid))
+                .select(RemotePin::as_select())
+                .for_update()
+                .first(connection),
+        }
+        .optional()
+        .map_err(|err| {
+            tracing::error!("lock remote pin error: {:?}", err);
+            AppError::RemotePinQueryError
+        })
+    }
+
+    /// Requests the services have not settled yet, least recently checked first.
+    pub fn list_unsettled(
+        connection: &mut PgConnection,
+        limit: i64,
+    ) -> Result<Vec<RemotePin>, AppError> {
+        remote_pins::table
+            .filter(remote_pins::status.eq_any(vec!["queued", "pinning"]))
+            .order(remote_pins::updated_at.asc())
+            .limit(limit)
+            .select(RemotePin::as_select())
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("list remote pins error: {:?}", err);
+                AppError::RemotePinQueryError
+            })
+    }
+
+    /// Points the row at a new request, e.g. after the directory was replaced.
+    pub fn update_request(
+        connection: &mut PgConnection,
+        id: Uuid,
+        cid: String,
+        request_id: String,
+        status: String,
+    ) -> Result<RemotePin, AppError> {
+        diesel::update(remote_pins::table.filter(remote_pins::id.eq(id)))
+            .set((
+                remote_pins::cid.eq(cid),
+                remote_pins::request_id.eq(request_id),
+                remote_pins::status.eq(status),
+                remote_pins::updated_at.eq(now),
+            ))
+            .returning(RemotePin::as_returning())
+            .get_result(connection)
+            .map_err(|err| {
+                tracing::error!("update remote pin request error: {:?}", err);
+                AppError::UpdateRemotePinFailed
+            })
+    }
+
+    /// Forgets every request made for the collection, returning them so they can be removed
+    /// from their services.
+    pub fn delete_by_collection(
+        connection: &mut PgConnection,
+        collection_id: Uuid,
+    ) -> Result<Vec<RemotePin>, AppError> {
+        diesel::delete(remote_pins::table.filter(remote_pins::collection_id.eq(collection_id)))
+            .returning(RemotePin::as_returning())
+            .get_results(connection)
+            .map_err(|err| {
+                tracing::error!("delete remote pins error: {:?}", err);
+                AppError::UpdateRemotePinFailed
+            })
+    }
+
+    /// Records the status the service reported for `request_id`. `None` if the row moved
+    /// on to another request meanwhile.
+    pub fn update_status(
+        connection: &mut PgConnection,
+        id: Uuid,
+        request_id: String,
+        status: String,
+    ) -> Result<Option<RemotePin>, AppError> {
+        diesel::update(
+            remote_pins::table
+                .filter(remote_pins::id.eq(id))
+                .filter(remote_pins::request_id.eq(request_id)),
+        )
+        .set((
+            remote_pins::status.eq(status),
+            remote_pins::updated_at.eq(now),
+        ))
+        .returning(RemotePin::as_returning())
+        .get_result(connection)
+        .optional()
+        .map_err(|err| {
+            tracing::error!("update remote pin status error: {:?}", err);
+            AppError::UpdateRemotePinFailed
+        })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = remote_pins)]
+pub struct InsertedRemotePin {
+    pub service: String,
+    pub cid: String,
+    pub request_id: String,
+    pub status: String,
+    pub collection_id: Option<Uuid>,
+}
+
+impl InsertedRemotePin {
+    /// Records the request, or points the service's existing row for the collection (or
+    /// for the CID, without one) at it. Returns the request id the existing row held.
+    pub fn upsert(&self, connection: &mut PgConnection) -> Result<Option<String>, AppError> {
+        let inserted = diesel::insert_into(remote_pins::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("create remote pin error: {:?}", err);
+                AppError::CreateRemotePinFailed
+            })?;
+        if inserted == 1 {
+            return Ok(None);
+        }
+        let existing = RemotePin::find_for_update(
+            connection,
+            self.service.clone(),
+            self.collection_id,
+            self.cid.clone(),
+        )?
+        .ok_or(AppError::CreateRemotePinFailed)?;
+        RemotePin::update_request(
+            connection,
+            existing.id,
+            self.cid.clone(),
+            self.request_id.clone(),
+            self.status.clone(),
+        )?;
+        Ok(Some(existing.request_id))
+    }
+}
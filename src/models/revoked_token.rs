@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::{exists, now};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AppError;
+
+use super::schema::revoked_tokens;
+
+/// An access token (by `jti`) that must be rejected until it expires on its own.
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = revoked_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RevokedToken {
+    pub jti: String,
+    pub expired_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl RevokedToken {
+    pub fn exists(connection: &mut PgConnection, jti: String) -> Result<bool, AppError> {
+        diesel::select(exists(
+            revoked_tokens::table.filter(revoked_tokens::jti.eq(jti)),
+        ))
+        .get_result(connection)
+        .map_err(|err| {
+            tracing::error!("find revoked token error: {:?}", err);
+            AppError::SessionQueryError
+        })
+    }
+
+    pub fn delete_expired(connection: &mut PgConnection) -> Result<usize, AppError> {
+        diesel::delete(revoked_tokens::table.filter(revoked_tokens::expired_at.le(now)))
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("delete expired revoked token error: {:?}", err);
+                AppError::SessionQueryError
+            })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = revoked_tokens)]
+pub struct InsertedRevokedToken {
+    pub jti: String,
+    pub expired_at: NaiveDateTime,
+}
+
+pub struct BatchInsertedRevokedToken {
+    pub tokens: Vec<InsertedRevokedToken>,
+}
+
+impl BatchInsertedRevokedToken {
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<usize, AppError> {
+        if self.tokens.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::insert_into(revoked_tokens::table)
+            .values(&self.tokens)
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .map_err(|err| {
+                tracing::error!("create revoked token error: {:?}", err);
+                AppError::SessionQueryError
+            })
+    }
+}
@@ -1,5 +1,26 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        address -> Varchar,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 16]
+        key_prefix -> Varchar,
+        #[max_length = 64]
+        key_hash -> Varchar,
+        #[max_length = 255]
+        scopes -> Varchar,
+        expired_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     collections (id) {
         id -> Uuid,
@@ -20,6 +41,57 @@ diesel::table! {
         dir_hash -> Varchar,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        next_token_id -> Int4,
+        deleted_at -> Nullable<Timestamptz>,
+        #[max_length = 16]
+        metadata_format -> Varchar,
+        description -> Nullable<Text>,
+        #[max_length = 255]
+        banner_url -> Nullable<Varchar>,
+        #[max_length = 255]
+        external_link -> Nullable<Varchar>,
+        seller_fee_basis_points -> Int4,
+        #[max_length = 64]
+        fee_recipient -> Nullable<Varchar>,
+        #[max_length = 64]
+        contract_cid -> Nullable<Varchar>,
+        revealed -> Bool,
+        #[max_length = 255]
+        placeholder_name -> Nullable<Varchar>,
+        placeholder_description -> Nullable<Text>,
+        #[max_length = 255]
+        placeholder_image_url -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    ipfs_jobs (id) {
+        id -> Uuid,
+        #[max_length = 16]
+        kind -> Varchar,
+        payload -> Jsonb,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        next_run_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        collection_id -> Nullable<Uuid>,
+        nft_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    login_nonces (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        address -> Varchar,
+        #[max_length = 64]
+        nonce -> Varchar,
+        expired_at -> Timestamptz,
+        created_at -> Timestamptz,
     }
 }
 
@@ -33,6 +105,9 @@ diesel::table! {
         trait_value -> Varchar,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
+        #[max_length = 32]
+        display_type -> Nullable<Varchar>,
+        max_value -> Nullable<Float8>,
     }
 }
 
@@ -52,6 +127,79 @@ diesel::table! {
         collection -> Varchar,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
+        #[max_length = 16]
+        publish_status -> Varchar,
+        chain_id -> Int4,
+        deleted_at -> Nullable<Timestamptz>,
+        animation_url -> Nullable<Text>,
+        #[max_length = 6]
+        background_color -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    pins (id) {
+        id -> Uuid,
+        #[max_length = 128]
+        cid -> Varchar,
+        #[max_length = 16]
+        kind -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        last_error -> Nullable<Text>,
+        collection_id -> Nullable<Uuid>,
+        verified_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        address -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        #[max_length = 64]
+        previous_token_hash -> Nullable<Varchar>,
+        #[max_length = 64]
+        access_token_jti -> Varchar,
+        #[max_length = 512]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 64]
+        ip_address -> Nullable<Varchar>,
+        expired_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        last_used_at -> Timestamptz,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    remote_pins (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        service -> Varchar,
+        #[max_length = 128]
+        cid -> Varchar,
+        #[max_length = 255]
+        request_id -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        collection_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        #[max_length = 64]
+        jti -> Varchar,
+        expired_at -> Timestamptz,
+        created_at -> Timestamptz,
     }
 }
 
@@ -68,12 +216,26 @@ diesel::table! {
         avatar_url -> Nullable<Varchar>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        #[max_length = 16]
+        role -> Varchar,
     }
 }
 
+diesel::joinable!(ipfs_jobs -> collections (collection_id));
+diesel::joinable!(ipfs_jobs -> nfts (nft_id));
+diesel::joinable!(pins -> collections (collection_id));
+diesel::joinable!(remote_pins -> collections (collection_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
     collections,
+    ipfs_jobs,
+    login_nonces,
     nft_traits,
     nfts,
+    pins,
+    refresh_tokens,
+    remote_pins,
+    revoked_tokens,
     users,
 );
@@ -5,9 +5,9 @@ use uuid::Uuid;
 
 use crate::errors::AppError;
 
-use super::{establish_connection, schema::users};
+use super::schema::users;
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
@@ -18,11 +18,14 @@ pub struct User {
     pub avatar_url: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub role: String,
 }
 
 impl User {
-    pub async fn find_by_address(address: String) -> Result<Option<User>, AppError> {
-        let connection = &mut establish_connection();
+    pub fn find_by_address(
+        connection: &mut PgConnection,
+        address: String,
+    ) -> Result<Option<User>, AppError> {
         users::table
             .filter(users::address.eq(address))
             .first(connection)
@@ -35,6 +38,49 @@ impl User {
                 }
             })
     }
+
+    pub fn list_by_addresses(
+        connection: &mut PgConnection,
+        addresses: Vec<String>,
+    ) -> Result<Vec<User>, AppError> {
+        users::table
+            .filter(users::address.eq_any(addresses))
+            .load(connection)
+            .map_err(|err| {
+                tracing::error!("find user by addresses error: {:?}", err);
+                AppError::UserQueryError
+            })
+    }
+
+    pub fn update_profile(
+        connection: &mut PgConnection,
+        address: String,
+        changes: &UpdatedUser,
+    ) -> Result<Option<User>, AppError> {
+        diesel::update(users::table.filter(users::address.eq(address)))
+            .set((changes, users::updated_at.eq(diesel::dsl::now)))
+            .get_result(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("update user profile error: {:?}", err);
+                AppError::UpdateUserFailed
+            })
+    }
+
+    pub fn update_role(
+        connection: &mut PgConnection,
+        address: String,
+        role: String,
+    ) -> Result<Option<User>, AppError> {
+        diesel::update(users::table.filter(users::address.eq(address)))
+            .set((users::role.eq(role), users::updated_at.eq(diesel::dsl::now)))
+            .get_result(connection)
+            .optional()
+            .map_err(|err| {
+                tracing::error!("update user role error: {:?}", err);
+                AppError::UpdateUserFailed
+            })
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -46,9 +92,17 @@ pub struct InsertedUser {
     pub avatar_url: Option<String>,
 }
 
+/// Profile fields a user can edit. `None` leaves a field unchanged; `Some(None)` clears it.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = users)]
+pub struct UpdatedUser {
+    pub name: Option<Option<String>>,
+    pub email: Option<Option<String>>,
+    pub avatar_url: Option<Option<String>>,
+}
+
 impl InsertedUser {
-    pub async fn insert(&self) -> Result<User, AppError> {
-        let connection = &mut establish_connection();
+    pub fn insert(&self, connection: &mut PgConnection) -> Result<User, AppError> {
         return diesel::insert_into(users::table)
             .values(self)
             .get_result(connection)
@@ -1,42 +1,120 @@
+use std::net::{IpAddr, SocketAddr};
+
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig, ALL_WEBSOCKET_PROTOCOLS};
 use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
-    extract::{ws::WebSocketUpgrade, State},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, Path, State},
     http,
     http::header::HeaderMap,
+    http::StatusCode,
     response::{Html, IntoResponse, Response},
     routing::{get, post},
-    Router,
+    Json, Router,
 };
+use chrono::Utc;
+use sha2::{Digest, Sha256};
 
 use crate::errors::AppError;
+use crate::keys;
 use crate::{
     app_state::AppState,
+    domain::collection::find_contract_metadata,
+    domain::guard::Authentication,
+    domain::nft::find_nft_metadata,
+    domain::session::ClientInfo,
     domain::token::{on_connection_init, Token},
 };
 
+/// Header carrying an API key, for clients that cannot sign in with a wallet.
+const X_API_KEY: &str = "x-api-key";
+
+/// Metadata changes on updates and reveals; clients revalidate with the ETag after this.
+const METADATA_CACHE_CONTROL: &str = "public, max-age=60";
+
 async fn graphql_playground() -> impl IntoResponse {
     Html(playground_source(
         GraphQLPlaygroundConfig::new("/graphql").subscription_endpoint("/ws"),
     ))
 }
 
+/// Longest User-Agent kept, matching `refresh_tokens.user_agent`.
+const USER_AGENT_MAX_CHARS: usize = 512;
+
+/// The client's address: the peer itself, or when the peer is a trusted proxy, the
+/// right-most `X-Forwarded-For` entry not added by another trusted proxy.
+fn client_ip(headers: &HeaderMap, remote_addr: SocketAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client_ip = remote_addr.ip();
+    if !trusted_proxies.contains(&client_ip) {
+        return client_ip;
+    }
+    let forwarded_for = headers
+        .get_all(http::header::HeaderName::from_static("x-forwarded-for"))
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded_for.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client_ip = ip;
+                if !trusted_proxies.contains(&ip) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client_ip
+}
+
+fn client_info(
+    headers: &HeaderMap,
+    remote_addr: SocketAddr,
+    trusted_proxies: &[IpAddr],
+) -> ClientInfo {
+    let user_agent = headers.get(http::header::USER_AGENT).map(|value| {
+        String::from_utf8_lossy(value.as_bytes())
+            .chars()
+            .take(USER_AGENT_MAX_CHARS)
+            .collect()
+    });
+    ClientInfo {
+        user_agent,
+        ip_address: Some(client_ip(headers, remote_addr, trusted_proxies).to_string()),
+    }
+}
+
 async fn graphql_handler(
     State(app_state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     req: GraphQLRequest,
 ) -> Result<GraphQLResponse, AppError> {
-    let mut req = req.into_inner();
+    let mut req = req.into_inner().data(client_info(
+        &headers,
+        remote_addr,
+        &app_state.config.server.trusted_proxies,
+    ));
     tracing::info!("graphql_handler: {} {}", req.query, req.variables);
 
     match headers
         .get(http::header::AUTHORIZATION)
         .map(|value| value.to_str().unwrap_or_default())
     {
-        None => Ok(app_state.schema.execute(req).await.into()),
+        None => {
+            if let Some(api_key) = headers
+                .get(X_API_KEY)
+                .map(|value| value.to_str().unwrap_or_default())
+            {
+                req = req.data(Authentication::from_api_key(&app_state.database, api_key).await);
+            }
+            Ok(app_state.schema.execute(req).await.into())
+        }
         Some(access_token) => match Token::parse_from_access_token(access_token.to_string()) {
             Ok(token) => {
-                req = req.data(token);
+                req = req
+                    .data(Authentication::from_token(&app_state.database, &token).await)
+                    .data(token);
                 Ok(app_state.schema.execute(req).await.into())
             }
             Err(err) => Err(err),
@@ -52,19 +130,178 @@ async fn graphql_websocket_handler(
     websocket
         .protocols(ALL_WEBSOCKET_PROTOCOLS)
         .on_upgrade(move |stream| {
+            let database = app_state.database.clone();
             GraphQLWebSocket::new(stream, app_state.schema.clone(), protocol)
-                .on_connection_init(on_connection_init)
+                .on_connection_init(move |value| on_connection_init(database, value))
                 .serve()
         })
 }
 
+/// Public keys for verifying our access tokens, for services that do not share our secrets.
+async fn jwks() -> impl IntoResponse {
+    (
+        [(http::header::CACHE_CONTROL, "public, max-age=300")],
+        Json(keys::current().jwks(Utc::now())),
+    )
+}
+
+/// Serves `body` as JSON with a strong ETag, answering a matching `If-None-Match` with 304.
+fn cached_json(headers: &HeaderMap, body: String) -> Response {
+    let etag = format!("\"{}\"", hex::encode(Sha256::digest(body.as_bytes())));
+    let not_modified = headers
+        .get(http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        })
+        .unwrap_or(false);
+    let cache_headers = [
+        (http::header::ETAG, etag),
+        (
+            http::header::CACHE_CONTROL,
+            METADATA_CACHE_CONTROL.to_string(),
+        ),
+    ];
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        cache_headers,
+        [(http::header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response()
+}
+
+/// `baseURI` lookups append the token id, with or without a `.json` suffix.
+fn parse_token_id(token_id: &str) -> Option<i32> {
+    token_id
+        .strip_suffix(".json")
+        .unwrap_or(token_id)
+        .parse()
+        .ok()
+}
+
+async fn nft_metadata(
+    State(app_state): State<AppState>,
+    Path((chain_id, contract_address, token_id)): Path<(i32, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let token_id = parse_token_id(&token_id).ok_or(AppError::NftNotFound)?;
+    let contract_address = contract_address.to_lowercase();
+    let body = app_state
+        .database
+        .run(move |connection| find_nft_metadata(connection, chain_id, contract_address, token_id))
+        .await?;
+    Ok(cached_json(&headers, body))
+}
+
+async fn contract_metadata(
+    State(app_state): State<AppState>,
+    Path((chain_id, contract_address)): Path<(i32, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let contract_address = contract_address.to_lowercase();
+    let body = app_state
+        .database
+        .run(move |connection| find_contract_metadata(connection, chain_id, contract_address))
+        .await?;
+    Ok(cached_json(&headers, body))
+}
+
+pub fn well_known_router() -> Router {
+    Router::new().route("/.well-known/jwks.json", get(jwks))
+}
+
 pub fn graphql_playground_router() -> Router {
     Router::new().route("/playground", get(graphql_playground))
 }
 
+/// Token and contract metadata over HTTP, for contracts whose `baseURI` points here.
+pub fn metadata_router(app_state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/metadata/:chain_id/:contract_address/contract.json",
+            get(contract_metadata),
+        )
+        .route(
+            "/metadata/:chain_id/:contract_address/:token_id",
+            get(nft_metadata),
+        )
+        .with_state(app_state)
+}
+
 pub fn graphql_router(app_state: AppState) -> Router {
     Router::new()
         .route("/graphql", post(graphql_handler))
         .route("/ws", get(graphql_websocket_handler))
         .with_state(app_state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_ids_accept_a_json_suffix() {
+        assert_eq!(parse_token_id("7"), Some(7));
+        assert_eq!(parse_token_id("7.json"), Some(7));
+        assert_eq!(parse_token_id("seven"), None);
+    }
+
+    #[test]
+    fn forwarded_for_is_only_believed_from_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let peer = SocketAddr::new(proxy, 443);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "198.51.100.1, 203.0.113.7, 10.0.0.2".parse().unwrap(),
+        );
+        assert_eq!(client_ip(&headers, peer, &[]), proxy);
+        assert_eq!(
+            client_ip(&headers, peer, &[proxy]),
+            "10.0.0.2".parse::<IpAddr>().unwrap()
+        );
+        let trusted = [proxy, "10.0.0.2".parse().unwrap()];
+        assert_eq!(
+            client_ip(&headers, peer, &trusted),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        headers.insert("x-forwarded-for", "not an ip".parse().unwrap());
+        assert_eq!(client_ip(&headers, peer, &[proxy]), proxy);
+    }
+
+    #[test]
+    fn long_user_agents_are_truncated_on_char_boundaries() {
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::USER_AGENT, "é".repeat(600).parse().unwrap());
+        let peer = SocketAddr::from(([127, 0, 0, 1], 80));
+        let client_info = client_info(&headers, peer, &[]);
+        assert_eq!(
+            client_info.user_agent.unwrap().chars().count(),
+            USER_AGENT_MAX_CHARS
+        );
+        assert_eq!(client_info.ip_address.as_deref(), Some("127.0.0.1"));
+    }
+
+    #[test]
+    fn matching_etags_are_not_modified() {
+        let body = r#"{"name":"Demo #1"}"#.to_string();
+        let response = cached_json(&HeaderMap::new(), body.clone());
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[http::header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_NONE_MATCH, etag.clone());
+        let response = cached_json(&headers, body.clone());
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[http::header::ETAG], etag);
+
+        let response = cached_json(&headers, r#"{"name":"Demo #2"}"#.to_string());
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
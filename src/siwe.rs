@@ -0,0 +1,367 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use sha3::{Digest, Keccak256};
+use web3_api::chain::ChainRpcRegistry;
+
+use crate::errors::AppError;
+
+const PREAMBLE_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+const SUPPORTED_VERSION: &str = "1";
+
+/// Tolerated clock drift between the wallet and the server.
+const CLOCK_SKEW: Duration = Duration::minutes(5);
+
+/// A parsed EIP-4361 (Sign-In With Ethereum) message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiweMessage {
+    pub domain: String,
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl FromStr for SiweMessage {
+    type Err = AppError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let mut lines = message.lines().peekable();
+
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(PREAMBLE_SUFFIX))
+            .ok_or(AppError::InvalidSiweMessage)?
+            .to_string();
+        let address = lines
+            .next()
+            .filter(|line| is_address(line) && has_valid_checksum(line))
+            .ok_or(AppError::InvalidSiweMessage)?
+            .to_string();
+
+        // Optional statement, surrounded by blank lines, up to the `URI` field.
+        let mut statement_lines = Vec::new();
+        while let Some(line) = lines.peek() {
+            if line.starts_with("URI: ") {
+                break;
+            }
+            if !line.is_empty() {
+                statement_lines.push(line.to_string());
+            }
+            lines.next();
+        }
+        let statement = match statement_lines.len() {
+            0 => None,
+            1 => statement_lines.pop(),
+            _ => return Err(AppError::InvalidSiweMessage),
+        };
+
+        let uri = required_field(lines.next(), "URI")?;
+        let version = required_field(lines.next(), "Version")?;
+        let chain_id = required_field(lines.next(), "Chain ID")?
+            .parse::<u64>()
+            .map_err(|_| AppError::InvalidSiweMessage)?;
+        let nonce = required_field(lines.next(), "Nonce")?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(AppError::InvalidSiweMessage);
+        }
+        let issued_at = parse_time(&required_field(lines.next(), "Issued At")?)?;
+
+        let mut expiration_time = None;
+        let mut not_before = None;
+        let mut request_id = None;
+        let mut resources = Vec::new();
+        while let Some(line) = lines.next() {
+            if let Some(value) = line.strip_prefix("Expiration Time: ") {
+                expiration_time = Some(parse_time(value)?);
+            } else if let Some(value) = line.strip_prefix("Not Before: ") {
+                not_before = Some(parse_time(value)?);
+            } else if let Some(value) = line.strip_prefix("Request ID: ") {
+                request_id = Some(value.to_string());
+            } else if line == "Resources:" {
+                for resource in lines.by_ref() {
+                    let resource = resource
+                        .strip_prefix("- ")
+                        .ok_or(AppError::InvalidSiweMessage)?;
+                    resources.push(resource.to_string());
+                }
+            } else if !line.is_empty() {
+                return Err(AppError::InvalidSiweMessage);
+            }
+        }
+
+        Ok(Self {
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+impl SiweMessage {
+    /// Checks the message was issued for this server and is valid at `now`.
+    pub fn validate(&self, domain: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+        if self.domain != domain {
+            tracing::error!("siwe domain mismatch: {} != {}", self.domain, domain);
+            return Err(AppError::InvalidSiweMessage);
+        }
+        if self.version != SUPPORTED_VERSION || self.chain_id == 0 {
+            return Err(AppError::InvalidSiweMessage);
+        }
+        if self.issued_at > now + CLOCK_SKEW {
+            return Err(AppError::InvalidSiweMessage);
+        }
+        if let Some(not_before) = self.not_before {
+            if not_before > now + CLOCK_SKEW {
+                return Err(AppError::SiweMessageNotYetValid);
+            }
+        }
+        if let Some(expiration_time) = self.expiration_time {
+            if expiration_time <= now {
+                return Err(AppError::SiweMessageExpired);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn required_field(line: Option<&str>, name: &str) -> Result<String, AppError> {
+    line.and_then(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.strip_prefix(": "))
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+        .ok_or(AppError::InvalidSiweMessage)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| AppError::InvalidSiweMessage)
+}
+
+pub fn is_address(value: &str) -> bool {
+    value.len() == 42
+        && value.starts_with("0x")
+        && value[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Checks the EIP-55 checksum of a mixed-case address. All-lowercase and all-uppercase
+/// addresses carry no checksum and are accepted as they are.
+pub fn has_valid_checksum(address: &str) -> bool {
+    let hex = &address[2..];
+    if hex == hex.to_lowercase() || hex == hex.to_uppercase() {
+        return true;
+    }
+    let hash = Keccak256::digest(hex.to_lowercase().as_bytes());
+    hex.chars().enumerate().all(|(i, c)| {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        !c.is_ascii_alphabetic() || c.is_ascii_uppercase() == (nibble >= 8)
+    })
+}
+
+/// EIP-191 `personal_sign` hash of `message`.
+pub fn hash_message(message: &str) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+    hasher.update(message.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Recovers the lowercase `0x` address that produced `signature` over `message`.
+pub fn recover_address(message: &str, signature: &str) -> Result<String, AppError> {
+    let bytes = decode_hex(signature)?;
+    if bytes.len() != 65 {
+        return Err(AppError::InvalidSignature);
+    }
+    let mut signature =
+        Signature::from_slice(&bytes[..64]).map_err(|_| AppError::InvalidSignature)?;
+    let v = match bytes[64] {
+        27 | 28 => bytes[64] - 27,
+        0 | 1 => bytes[64],
+        _ => return Err(AppError::InvalidSignature),
+    };
+    let mut recovery_id = RecoveryId::from_byte(v).ok_or(AppError::InvalidSignature)?;
+    // k256 only accepts low-S signatures; flip the parity when normalizing.
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id = RecoveryId::from_byte(v ^ 1).ok_or(AppError::InvalidSignature)?;
+    }
+
+    let verifying_key =
+        VerifyingKey::recover_from_prehash(&hash_message(message), &signature, recovery_id)
+            .map_err(|err| {
+                tracing::error!("recover signer error: {:?}", err);
+                AppError::InvalidSignature
+            })?;
+    let public_key = verifying_key.to_encoded_point(false);
+    let hash = Keccak256::digest(&public_key.as_bytes()[1..]);
+    Ok(format!("0x{}", hex::encode(&hash[12..])))
+}
+
+/// Checks `signature` was produced by the message's address. Signatures that do not
+/// ecrecover to it are handed to the address as an EIP-1271 contract wallet on the
+/// message's chain.
+pub async fn verify_signature(
+    chain_rpcs: &ChainRpcRegistry,
+    siwe_message: &SiweMessage,
+    message: &str,
+    signature: &str,
+) -> Result<(), AppError> {
+    let address = siwe_message.address.to_lowercase();
+    if matches!(recover_address(message, signature), Ok(signer) if signer == address) {
+        return Ok(());
+    }
+
+    let signature = decode_hex(signature)?;
+    let valid = chain_rpcs
+        .is_valid_signature(
+            siwe_message.chain_id,
+            &address,
+            hash_message(message),
+            &signature,
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!("EIP-1271 signature check error: {:?}", err);
+            AppError::InvalidSignature
+        })?;
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidSignature)
+    }
+}
+
+pub fn decode_hex(value: &str) -> Result<Vec<u8>, AppError> {
+    hex::decode(value.trim_start_matches("0x")).map_err(|_| AppError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "localhost:3000 wants you to sign in with your Ethereum account:
+0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf
+
+Sign in to the NFT marketplace.
+
+URI: http://localhost:3000
+Version: 1
+Chain ID: 1
+Nonce: 32891756aaf54d3a
+Issued At: 2024-05-10T10:00:00Z
+Expiration Time: 2024-05-10T10:10:00Z
+Resources:
+- https://example.com/terms";
+
+    fn at(value: &str) -> DateTime<Utc> {
+        parse_time(value).unwrap()
+    }
+
+    #[test]
+    fn parse_message() {
+        let message = SiweMessage::from_str(MESSAGE).unwrap();
+        assert_eq!(message.domain, "localhost:3000");
+        assert_eq!(
+            message.address,
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+        assert_eq!(
+            message.statement.as_deref(),
+            Some("Sign in to the NFT marketplace.")
+        );
+        assert_eq!(message.chain_id, 1);
+        assert_eq!(message.nonce, "32891756aaf54d3a");
+        assert_eq!(message.issued_at, at("2024-05-10T10:00:00Z"));
+        assert_eq!(message.expiration_time, Some(at("2024-05-10T10:10:00Z")));
+        assert_eq!(message.resources, vec!["https://example.com/terms"]);
+    }
+
+    #[test]
+    fn parse_message_without_statement() {
+        let message = MESSAGE.replace("Sign in to the NFT marketplace.\n\n", "");
+        let message = SiweMessage::from_str(&message).unwrap();
+        assert_eq!(message.statement, None);
+    }
+
+    #[test]
+    fn reject_malformed_message() {
+        assert!(SiweMessage::from_str("hello").is_err());
+        let message = MESSAGE.replace("Chain ID: 1", "Chain ID: mainnet");
+        assert!(SiweMessage::from_str(&message).is_err());
+        let message = MESSAGE.replace("Nonce: 32891756aaf54d3a", "Nonce: abc");
+        assert!(SiweMessage::from_str(&message).is_err());
+        // one letter of the checksummed address in the wrong case
+        let message = MESSAGE.replace(
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf",
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395BdF",
+        );
+        assert!(SiweMessage::from_str(&message).is_err());
+        let message = MESSAGE.replace(
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf",
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf",
+        );
+        assert!(SiweMessage::from_str(&message).is_ok());
+    }
+
+    #[test]
+    fn validate_message() {
+        let message = SiweMessage::from_str(MESSAGE).unwrap();
+        let now = at("2024-05-10T10:05:00Z");
+        assert_eq!(message.validate("localhost:3000", now), Ok(()));
+        assert_eq!(
+            message.validate("evil.com", now),
+            Err(AppError::InvalidSiweMessage)
+        );
+        assert_eq!(
+            message.validate("localhost:3000", at("2024-05-10T10:10:00Z")),
+            Err(AppError::SiweMessageExpired)
+        );
+        let message = SiweMessage {
+            not_before: Some(at("2024-05-10T10:08:00Z")),
+            ..message
+        };
+        assert_eq!(
+            message.validate("localhost:3000", at("2024-05-10T10:02:00Z")),
+            Err(AppError::SiweMessageNotYetValid)
+        );
+        assert_eq!(message.validate("localhost:3000", now), Ok(()));
+    }
+
+    #[test]
+    fn recover_signer() {
+        let signing_key = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let (signature, recovery_id) = signing_key
+            .sign_prehash_recoverable(&hash_message(MESSAGE))
+            .unwrap();
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+
+        let recovered = recover_address(MESSAGE, &format!("0x{}", hex::encode(&bytes))).unwrap();
+        let public_key = signing_key.verifying_key().to_encoded_point(false);
+        let expected = Keccak256::digest(&public_key.as_bytes()[1..]);
+        assert_eq!(recovered, format!("0x{}", hex::encode(&expected[12..])));
+
+        assert_eq!(
+            recover_address(MESSAGE, "0x1234"),
+            Err(AppError::InvalidSignature)
+        );
+    }
+}
@@ -1,19 +1,47 @@
-use std::io::Read;
-
 use async_graphql::{Context, Upload};
+use reqwest::Body;
+use tokio::io::AsyncReadExt;
 
 use crate::errors::AppError;
 
-pub fn parse_upload(ctx: &Context<'_>, file: Upload) -> Result<(String, Vec<u8>), AppError> {
-    let file_name = file.value(ctx).unwrap().filename;
-    let mut buffer = Vec::new();
-    file.value(ctx)
-        .unwrap()
+/// Size of the chunks an upload is forwarded to IPFS in.
+const UPLOAD_CHUNK_SIZE: usize = 256 * 1024;
+
+/// An upload opened for streaming to IPFS.
+pub struct UploadStream {
+    pub filename: String,
+    pub body: Body,
+    pub length: u64,
+}
+
+/// Opens an upload as a streamed request body. async-graphql spools uploads to a temporary
+/// file, which is read a chunk at a time instead of being loaded into memory.
+pub fn parse_upload(ctx: &Context<'_>, file: Upload) -> Result<UploadStream, AppError> {
+    let value = file.value(ctx).map_err(|err| {
+        tracing::error!("upload file error: {:?}", err);
+        AppError::UploadMissingFile
+    })?;
+    let length = value
         .content
-        .read_to_end(&mut buffer)
+        .metadata()
         .map_err(|err| {
             tracing::error!("upload file error: {:?}", err);
             AppError::UploadMissingFile
-        })?;
-    Ok((file_name, buffer))
+        })?
+        .len();
+    let content = tokio::fs::File::from_std(value.content);
+    let chunks = futures_util::stream::try_unfold(content, |mut content| async move {
+        let mut chunk = vec![0; UPLOAD_CHUNK_SIZE];
+        let read = content.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        chunk.truncate(read);
+        Ok(Some((chunk, content)))
+    });
+    Ok(UploadStream {
+        filename: value.filename,
+        body: Body::wrap_stream(chunks),
+        length,
+    })
 }
@@ -0,0 +1,1156 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+use diesel::Connection;
+use ipfs_api::client::Client;
+use ipfs_api::remote::RemotePinning;
+use ipfs_api::req::files::{
+    FlushQuery, FlushRequest, MkdirQuery, MkdirRequest, StatQuery, StatRequest, WriteQuery,
+    WriteRequest,
+};
+use ipfs_api::req::pin::{
+    PinAddQuery, PinAddRequest, PinLsQuery, PinLsRequest, PinRmQuery, PinRmRequest, PinType,
+    PinUpdateQuery, PinUpdateRequest, PinVerifyQuery, PinVerifyRequest,
+};
+use ipfs_api::req::remote::{AddPinRequest, RemotePinStatus};
+use ipfs_api::resp::remote::PinStatusResponse;
+use uuid::Uuid;
+
+use crate::{
+    config::{RemotePinningServices, WorkerConfig},
+    domain::collection::CONTRACT_METADATA_FILE,
+    domain::ipfs_job::{self, IpfsJobKind, IpfsOperation},
+    domain::nft::PublishStatus,
+    domain::pin::{enqueue_remote_pins, PinStatus},
+    errors::AppError,
+    models::collection::Collection,
+    models::ipfs_job::IpfsJob,
+    models::nft::NFT,
+    models::pin::Pin,
+    models::remote_pin::{InsertedRemotePin, RemotePin},
+    models::Database,
+};
+
+/// Delay before the first retry; doubled on every further attempt.
+const BASE_BACKOFF_SECONDS: i64 = 5;
+const MAX_BACKOFF_SECONDS: i64 = 60 * 60;
+
+/// Remote requests checked per reconciliation.
+const REMOTE_POLL_LIMIT: i64 = 100;
+
+/// What a successful job leaves to record.
+enum Outcome {
+    Done,
+    /// The node pinned this CID.
+    Pinned(String),
+    /// The node pinned the directory at `path`, flushed to `cid`, holding `contract.json`
+    /// at `contract_cid`.
+    DirectoryPinned {
+        path: String,
+        cid: String,
+        contract_cid: Option<String>,
+    },
+    /// A remote service accepted a pin request, superseding the request `replaced` if set.
+    RemotePinned {
+        service: String,
+        replaced: Option<String>,
+        status: PinStatusResponse,
+    },
+}
+
+#[derive(Debug)]
+enum JobError {
+    /// Worth retrying, e.g. the node was unreachable.
+    Transient(String),
+    /// Retrying cannot help.
+    Permanent(String),
+}
+
+/// Delay before retrying a job that has failed `attempts` times.
+fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    Duration::seconds(
+        BASE_BACKOFF_SECONDS
+            .saturating_mul(2i64.saturating_pow(exponent))
+            .min(MAX_BACKOFF_SECONDS),
+    )
+}
+
+async fn pin_add<C: Client>(client: &C, cid: &String) -> Result<(), JobError> {
+    client
+        .pin_add(PinAddRequest {
+            query: PinAddQuery::new_with_arg(cid),
+        })
+        .await
+        .map(|_| ())
+        .map_err(|err| JobError::Transient(format!("pin {}: {}", cid, err)))
+}
+
+/// Flushes the directory at `path` and pins its CID, updating from `previous` so only
+/// changed blocks are fetched. `previous` stays pinned until no row references it.
+async fn pin_directory<C: Client>(
+    client: &C,
+    path: &String,
+    previous: Option<String>,
+) -> Result<String, JobError> {
+    let cid = client
+        .files_flush(FlushRequest {
+            query: FlushQuery::new_with_arg(path),
+        })
+        .await
+        .map(|response| response.cid)
+        .map_err(|err| JobError::Transient(format!("flush {}: {}", path, err)))?;
+    match previous.filter(|previous| *previous != cid) {
+        Some(previous) => {
+            let updated = client
+                .pin_update(PinUpdateRequest {
+                    query: PinUpdateQuery {
+                        from: previous.clone(),
+                        to: cid.clone(),
+                        unpin: Some(false),
+                    },
+                })
+                .await;
+            if let Err(err) = updated {
+                // the old pin may be gone already, e.g. removed by reconciliation
+                tracing::warn!("pin update {} -> {} failed: {}", previous, cid, err);
+                pin_add(client, &cid).await?;
+            }
+        }
+        None => pin_add(client, &cid).await?,
+    }
+    Ok(cid)
+}
+
+/// CID of the collection's `contract.json` in the flushed directory at `path`. `None` if
+/// the directory has none.
+async fn stat_contract_metadata<C: Client>(client: &C, path: &str) -> Option<String> {
+    let path = format!("{}/{}", path, CONTRACT_METADATA_FILE);
+    match client
+        .files_stat(StatRequest {
+            query: StatQuery::new_with_arg(&path),
+        })
+        .await
+    {
+        Ok(response) => response.hash,
+        Err(err) => {
+            tracing::warn!("stat {} error: {:?}", path, err);
+            None
+        }
+    }
+}
+
+/// Asks `remote` to pin `cid`, replacing the `previous` request if set. `None` if
+/// `previous` pins `cid` already. When the service lost `previous`, the pin is added anew
+/// and `previous` removed, so a request it still tracks is not left behind.
+async fn request_remote_pin<R: RemotePinning>(
+    remote: &R,
+    previous: Option<&RemotePin>,
+    name: Option<String>,
+    cid: &String,
+) -> Result<Option<PinStatusResponse>, JobError> {
+    let request = AddPinRequest {
+        name,
+        ..AddPinRequest::new_with_cid(cid)
+    };
+    let Some(previous) = previous else {
+        return remote
+            .add_pin(request)
+            .await
+            .map(Some)
+            .map_err(|err| JobError::Transient(format!("remote pin {}: {}", cid, err)));
+    };
+    if previous.cid == *cid && previous.status != RemotePinStatus::Failed.as_str() {
+        return Ok(None);
+    }
+    match remote
+        .replace_pin(&previous.request_id, request.clone())
+        .await
+    {
+        Ok(status) => Ok(Some(status)),
+        Err(err) => {
+            tracing::warn!("replace remote pin {} failed: {}", previous.request_id, err);
+            let status = remote
+                .add_pin(request)
+                .await
+                .map_err(|err| JobError::Transient(format!("remote pin {}: {}", cid, err)))?;
+            if let Err(err) = remote.remove_pin(&previous.request_id).await {
+                tracing::warn!("remove remote pin {} error: {:?}", previous.request_id, err);
+            }
+            Ok(Some(status))
+        }
+    }
+}
+
+/// Pins `cid` to `service`. A directory replaces the service's previous request for its
+/// collection, as does a request the service reported failed.
+async fn remote_pin(
+    database: &Database,
+    remotes: &RemotePinningServices,
+    collection_id: Option<Uuid>,
+    service: String,
+    cid: String,
+) -> Result<Outcome, JobError> {
+    let remote = remotes
+        .get(&service)
+        .ok_or_else(|| JobError::Permanent(format!("remote pin: unknown service {}", service)))?;
+    let (find_service, find_cid) = (service.clone(), cid.clone());
+    let previous = database
+        .run(move |connection| RemotePin::find(connection, find_service, collection_id, find_cid))
+        .await
+        .map_err(|err| JobError::Transient(format!("remote pin {}: {:?}", cid, err)))?;
+    let name = collection_id.map(|collection_id| collection_id.to_string());
+    let status = request_remote_pin(remote, previous.as_ref(), name, &cid)
+        .await
+        .map_err(|err| {
+            JobError::Transient(format!("remote pin {} on {}: {}", cid, service, err))
+        })?;
+    Ok(match status {
+        Some(status) => Outcome::RemotePinned {
+            service,
+            replaced: previous.map(|previous| previous.request_id),
+            status,
+        },
+        None => Outcome::Done,
+    })
+}
+
+/// Removes `request_id` from `service`.
+async fn remote_unpin(
+    remotes: &RemotePinningServices,
+    service: String,
+    request_id: String,
+) -> Result<Outcome, JobError> {
+    let remote = remotes
+        .get(&service)
+        .ok_or_else(|| JobError::Permanent(format!("remote unpin: unknown service {}", service)))?;
+    remote
+        .remove_pin(&request_id)
+        .await
+        .map(|_| Outcome::Done)
+        .map_err(|err| {
+            JobError::Transient(format!(
+                "remote unpin {} on {}: {}",
+                request_id, service, err
+            ))
+        })
+}
+
+/// Runs `operation` against the node.
+async fn execute<C: Client>(
+    client: &C,
+    operation: IpfsOperation,
+    previous_pin: Option<String>,
+) -> Result<Outcome, JobError> {
+    match operation {
+        IpfsOperation::Mkdir { path } => client
+            .files_mkdir(MkdirRequest {
+                query: MkdirQuery::new_with_arg(&path),
+            })
+            .await
+            .map(|_| Outcome::Done)
+            .map_err(|err| JobError::Transient(format!("mkdir {}: {}", path, err))),
+        IpfsOperation::Write { path, content } => {
+            let filename = path.rsplit('/').next().unwrap_or_default().to_string();
+            let mut query = WriteQuery::new_with_arg(path.clone());
+            query.parents = Some(true);
+            query.truncate = Some(true);
+            client
+                .files_write(WriteRequest {
+                    query,
+                    bytes: content.into_bytes(),
+                    filename,
+                })
+                .await
+                .map(|_| Outcome::Done)
+                .map_err(|err| JobError::Transient(format!("write {}: {}", path, err)))
+        }
+        IpfsOperation::Flush => client
+            .files_flush(FlushRequest {
+                query: FlushQuery::default(),
+            })
+            .await
+            .map(|_| Outcome::Done)
+            .map_err(|err| JobError::Transient(format!("flush: {}", err))),
+        IpfsOperation::Pin { cid } => pin_add(client, &cid).await.map(|_| Outcome::Pinned(cid)),
+        IpfsOperation::PinDirectory { path } => {
+            let cid = pin_directory(client, &path, previous_pin).await?;
+            // recorded from the flushed directory, so it matches what the node serves
+            let contract_cid = stat_contract_metadata(client, &path).await;
+            Ok(Outcome::DirectoryPinned {
+                path,
+                cid,
+                contract_cid,
+            })
+        }
+        operation @ (IpfsOperation::RemotePin { .. } | IpfsOperation::RemoteUnpin { .. }) => {
+            Err(JobError::Permanent(format!(
+                "{}: not a node operation",
+                operation.kind().as_str()
+            )))
+        }
+    }
+}
+
+/// Unpins CIDs the last referencing row let go of. A CID referenced again meanwhile is
+/// re-pinned by reconciliation.
+async fn unpin<C: Client>(client: &C, cids: Vec<String>) {
+    for cid in cids {
+        if let Err(err) = client
+            .pin_rm(PinRmRequest {
+                query: PinRmQuery {
+                    arg: cid.clone(),
+                    recursive: None,
+                },
+            })
+            .await
+        {
+            tracing::warn!("ipfs pin rm {} error: {:?}", cid, err);
+        }
+    }
+}
+
+/// Runs one claimed job and records the outcome. A successful metadata write also marks
+/// its NFT published, and a pin is recorded, in the same transaction as completing the job.
+/// A new directory pin is queued for every remote pinning service, and the collection's
+/// `contract.json` CID recorded. A directory pin only
+/// advances from the pin it started from; otherwise it is queued again from the current one.
+async fn run_job<C: Client>(
+    database: &Database,
+    client: &C,
+    remotes: &RemotePinningServices,
+    job: IpfsJob,
+) -> Result<(), AppError> {
+    let directory_of = job
+        .collection_id
+        .filter(|_| job.kind == IpfsJobKind::PinDirectory.as_str());
+    let previous_pin = match directory_of {
+        Some(collection_id) => database
+            .run(move |connection| Pin::find_directory(connection, collection_id))
+            .await?
+            .map(|pin| pin.cid),
+        None => None,
+    };
+    let result = match serde_json::from_value::<IpfsOperation>(job.payload) {
+        Ok(IpfsOperation::RemotePin { service, cid }) => {
+            remote_pin(database, remotes, job.collection_id, service, cid).await
+        }
+        Ok(IpfsOperation::RemoteUnpin {
+            service,
+            request_id,
+        }) => remote_unpin(remotes, service, request_id).await,
+        Ok(operation) => execute(client, operation, previous_pin.clone()).await,
+        Err(err) => Err(JobError::Permanent(format!("invalid payload: {}", err))),
+    };
+
+    let (id, attempts, max_attempts) = (job.id, job.attempts, job.max_attempts);
+    let publishes_nft = job
+        .nft_id
+        .filter(|_| job.kind == IpfsJobKind::Write.as_str());
+    let remote_services: Vec<String> = remotes.keys().cloned().collect();
+    let remote_collection_id = job.collection_id;
+    let unpinned = database
+        .run(move |connection| match result {
+            Ok(outcome) => connection.transaction(|connection| {
+                let mut unpinned = vec![];
+                IpfsJob::complete(connection, id)?;
+                if let Some(nft_id) = publishes_nft {
+                    NFT::update_publish_status(
+                        connection,
+                        nft_id,
+                        PublishStatus::Published.as_str().to_string(),
+                    )?;
+                }
+                match (outcome, directory_of) {
+                    (
+                        Outcome::DirectoryPinned {
+                            path,
+                            cid,
+                            contract_cid,
+                        },
+                        Some(collection_id),
+                    ) => {
+                        let advanced = Pin::advance_directory(
+                            connection,
+                            collection_id,
+                            previous_pin.clone(),
+                            cid.clone(),
+                        )?;
+                        let released = if advanced {
+                            Collection::update_dir_hash(connection, collection_id, cid.clone())?;
+                            if let Some(contract_cid) = contract_cid {
+                                Collection::update_contract_cid(
+                                    connection,
+                                    collection_id,
+                                    contract_cid,
+                                )?;
+                            }
+                            enqueue_remote_pins(
+                                connection,
+                                &remote_services,
+                                &cid,
+                                Some(collection_id),
+                            )?;
+                            previous_pin.filter(|previous| *previous != cid)
+                        } else {
+                            let operation = IpfsOperation::PinDirectory { path };
+                            ipfs_job::enqueue(connection, &operation, Some(collection_id), None)?;
+                            Some(cid)
+                        };
+                        if let Some(released) = released {
+                            if Pin::count_references(connection, released.clone())? == 0 {
+                                unpinned.push(released);
+                            }
+                        }
+                    }
+                    (Outcome::Pinned(cid), _) => {
+                        Pin::mark_pinned(connection, cid)?;
+                    }
+                    (
+                        Outcome::RemotePinned {
+                            service,
+                            replaced,
+                            status,
+                        },
+                        _,
+                    ) => {
+                        let request_id = status.requestid.clone();
+                        let superseded = InsertedRemotePin {
+                            service: service.clone(),
+                            cid: status.pin.cid,
+                            request_id: status.requestid,
+                            status: status.status.as_str().to_string(),
+                            collection_id: remote_collection_id,
+                        }
+                        .upsert(connection)?;
+                        // a concurrent job's request this one took the row from
+                        if let Some(superseded) = superseded.filter(|superseded| {
+                            Some(superseded) != replaced.as_ref() && *superseded != request_id
+                        }) {
+                            let operation = IpfsOperation::RemoteUnpin {
+                                service,
+                                request_id: superseded,
+                            };
+                            ipfs_job::enqueue(connection, &operation, remote_collection_id, None)?;
+                        }
+                    }
+                    (Outcome::DirectoryPinned { .. } | Outcome::Done, _) => {}
+                }
+                Ok(unpinned)
+            }),
+            Err(JobError::Transient(last_error)) if attempts < max_attempts => {
+                tracing::warn!(
+                    "ipfs job {} attempt {} failed: {}",
+                    id,
+                    attempts,
+                    last_error
+                );
+                let retry_at = (Utc::now() + backoff(attempts)).naive_utc();
+                IpfsJob::fail(connection, id, last_error, Some(retry_at)).map(|_| vec![])
+            }
+            Err(JobError::Transient(last_error) | JobError::Permanent(last_error)) => {
+                tracing::error!("ipfs job {} failed: {}", id, last_error);
+                IpfsJob::fail(connection, id, last_error, None).map(|_| vec![])
+            }
+        })
+        .await?;
+    unpin(client, unpinned).await;
+    Ok(())
+}
+
+/// Claims and runs due jobs until none are left.
+async fn drain<C: Client>(
+    database: &Database,
+    client: &C,
+    remotes: &RemotePinningServices,
+    config: &WorkerConfig,
+) -> Result<(), AppError> {
+    loop {
+        let batch_size = config.batch_size;
+        let lease_until = (Utc::now() + Duration::seconds(config.lease_seconds)).naive_utc();
+        let jobs = database
+            .run(move |connection| IpfsJob::claim_due(connection, batch_size, lease_until))
+            .await?;
+        if jobs.is_empty() {
+            return Ok(());
+        }
+        for job in jobs {
+            run_job(database, client, remotes, job).await?;
+        }
+    }
+}
+
+/// Pins the node still holds completely, and those it lost with the reason.
+struct PinCheck {
+    verified: Vec<Uuid>,
+    missing: Vec<(Pin, String)>,
+}
+
+/// Checks `pins` against the node's recursive pins and `pin/verify`. Broken pins are removed
+/// from the node, as pinning a CID that is still pinned does not fetch its missing blocks.
+async fn check_pins<C: Client>(client: &C, pins: Vec<Pin>) -> Result<PinCheck, AppError> {
+    let pinned: HashSet<String> = client
+        .pin_ls(PinLsRequest {
+            query: PinLsQuery {
+                typ: Some(PinType::Recursive),
+                ..Default::default()
+            },
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!("ipfs pin ls error: {:?}", err);
+            AppError::RequestIpfsFailed
+        })?
+        .keys
+        .into_keys()
+        .collect();
+    let broken: HashSet<String> = client
+        .pin_verify(PinVerifyRequest {
+            query: PinVerifyQuery::default(),
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!("ipfs pin verify error: {:?}", err);
+            AppError::RequestIpfsFailed
+        })?
+        .pins
+        .into_iter()
+        .filter(|pin| !pin.ok)
+        .map(|pin| pin.cid)
+        .collect();
+
+    let mut check = PinCheck {
+        verified: vec![],
+        missing: vec![],
+    };
+    let mut removed = HashSet::new();
+    for pin in pins {
+        if broken.contains(&pin.cid) {
+            // several rows may reference one CID; it is removed once
+            if removed.insert(pin.cid.clone()) {
+                unpin(client, vec![pin.cid.clone()]).await;
+            }
+            check.missing.push((pin, "incomplete".to_string()));
+        } else if !pinned.contains(&pin.cid) {
+            check.missing.push((pin, "not pinned".to_string()));
+        } else if PinStatus::parse(&pin.status) == PinStatus::Pinned {
+            check.verified.push(pin.id);
+        }
+    }
+    Ok(check)
+}
+
+/// Compares the recorded pins with the node's and queues a re-pin for every one that is
+/// gone or fails `pin/verify`. Returns how many were queued.
+async fn reconcile<C: Client>(database: &Database, client: &C) -> Result<usize, AppError> {
+    // Load the rows first, so a pin recorded meanwhile is not reported missing.
+    let pins = database.run(Pin::list).await?;
+    let PinCheck { verified, missing } = check_pins(client, pins).await?;
+
+    database
+        .run(move |connection| {
+            Pin::mark_verified(connection, verified)?;
+            let mut queued = 0;
+            for (pin, reason) in missing {
+                connection.transaction(|connection| {
+                    if Pin::mark_missing(connection, pin.id, reason)?.is_some() {
+                        let operation = IpfsOperation::Pin { cid: pin.cid };
+                        ipfs_job::enqueue(connection, &operation, pin.collection_id, None)?;
+                        queued += 1;
+                    }
+                    Ok::<_, AppError>(())
+                })?;
+            }
+            Ok(queued)
+        })
+        .await
+}
+
+/// What the services report for `remote_pins`. Requests of services no longer configured,
+/// or that could not be asked about, are left out.
+async fn check_remote_pins(
+    remotes: &RemotePinningServices,
+    remote_pins: Vec<RemotePin>,
+) -> Vec<(RemotePin, RemotePinStatus)> {
+    let mut statuses = vec![];
+    for remote_pin in remote_pins {
+        // services removed from the configuration are left alone
+        let Some(remote) = remotes.get(&remote_pin.service) else {
+            continue;
+        };
+        match remote.get_pin(&remote_pin.request_id).await {
+            Ok(status) => statuses.push((remote_pin, status.status)),
+            Err(err) => tracing::warn!(
+                "poll remote pin {} on {} error: {:?}",
+                remote_pin.request_id,
+                remote_pin.service,
+                err
+            ),
+        }
+    }
+    statuses
+}
+
+/// Asks each remote service about requests it has not settled. A failed request is queued
+/// again, which replaces it. Returns how many were queued.
+async fn poll_remote_pins(
+    database: &Database,
+    remotes: &RemotePinningServices,
+) -> Result<usize, AppError> {
+    if remotes.is_empty() {
+        return Ok(0);
+    }
+    let remote_pins = database
+        .run(|connection| RemotePin::list_unsettled(connection, REMOTE_POLL_LIMIT))
+        .await?;
+    let statuses = check_remote_pins(remotes, remote_pins).await;
+    database
+        .run(move |connection| {
+            let mut queued = 0;
+            for (remote_pin, status) in statuses {
+                connection.transaction(|connection| {
+                    let updated = RemotePin::update_status(
+                        connection,
+                        remote_pin.id,
+                        remote_pin.request_id,
+                        status.as_str().to_string(),
+                    )?;
+                    if updated.is_some() && status == RemotePinStatus::Failed {
+                        let operation = IpfsOperation::RemotePin {
+                            service: remote_pin.service,
+                            cid: remote_pin.cid,
+                        };
+                        ipfs_job::enqueue(connection, &operation, remote_pin.collection_id, None)?;
+                        queued += 1;
+                    }
+                    Ok::<_, AppError>(())
+                })?;
+            }
+            Ok(queued)
+        })
+        .await
+}
+
+/// Starts the background tasks draining the IPFS outbox and reconciling local and remote
+/// pins. Several instances may run at once; rows are claimed with `FOR UPDATE SKIP LOCKED`.
+pub fn spawn<C>(database: Database, client: C, remotes: RemotePinningServices, config: WorkerConfig)
+where
+    C: Client + Send + Sync + 'static,
+{
+    let client = Arc::new(client);
+    let remotes = Arc::new(remotes);
+    let reconcile_database = database.clone();
+    let reconcile_client = client.clone();
+    let reconcile_remotes = remotes.clone();
+    let reconcile_seconds = config.reconcile_seconds;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(StdDuration::from_secs(config.poll_seconds));
+        loop {
+            interval.tick().await;
+            if let Err(err) = drain(&database, client.as_ref(), &remotes, &config).await {
+                tracing::error!("ipfs worker error: {:?}", err);
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(StdDuration::from_secs(reconcile_seconds));
+        loop {
+            interval.tick().await;
+            match reconcile(&reconcile_database, reconcile_client.as_ref()).await {
+                Ok(0) => {}
+                Ok(queued) => tracing::warn!("queued {} missing pins", queued),
+                Err(err) => tracing::error!("pin reconciliation error: {:?}", err),
+            }
+            match poll_remote_pins(&reconcile_database, &reconcile_remotes).await {
+                Ok(0) => {}
+                Ok(queued) => tracing::warn!("queued {} failed remote pins", queued),
+                Err(err) => tracing::error!("remote pin polling error: {:?}", err),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ipfs_api::client::LocalIPFSClient;
+    use ipfs_api::remote::RemotePinningClient;
+    use mockito::{Matcher, Server};
+
+    use super::*;
+
+    fn client(server: &Server) -> LocalIPFSClient {
+        LocalIPFSClient::new(format!("{}/api/v0", server.url()))
+    }
+
+    fn pin(cid: &str, status: PinStatus) -> Pin {
+        let now = Utc::now().naive_utc();
+        Pin {
+            id: Uuid::new_v4(),
+            cid: cid.to_string(),
+            kind: "image".to_string(),
+            status: status.as_str().to_string(),
+            last_error: None,
+            collection_id: None,
+            verified_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn remote(server: &Server) -> RemotePinningClient {
+        RemotePinningClient::new(format!("{}/psa", server.url()), "secret".to_string()).unwrap()
+    }
+
+    fn remote_pin(
+        service: &str,
+        cid: &str,
+        request_id: &str,
+        status: RemotePinStatus,
+    ) -> RemotePin {
+        let now = Utc::now().naive_utc();
+        RemotePin {
+            id: Uuid::new_v4(),
+            service: service.to_string(),
+            cid: cid.to_string(),
+            request_id: request_id.to_string(),
+            status: status.as_str().to_string(),
+            collection_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn pin_status(request_id: &str, cid: &str, status: &str) -> String {
+        serde_json::json!({
+            "requestid": request_id,
+            "status": status,
+            "created": "2024-05-26T08:00:00Z",
+            "pin": { "cid": cid },
+            "delegates": [],
+        })
+        .to_string()
+    }
+
+    async fn mock_flush(server: &mut Server, cid: &str) -> mockito::Mock {
+        server
+            .mock("POST", "/api/v0/files/flush")
+            .match_query(Matcher::UrlEncoded("arg".to_string(), "/0xdir".to_string()))
+            .with_body(format!(r#"{{"Cid":"{}"}}"#, cid))
+            .create_async()
+            .await
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1), Duration::seconds(5));
+        assert_eq!(backoff(2), Duration::seconds(10));
+        assert_eq!(backoff(4), Duration::seconds(40));
+        assert_eq!(backoff(20), Duration::seconds(MAX_BACKOFF_SECONDS));
+        assert_eq!(backoff(i32::MAX), Duration::seconds(MAX_BACKOFF_SECONDS));
+    }
+
+    #[test]
+    fn operations_round_trip_through_the_payload() {
+        let operation = IpfsOperation::Write {
+            path: "/dir/1.json".to_string(),
+            content: "{}".to_string(),
+        };
+        let payload = serde_json::to_value(&operation).unwrap();
+        assert_eq!(payload["kind"], IpfsJobKind::Write.as_str());
+        assert_eq!(
+            serde_json::from_value::<IpfsOperation>(payload).unwrap(),
+            operation
+        );
+        assert_eq!(
+            serde_json::to_value(IpfsOperation::Flush).unwrap(),
+            serde_json::json!({ "kind": "flush" })
+        );
+    }
+
+    #[test]
+    fn directory_pins_use_their_own_kind() {
+        let operation = IpfsOperation::PinDirectory {
+            path: "dir".to_string(),
+        };
+        assert_eq!(operation.kind(), IpfsJobKind::PinDirectory);
+        let payload = serde_json::to_value(&operation).unwrap();
+        assert_eq!(payload["kind"], IpfsJobKind::PinDirectory.as_str());
+        assert_eq!(
+            IpfsJobKind::parse(IpfsJobKind::PinDirectory.as_str()),
+            Some(IpfsJobKind::PinDirectory)
+        );
+        assert_eq!(
+            serde_json::from_value::<IpfsOperation>(payload).unwrap(),
+            operation
+        );
+    }
+
+    #[test]
+    fn remote_pins_name_their_service() {
+        let operation = IpfsOperation::RemotePin {
+            service: "backup".to_string(),
+            cid: "QmDir".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&operation).unwrap(),
+            serde_json::json!({ "kind": "remote_pin", "service": "backup", "cid": "QmDir" })
+        );
+        assert_eq!(
+            IpfsJobKind::parse(operation.kind().as_str()),
+            Some(IpfsJobKind::RemotePin)
+        );
+    }
+
+    #[tokio::test]
+    async fn pin_jobs_pin_the_cid() {
+        let mut server = Server::new_async().await;
+        let add = server
+            .mock("POST", "/api/v0/pin/add")
+            .match_query(Matcher::UrlEncoded(
+                "arg".to_string(),
+                "QmImage".to_string(),
+            ))
+            .with_body(r#"{"Pins":["QmImage"]}"#)
+            .create_async()
+            .await;
+        let operation = IpfsOperation::Pin {
+            cid: "QmImage".to_string(),
+        };
+        let outcome = execute(&client(&server), operation, None).await;
+        assert!(matches!(outcome, Ok(Outcome::Pinned(cid)) if cid == "QmImage"));
+        add.assert_async().await;
+
+        server.reset_async().await;
+        server
+            .mock("POST", "/api/v0/pin/add")
+            .with_status(500)
+            .with_body(r#"{"Message":"context deadline exceeded"}"#)
+            .create_async()
+            .await;
+        let operation = IpfsOperation::Pin {
+            cid: "QmImage".to_string(),
+        };
+        let outcome = execute(&client(&server), operation, None).await;
+        assert!(matches!(outcome, Err(JobError::Transient(_))));
+    }
+
+    #[tokio::test]
+    async fn directory_pins_update_without_unpinning_the_previous_cid() {
+        let mut server = Server::new_async().await;
+        let flush = mock_flush(&mut server, "QmNew").await;
+        let update = server
+            .mock("POST", "/api/v0/pin/update")
+            .match_query(Matcher::Exact(
+                "arg=QmOld&arg=QmNew&unpin=false".to_string(),
+            ))
+            .with_body(r#"{"Pins":["QmOld","QmNew"]}"#)
+            .create_async()
+            .await;
+        let cid = pin_directory(
+            &client(&server),
+            &"0xdir".to_string(),
+            Some("QmOld".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(cid, "QmNew");
+        flush.assert_async().await;
+        update.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn directory_pins_fall_back_to_pin_add() {
+        let mut server = Server::new_async().await;
+        mock_flush(&mut server, "QmNew").await;
+        server
+            .mock("POST", "/api/v0/pin/update")
+            .with_status(500)
+            .with_body(r#"{"Message":"'from' cid was not recursively pinned already"}"#)
+            .create_async()
+            .await;
+        let add = server
+            .mock("POST", "/api/v0/pin/add")
+            .match_query(Matcher::UrlEncoded("arg".to_string(), "QmNew".to_string()))
+            .with_body(r#"{"Pins":["QmNew"]}"#)
+            .create_async()
+            .await;
+        let cid = pin_directory(
+            &client(&server),
+            &"0xdir".to_string(),
+            Some("QmGone".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(cid, "QmNew");
+        add.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn unchanged_directories_are_pinned_in_place() {
+        let mut server = Server::new_async().await;
+        mock_flush(&mut server, "QmSame").await;
+        let update = server
+            .mock("POST", "/api/v0/pin/update")
+            .expect(0)
+            .create_async()
+            .await;
+        let add = server
+            .mock("POST", "/api/v0/pin/add")
+            .with_body(r#"{"Pins":["QmSame"]}"#)
+            .create_async()
+            .await;
+        let cid = pin_directory(
+            &client(&server),
+            &"0xdir".to_string(),
+            Some("QmSame".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(cid, "QmSame");
+        update.assert_async().await;
+        add.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn contract_metadata_cid_is_read_from_the_flushed_directory() {
+        let mut server = Server::new_async().await;
+        let stat = server
+            .mock("POST", "/api/v0/files/stat")
+            .match_query(Matcher::UrlEncoded(
+                "arg".to_string(),
+                "/0xdir/contract.json".to_string(),
+            ))
+            .with_body(r#"{"Hash":"QmContract","Size":120,"Type":"file"}"#)
+            .create_async()
+            .await;
+        assert_eq!(
+            stat_contract_metadata(&client(&server), "0xdir").await,
+            Some("QmContract".to_string())
+        );
+        stat.assert_async().await;
+        assert_eq!(
+            stat_contract_metadata(&client(&server), "0xother").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn check_pins_reports_lost_and_broken_pins() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/pin/ls")
+            .match_query(Matcher::Exact("type=recursive".to_string()))
+            .with_body(r#"{"Keys":{"QmKept":{"Type":"recursive"},"QmBroken":{"Type":"recursive"},"QmRepinned":{"Type":"recursive"}}}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/v0/pin/verify")
+            .with_body(concat!(
+                r#"{"Cid":"QmKept","Ok":true}"#,
+                "\n",
+                r#"{"Cid":"QmBroken","Ok":false,"BadNodes":[{"Cid":"QmBlock","Err":"merkledag: not found"}]}"#,
+                "\n",
+                r#"{"Cid":"QmRepinned","Ok":true}"#,
+                "\n",
+            ))
+            .create_async()
+            .await;
+        // the broken CID is removed once, however many rows reference it
+        let rm = server
+            .mock("POST", "/api/v0/pin/rm")
+            .match_query(Matcher::UrlEncoded(
+                "arg".to_string(),
+                "QmBroken".to_string(),
+            ))
+            .with_body(r#"{"Pins":["QmBroken"]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let kept = pin("QmKept", PinStatus::Pinned);
+        let check = check_pins(
+            &client(&server),
+            vec![
+                kept.clone(),
+                pin("QmBroken", PinStatus::Pinned),
+                pin("QmBroken", PinStatus::Pinned),
+                pin("QmLost", PinStatus::Pinned),
+                pin("QmRepinned", PinStatus::Missing),
+            ],
+        )
+        .await
+        .unwrap();
+        rm.assert_async().await;
+        assert_eq!(check.verified, vec![kept.id]);
+        let missing: Vec<(&str, &str)> = check
+            .missing
+            .iter()
+            .map(|(pin, reason)| (pin.cid.as_str(), reason.as_str()))
+            .collect();
+        assert_eq!(
+            missing,
+            vec![
+                ("QmBroken", "incomplete"),
+                ("QmBroken", "incomplete"),
+                ("QmLost", "not pinned"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn check_pins_fails_when_the_node_is_unreachable() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/api/v0/pin/ls")
+            .with_status(500)
+            .create_async()
+            .await;
+        let result = check_pins(&client(&server), vec![pin("QmKept", PinStatus::Pinned)]).await;
+        assert!(matches!(result, Err(AppError::RequestIpfsFailed)));
+    }
+
+    #[tokio::test]
+    async fn remote_pins_replace_the_previous_request() {
+        let mut server = Server::new_async().await;
+        let replace = server
+            .mock("POST", "/psa/pins/r1")
+            .match_header("authorization", "Bearer secret")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "cid": "QmNew" })))
+            .with_status(202)
+            .with_body(pin_status("r2", "QmNew", "queued"))
+            .create_async()
+            .await;
+        let previous = remote_pin("backup", "QmOld", "r1", RemotePinStatus::Pinned);
+        let status = request_remote_pin(
+            &remote(&server),
+            Some(&previous),
+            None,
+            &"QmNew".to_string(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        replace.assert_async().await;
+        assert_eq!(status.requestid, "r2");
+        assert_eq!(status.status, RemotePinStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn remote_pins_fall_back_to_adding_and_remove_the_lost_request() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/psa/pins/r1")
+            .with_status(404)
+            .with_body(r#"{"error":{"reason":"NOT_FOUND"}}"#)
+            .create_async()
+            .await;
+        let add = server
+            .mock("POST", "/psa/pins")
+            .with_status(202)
+            .with_body(pin_status("r2", "QmNew", "queued"))
+            .create_async()
+            .await;
+        let remove = server
+            .mock("DELETE", "/psa/pins/r1")
+            .with_status(202)
+            .expect(1)
+            .create_async()
+            .await;
+        let previous = remote_pin("backup", "QmOld", "r1", RemotePinStatus::Pinned);
+        let status = request_remote_pin(
+            &remote(&server),
+            Some(&previous),
+            None,
+            &"QmNew".to_string(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        add.assert_async().await;
+        remove.assert_async().await;
+        assert_eq!(status.requestid, "r2");
+    }
+
+    #[tokio::test]
+    async fn settled_remote_pins_are_only_replaced_when_failed() {
+        let mut server = Server::new_async().await;
+        let replace = server
+            .mock("POST", "/psa/pins/r1")
+            .with_status(202)
+            .with_body(pin_status("r2", "QmDir", "queued"))
+            .expect(1)
+            .create_async()
+            .await;
+        let remote = remote(&server);
+        let pinned = remote_pin("backup", "QmDir", "r1", RemotePinStatus::Pinned);
+        let status = request_remote_pin(&remote, Some(&pinned), None, &"QmDir".to_string())
+            .await
+            .unwrap();
+        assert!(status.is_none());
+        let failed = remote_pin("backup", "QmDir", "r1", RemotePinStatus::Failed);
+        let status = request_remote_pin(&remote, Some(&failed), None, &"QmDir".to_string())
+            .await
+            .unwrap();
+        assert_eq!(status.unwrap().requestid, "r2");
+        replace.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn polling_reports_failed_requests_for_requeueing() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/psa/pins/r1")
+            .with_body(pin_status("r1", "QmA", "failed"))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/psa/pins/r2")
+            .with_body(pin_status("r2", "QmB", "pinned"))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/psa/pins/r3")
+            .with_status(500)
+            .create_async()
+            .await;
+        let remotes = RemotePinningServices::from([("backup".to_string(), remote(&server))]);
+        let statuses = check_remote_pins(
+            &remotes,
+            vec![
+                remote_pin("backup", "QmA", "r1", RemotePinStatus::Queued),
+                remote_pin("backup", "QmB", "r2", RemotePinStatus::Pinning),
+                remote_pin("backup", "QmC", "r3", RemotePinStatus::Queued),
+                remote_pin("removed", "QmD", "r4", RemotePinStatus::Queued),
+            ],
+        )
+        .await;
+        let statuses: Vec<(&str, RemotePinStatus)> = statuses
+            .iter()
+            .map(|(remote_pin, status)| (remote_pin.request_id.as_str(), *status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("r1", RemotePinStatus::Failed),
+                ("r2", RemotePinStatus::Pinned)
+            ]
+        );
+    }
+
+    #[test]
+    fn remote_unpins_name_the_request() {
+        let operation = IpfsOperation::RemoteUnpin {
+            service: "backup".to_string(),
+            request_id: "r1".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&operation).unwrap(),
+            serde_json::json!({ "kind": "remote_unpin", "service": "backup", "request_id": "r1" })
+        );
+        assert_eq!(
+            IpfsJobKind::parse(operation.kind().as_str()),
+            Some(IpfsJobKind::RemoteUnpin)
+        );
+    }
+}
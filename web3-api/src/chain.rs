@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+
+use crate::signature;
+
+/// JSON-RPC endpoints keyed by EIP-155 chain id.
+#[derive(Debug, Clone, Default)]
+pub struct ChainRpcRegistry {
+    rpc_urls: HashMap<u64, String>,
+}
+
+impl ChainRpcRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma separated list of `<chain_id>=<rpc_url>` pairs,
+    /// e.g. `1=https://eth.example,11155111=https://sepolia.example`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut registry = Self::new();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (chain_id, rpc_url) = entry
+                .split_once('=')
+                .ok_or_else(|| eyre!("invalid chain rpc entry: {entry}"))?;
+            registry.insert(chain_id.trim().parse()?, rpc_url.trim());
+        }
+        Ok(registry)
+    }
+
+    pub fn insert(&mut self, chain_id: u64, rpc_url: impl Into<String>) {
+        self.rpc_urls.insert(chain_id, rpc_url.into());
+    }
+
+    pub fn rpc_url(&self, chain_id: u64) -> Option<&str> {
+        self.rpc_urls.get(&chain_id).map(String::as_str)
+    }
+
+    /// EIP-1271 check of `signature` against the contract wallet `address` on `chain_id`.
+    pub async fn is_valid_signature(
+        &self,
+        chain_id: u64,
+        address: &str,
+        hash: [u8; 32],
+        signature: &[u8],
+    ) -> Result<bool> {
+        let rpc_url = self
+            .rpc_url(chain_id)
+            .ok_or_else(|| eyre!("no rpc configured for chain {chain_id}"))?;
+        signature::is_valid_signature(rpc_url, address, hash, signature).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_registry() {
+        let registry =
+            ChainRpcRegistry::parse("1=https://eth.example, 11155111=http://127.0.0.1:8545")
+                .unwrap();
+        assert_eq!(registry.rpc_url(1), Some("https://eth.example"));
+        assert_eq!(registry.rpc_url(11155111), Some("http://127.0.0.1:8545"));
+        assert_eq!(registry.rpc_url(5), None);
+
+        assert!(ChainRpcRegistry::parse("").unwrap().rpc_url(1).is_none());
+        assert!(ChainRpcRegistry::parse("mainnet=https://eth.example").is_err());
+    }
+}
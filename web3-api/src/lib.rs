@@ -10,6 +10,9 @@ use alloy::{
 
 use eyre::Result;
 
+pub mod chain;
+pub mod signature;
+
 pub async fn tract_transaction() -> Result<()> {
     // Spin up a forked Anvil node.
     // Ensure `anvil` is available in $PATH.
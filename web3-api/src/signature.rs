@@ -0,0 +1,88 @@
+use alloy::{
+    primitives::{Address, Bytes, FixedBytes, B256},
+    providers::ProviderBuilder,
+    sol,
+};
+use eyre::Result;
+
+sol! {
+    #[sol(rpc)]
+    interface IERC1271 {
+        function isValidSignature(bytes32 hash, bytes signature) external view returns (bytes4 magicValue);
+    }
+}
+
+/// `bytes4(keccak256("isValidSignature(bytes32,bytes)"))`, returned by a contract wallet
+/// that accepts the signature.
+pub const ERC1271_MAGIC_VALUE: FixedBytes<4> = FixedBytes([0x16, 0x26, 0xba, 0x7e]);
+
+/// Asks the contract at `address` whether `signature` is valid for `hash` (EIP-1271).
+///
+/// Accounts without code, or contracts that do not implement the interface, return an error.
+pub async fn is_valid_signature(
+    rpc_url: &str,
+    address: &str,
+    hash: [u8; 32],
+    signature: &[u8],
+) -> Result<bool> {
+    let provider = ProviderBuilder::new().on_http(rpc_url.parse()?);
+    let address: Address = address.parse()?;
+    let contract = IERC1271::new(address, provider);
+
+    let IERC1271::isValidSignatureReturn { magicValue } = contract
+        .isValidSignature(B256::from(hash), Bytes::copy_from_slice(signature))
+        .call()
+        .await?;
+    Ok(magicValue == ERC1271_MAGIC_VALUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{node_bindings::Anvil, primitives::bytes, providers::Provider};
+
+    use super::*;
+
+    // Runtime code that returns the EIP-1271 magic value for any call.
+    const ALWAYS_VALID_WALLET: Bytes = bytes!("631626ba7e60e01b60005260206000f3");
+    // Runtime code that returns `0xffffffff` for any call.
+    const ALWAYS_INVALID_WALLET: Bytes = bytes!("63ffffffff60e01b60005260206000f3");
+
+    async fn set_code(rpc_url: &str, address: &str, code: Bytes) {
+        let provider = ProviderBuilder::new().on_http(rpc_url.parse().unwrap());
+        let address: Address = address.parse().unwrap();
+        provider
+            .raw_request::<_, ()>("anvil_setCode".into(), (address, code))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn contract_wallet_signature() {
+        // Ensure `anvil` is available in $PATH.
+        let anvil = Anvil::new().try_spawn().unwrap();
+        let rpc_url = anvil.endpoint();
+
+        let valid_wallet = "0x00000000000000000000000000000000000c0de1";
+        let invalid_wallet = "0x00000000000000000000000000000000000c0de2";
+        set_code(&rpc_url, valid_wallet, ALWAYS_VALID_WALLET).await;
+        set_code(&rpc_url, invalid_wallet, ALWAYS_INVALID_WALLET).await;
+
+        let hash = [7u8; 32];
+        let signature = [1u8; 65];
+        assert!(is_valid_signature(&rpc_url, valid_wallet, hash, &signature)
+            .await
+            .unwrap());
+        assert!(!is_valid_signature(&rpc_url, invalid_wallet, hash, &signature)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn externally_owned_account_is_not_a_wallet() {
+        let anvil = Anvil::new().try_spawn().unwrap();
+        let eoa = anvil.addresses()[0].to_string();
+
+        let result = is_valid_signature(&anvil.endpoint(), &eoa, [7u8; 32], &[1u8; 65]).await;
+        assert!(!matches!(result, Ok(true)));
+    }
+}